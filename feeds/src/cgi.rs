@@ -21,8 +21,10 @@ use crate::{
     formfields::FormFields,
     pagegen::{GetBody, PageGen},
     query::Query,
+    urls::Urls,
 };
 use anyhow::{self as ah, format_err as err};
+use feedscfg::Config;
 use std::{
     env,
     ffi::OsString,
@@ -116,11 +118,13 @@ pub struct Cgi {
     body_type: String,
     _host: String,
     _cookie: OsString,
+    last_event_id: String,
+    urls: Urls,
     start_stamp: Option<Instant>,
 }
 
 impl Cgi {
-    pub async fn new() -> ah::Result<Self> {
+    pub async fn new(config: &Config) -> ah::Result<Self> {
         let start_stamp = if DEBUG { Some(Instant::now()) } else { None };
 
         let query = get_cgienv_str("QUERY_STRING").unwrap_or_default();
@@ -130,6 +134,17 @@ impl Cgi {
         let body_type = get_cgienv_str("CONTENT_TYPE").unwrap_or_default();
         let host = get_cgienv_str("HTTP_HOST").unwrap_or_default();
         let cookie = get_cgienv("HTTP_COOKIE")?;
+        let last_event_id = get_cgienv_str("HTTP_LAST_EVENT_ID").unwrap_or_default();
+        let script_name = get_cgienv_str("SCRIPT_NAME").unwrap_or_default();
+        let forwarded_prefix = get_cgienv_str("HTTP_X_FORWARDED_PREFIX").unwrap_or_default();
+        let https = get_cgienv_str("HTTPS").unwrap_or_default();
+        let forwarded_proto = get_cgienv_str("HTTP_X_FORWARDED_PROTO").unwrap_or_default();
+        let urls = Urls::new(&config.ui, &script_name, &forwarded_prefix).with_origin(
+            &config.ui,
+            &https,
+            &forwarded_proto,
+            &host,
+        );
 
         Ok(Self {
             query,
@@ -139,10 +154,16 @@ impl Cgi {
             body_type,
             _host: host,
             _cookie: cookie,
+            last_event_id,
+            urls,
             start_stamp,
         })
     }
 
+    pub fn urls(&self) -> &Urls {
+        &self.urls
+    }
+
     pub async fn run(&mut self, pagegen: &mut PageGen<'_>) {
         let Ok(query) = Query::parse(&self.query) else {
             response_400_bad_request("Invalid QUERY_STRING in URI.");
@@ -160,6 +181,21 @@ impl Cgi {
                     }
                 }
             },
+            "GET" if pagegen.is_stream(&self.path) => {
+                let headers = [
+                    "Cache-Control: no-cache".to_string(),
+                    "X-Accel-Buffering: no".to_string(),
+                ];
+                response_200_ok(None, pagegen.stream_mime(), &headers, None);
+                let last_event_id = Some(self.last_event_id.as_str()).filter(|id| !id.is_empty());
+                let mut f = io::stdout();
+                if let Err(e) = pagegen
+                    .stream(&self.path, &query, last_event_id, &mut f)
+                    .await
+                {
+                    log::error!("GET stream failed: {e:?}");
+                }
+            }
             "GET" => match pagegen.get(&self.path, &query, GetBody::Yes).await {
                 Ok(res) => {
                     response_200_ok(Some(res.body.as_bytes()), &res.mime, &[], self.start_stamp)
@@ -23,17 +23,30 @@ mod cgi;
 mod formfields;
 mod pagegen;
 mod query;
+mod template;
+mod urls;
 mod wakeup;
 
 use crate::{cgi::Cgi, pagegen::PageGen};
 use anyhow::{self as ah, Context as _};
 use clap::Parser;
+use feedscfg::Config;
 use feedsdb::Db;
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 use tokio::runtime;
 
 #[derive(Parser, Debug, Clone)]
 struct Opts {
+    /// The configuration file.
+    ///
+    /// The `*.conf` files in the `conf.d` directory next to it are merged in lexical order.
+    #[arg(long, env = feedscfg::CONFIG_FILE_ENV, default_value = feedscfg::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+
+    /// Fail, if the configuration file does not exist.
+    #[arg(long, env = feedscfg::CONFIG_STRICT_ENV)]
+    strict_config: bool,
+
     /// The name of the database to use.
     #[arg(long, default_value = "feeds")]
     db: String,
@@ -46,16 +59,20 @@ struct Opts {
 }
 
 async fn async_main(opts: Opts) -> ah::Result<()> {
+    let config = Config::load(&opts.config, opts.strict_config)?;
+
     // Create the database access object.
     let db = Db::new(&opts.db).await.context("Database")?;
 
+    // Get the CGI environment from the web server.
+    let mut cgi = Cgi::new(&config).await.context("Initialize CGI")?;
+
     // Create the page generator.
-    let mut pagegen = PageGen::new(&db)
+    let mut pagegen = PageGen::new(&db, &config, cgi.urls().clone())
         .await
         .context("Initialze page generator")?;
 
     // Handle the CGI with the web server.
-    let mut cgi = Cgi::new().await.context("Initialize CGI")?;
     cgi.run(&mut pagegen).await;
     Ok(())
 }
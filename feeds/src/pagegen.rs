@@ -18,15 +18,41 @@
 //
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::{formfields::FormFields, query::Query, wakeup::wakeup_feedsd};
+use crate::{
+    formfields::FormFields,
+    query::Query,
+    template::Templates,
+    urls::Urls,
+    wakeup::{refresh_feeds_now, wakeup_feedsd},
+};
 use anyhow::{self as ah, Context as _, format_err as err};
-use feedsdb::{Db, DbConn, FeedsExt};
-use std::{fmt::Write as _, write as wr, writeln as ln};
+use chrono::Utc;
+use feedscfg::Config;
+use feedsdb::{Db, DbConn, FeedsExt, ItemFilter};
+use feedsfmt::export::{
+    ExportFormat, ExportMeta, export, export_query, export_title, get_export_items,
+};
+use serde::Serialize;
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
 
 const MIME_TEXT: &str = "text/plain";
 const MIME_HTML: &str = "text/html";
-const BODY_PREALLOC: usize = 1024 * 1024;
-const TEMPLATE_JS: &str = include_str!("pagegen_template.js");
+const MIME_JSON: &str = "application/json";
+const MIME_EVENT_STREAM: &str = "text/event-stream";
+
+/// Interval for polling the database for feed update revision changes.
+const REV_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Maximum time a long-poll request blocks.
+const LONGPOLL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum lifetime of one event stream. The browser reconnects afterwards.
+const EVENTS_TIMEOUT: Duration = Duration::from_secs(300);
+/// Interval for keep-alive comments in the event stream.
+const EVENTS_KEEPALIVE: Duration = Duration::from_secs(15);
+/// Number of items in an export, if the query does not set `limit`.
+const EXPORT_DEFAULT_LIMIT: usize = 100;
 
 fn floor_char_boundary(s: &str, mut i: usize) -> usize {
     if i >= s.len() {
@@ -42,265 +68,467 @@ fn floor_char_boundary(s: &str, mut i: usize) -> usize {
     }
 }
 
-fn escape(s: &str, maxlen: usize) -> String {
+/// Limit the length of `s` to `maxlen` bytes.
+/// Escaping is done by the templates.
+fn snip(s: &str, maxlen: usize) -> String {
     let boundary = floor_char_boundary(s, maxlen);
     let mut snipped = s[0..boundary].to_string();
     if snipped.len() != s.len() {
         snipped.push_str("...");
     }
-    html_escape::encode_safe(&snipped).into_owned()
+    snipped
 }
 
-fn escape_comment(s: &str) -> String {
-    s.replace("-->", "_->")
+/// Check whether `link` can be used as the target of a link in the page.
+///
+/// Only http(s) and relative links are allowed.
+/// Other schemes, such as `javascript:`, are not.
+fn is_safe_link(link: &str) -> bool {
+    // Browsers ignore whitespace and control characters in the scheme.
+    let link: String = link
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    match link.find([':', '/', '?', '#']) {
+        Some(pos) if link[pos..].starts_with(':') => {
+            let scheme = link[..pos].to_ascii_lowercase();
+            scheme == "http" || scheme == "https"
+        }
+        _ => true,
+    }
 }
 
-#[rustfmt::skip]
-async fn gen_feed_list(
-    b: &mut String,
-    conn: &mut DbConn,
-    active_feed_id: Option<i64>,
-    peek: bool,
-) -> ah::Result<FeedsExt> {
-    let (feeds, feeds_ext) = conn.get_feeds(if peek { None } else { active_feed_id }).await
-        .context("Database: Get feeds")?;
+/// Limit the length of the item `link` and drop it, if it is not safe.
+fn safe_link(link: &str) -> String {
+    if is_safe_link(link) {
+        snip(link, 1024)
+    } else {
+        String::new()
+    }
+}
 
-    ln!(b, r#"<div id="feed_list">"#)?;
-    ln!(b, r#"  <form method="post" enctype="multipart/form-data">"#)?;
-    ln!(b, r#"    <table align="center" id="feed_table">"#)?;
-    ln!(b, r#"      <tr>"#)?;
-    ln!(b, r#"        <th colspan="3">"#)?;
-    ln!(b, r#"          <a href="/cgi-bin/feeds">"#)?;
-    ln!(b, r#"            <div id="feed_table_head">feeds</div>"#)?;
-    ln!(b, r#"            <div id="feed_table_head_ext" hidden></div>"#)?;
-    ln!(b, r#"          </a>"#)?;
-    ln!(b, r#"        </th>"#)?;
-    ln!(b, r#"      </tr>"#)?;
-    for feed in feeds {
-        let tr_class = if feed.feed_id == active_feed_id {
-            r#" class="active_row""#
-        } else {
-            ""
-        };
+/// Reduce the revision to a number that is safe to handle in JavaScript.
+fn mask_rev(rev: i64) -> i64 {
+    rev & i32::MAX as i64
+}
 
-        let mut classes = String::new();
-        if feed.disabled {
-            wr!(&mut classes, "disabled")?;
-        }
-        if feed.updated_items > 0 {
-            if !classes.is_empty() {
-                wr!(&mut classes, " ")?;
-            }
-            wr!(&mut classes, "new_items")?;
-        }
+#[derive(Serialize)]
+struct FeedUpdateFeed {
+    id: i64,
+    updated_items: i64,
+    disabled: bool,
+}
 
-        let feed_id = feed.feed_id.expect("get_feeds() feed_id was None");
-        let mut title = escape(&feed.title, 32);
-        if feed.disabled {
-            title.push_str(" (DISABLED)");
-        }
+#[derive(Serialize)]
+struct FeedUpdate {
+    rev: i64,
+    feeds: Vec<FeedUpdateFeed>,
+}
 
-        let updated_items = if feed.updated_items > 0 {
-            format!(" ({})", feed.updated_items)
-        } else {
-            "".to_string()
-        };
+async fn get_feed_update(conn: &mut DbConn) -> ah::Result<FeedUpdate> {
+    let (feeds, feeds_ext) = conn.get_feeds(None).await.context("Database: Get feeds")?;
+    Ok(FeedUpdate {
+        rev: mask_rev(feeds_ext.feed_update_revision),
+        feeds: feeds
+            .iter()
+            .map(|feed| FeedUpdateFeed {
+                id: feed.feed_id.expect("get_feeds() feed_id was None"),
+                updated_items: feed.updated_items,
+                disabled: feed.disabled,
+            })
+            .collect(),
+    })
+}
 
-        ln!(b, r#"      <tr{tr_class}>"#)?;
-        ln!(b, r#"        <!-- {} -->"#, escape_comment(&title))?;
-        ln!(b, r#"        <!-- {} -->"#, escape_comment(&feed.href))?;
-        ln!(b, r#"        <td>"#)?;
-        ln!(b, r#"          <a class="peek" href="/cgi-bin/feeds?id={feed_id}&peek=1">&#128065;</a>"#)?;
-        ln!(b, r#"        </td>"#)?;
-        ln!(b, r#"        <td>"#)?;
-        ln!(b, r#"          <input name="del" value="{feed_id}" type="checkbox">"#)?;
-        ln!(b, r#"        </td>"#)?;
-        ln!(b, r#"        <td class="feed_title">"#)?;
-        ln!(b, r#"          <span class="{classes}">"#)?;
-        ln!(b, r#"            <a href="/cgi-bin/feeds?id={feed_id}">"#)?;
-        ln!(b, r#"              {title}"#)?;
-        ln!(b, r#"            </a>"#)?;
-        ln!(b, r#"          </span>"#)?;
-        ln!(b, r#"          {updated_items}"#)?;
-        ln!(b, r#"        </td>"#)?;
-        ln!(b, r#"      </tr>"#)?;
+/// Wait until the feed update revision differs from `client_rev` or until `timeout` expired.
+///
+/// Returns the current (masked) feed update revision.
+async fn wait_feed_update_rev(
+    conn: &mut DbConn,
+    client_rev: Option<i64>,
+    timeout: Duration,
+) -> ah::Result<i64> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let rev = mask_rev(conn.get_feed_update_revision().await?);
+        let now = Instant::now();
+        if client_rev != Some(rev) || now >= deadline {
+            break Ok(rev);
+        }
+        tokio::time::sleep(REV_POLL_INTERVAL.min(deadline - now)).await;
     }
+}
+
+#[derive(Serialize)]
+struct FeedCtx {
+    id: i64,
+    title: String,
+    href: String,
+    disabled: bool,
+    updated_items: i64,
+    active: bool,
+}
+
+#[derive(Serialize)]
+struct ItemCtx {
+    id: String,
+    link: String,
+    title: String,
+    author: String,
+    summary: String,
+    date: String,
+    seen: bool,
+    starred: bool,
+    important: bool,
+    tags: Vec<String>,
+    /// The no-highlighting rule that suppressed the highlighting.
+    suppressed_by: Option<String>,
+    /// The seen-state of the item after this page has been generated.
+    seen_after: bool,
+    /// "new", "updated" or empty.
+    status: &'static str,
+    history: bool,
+}
+
+#[derive(Serialize, Default)]
+struct NavCtx {
+    newer: Option<usize>,
+    older: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct FlagsCtx {
+    peek: bool,
+    per_item_seen: bool,
+    server_sent_events: bool,
+}
+
+/// The context that is passed to the page template.
+#[derive(Serialize)]
+struct PageCtx<'a> {
+    feeds: Vec<FeedCtx>,
+    feed_id: Option<i64>,
+    items: Option<Vec<ItemCtx>>,
+    history: Option<Vec<ItemCtx>>,
+    offset: usize,
+    nav: NavCtx,
+    flags: FlagsCtx,
+    urls: &'a Urls,
+    feed_update_revision: i64,
+    refresh: Option<RefreshCtx>,
+}
 
-    ln!(b, r#"    </table>"#)?;
-    ln!(b, r#"    <input type="submit" class="button" value="delete">"#)?;
-    ln!(b, r#"  </form>"#)?;
-    ln!(b, r#"  <form method="post" enctype="multipart/form-data">"#)?;
-    ln!(b, r#"    <input name="add" class="button" type="text">"#)?;
-    ln!(b, r#"    <input type="submit" class="button" value="add">"#)?;
-    ln!(b, r#"  </form>"#)?;
-    ln!(b, r#"</div>"#)?;
-    Ok(feeds_ext)
+/// The outcome of a "refresh now" request.
+#[derive(Serialize)]
+struct RefreshCtx {
+    new_items: usize,
+    error: Option<String>,
+}
+
+async fn gen_feed_list(
+    conn: &mut DbConn,
+    config: &Config,
+    active_feed_id: Option<i64>,
+    peek: bool,
+) -> ah::Result<(Vec<FeedCtx>, FeedsExt)> {
+    // In per-item-seen mode the feed stays highlighted
+    // until all of its items have been marked as seen.
+    let reset_feed_id = if peek || config.ui.per_item_seen {
+        None
+    } else {
+        active_feed_id
+    };
+    let (feeds, feeds_ext) = conn
+        .get_feeds(reset_feed_id)
+        .await
+        .context("Database: Get feeds")?;
+
+    let feeds = feeds
+        .into_iter()
+        .map(|feed| FeedCtx {
+            id: feed.feed_id.expect("get_feeds() feed_id was None"),
+            title: snip(&feed.title, 32),
+            active: feed.feed_id == active_feed_id,
+            href: feed.href,
+            disabled: feed.disabled,
+            updated_items: feed.updated_items,
+        })
+        .collect();
+    Ok((feeds, feeds_ext))
 }
 
-#[rustfmt::skip]
 async fn gen_item_list(
-    b: &mut String,
     conn: &mut DbConn,
+    config: &Config,
     feed_id: i64,
     peek: bool,
-) -> ah::Result<()> {
-    let items = conn.get_feed_items(feed_id, peek).await
+    offset: usize,
+) -> ah::Result<(Vec<ItemCtx>, NavCtx)> {
+    let mark_seen = !peek && !config.ui.per_item_seen;
+    let limit = config.ui.items_per_page.map(|l| l.get());
+    let (items, items_ext) = conn
+        .get_feed_items(feed_id, !mark_seen, offset, limit)
+        .await
         .context("Database: Get feed items")?;
 
-    ln!(b, r#"<div id="item_list">"#)?;
-    for (item, item_ext) in items {
-        let item_id = item.item_id.as_ref().expect("get_feed_items() item_id was None");
-        let link = escape(&item.link, 1024);
-        let title = escape(&item.title, 256);
-        let summary = escape(&item.summary, 4096);
-        let classes = if item.seen { "item" } else { "item unseen" };
-        let author = if item.author.is_empty() {
-            "".to_string()
-        } else {
-            format!("{} - ", escape(&item.author, 32))
-        };
-        let timestring = item.published.format("%Y-%m-%d %H:%M:%S");
-        let mut new_marker = if item.seen { "" } else { "<b>(NEW)</b> " };
-        let mut history = String::new();
-        if item_ext.count > 1 {
-            if item_ext.any_seen && !item_ext.all_seen {
-                new_marker = "<b>(updated)</b> ";
+    let nav = match limit {
+        Some(limit) => NavCtx {
+            newer: (offset > 0).then(|| offset.saturating_sub(limit)),
+            older: items_ext.more_items.then(|| offset.saturating_add(limit)),
+        },
+        None => Default::default(),
+    };
+
+    let items = items
+        .into_iter()
+        .map(|(item, item_ext)| {
+            let mut status = if item.seen { "" } else { "new" };
+            if item_ext.count > 1 && item_ext.any_seen && !item_ext.all_seen {
+                status = "updated";
             }
-            wr!(&mut history, r#"<a class="history" href="/cgi-bin/feeds?"#)?;
-            if peek {
-                wr!(&mut history, r#"id={feed_id}&itemid={item_id}&peek=1">(history)</a>"#)?;
-            } else {
-                wr!(&mut history, r#"id={feed_id}&itemid={item_id}">(history)</a>"#)?;
+            ItemCtx {
+                id: item.item_id.expect("get_feed_items() item_id was None"),
+                link: safe_link(&item.link),
+                title: snip(&item.title, 256),
+                author: snip(&item.author, 32),
+                summary: snip(&item.summary, 4096),
+                date: item.published.format("%Y-%m-%d %H:%M:%S").to_string(),
+                seen: item.seen,
+                starred: item.starred,
+                important: item.important,
+                tags: item.tags,
+                suppressed_by: item.suppressed_by,
+                seen_after: mark_seen || item.seen,
+                status,
+                history: item_ext.count > 1,
             }
-        }
-        ln!(b, r#"  <div class="{classes}">"#)?;
-        ln!(b, r#"    <a class="title" href="{link}">{author}{title}</a>"#)?;
-        ln!(b, r#"    {history}"#)?;
-        if !peek || item.seen {
-            ln!(b, r#"    <br />"#)?;
-            ln!(b, r#"    <form class="unseen_form" method="post" enctype="multipart/form-data" action="/cgi-bin/feeds?id={feed_id}">"#)?;
-            ln!(b, r#"      <button name="unseen" value="{item_id}" type="submit" class="button">unseen</button>"#)?;
-            ln!(b, r#"    </form>"#)?;
-        }
-        ln!(b, r#"    <br />"#)?;
-        ln!(b, r#"    <div class="date">{new_marker}{timestring}</div>"#)?;
-        ln!(b, r#"    <br />"#)?;
-        ln!(b, r#"    <div class="summary">{summary}</div>"#)?;
-        ln!(b, r#"  </div>"#)?;
-        ln!(b, r#"  <hr />"#)?;
-    }
-    ln!(b, r#"</div>"#)?;
-    Ok(())
+        })
+        .collect();
+    Ok((items, nav))
 }
 
-#[rustfmt::skip]
 async fn gen_item_history_list(
-    b: &mut String,
     conn: &mut DbConn,
     feed_id: i64,
     item_id: &str,
     peek: bool,
-) -> ah::Result<()> {
-    let items = conn.get_feed_items_by_item_id(feed_id, item_id, peek).await
+) -> ah::Result<Vec<ItemCtx>> {
+    let items = conn
+        .get_feed_items_by_item_id(feed_id, item_id, peek)
+        .await
         .context("Database: Get items by item_id")?;
 
-    ln!(b, r#"<div id="item_list">"#)?;
-    for item in items {
-        let link = escape(&item.link, 1024);
-        let title = escape(&item.title, 256);
-        let summary = escape(&item.summary, 4096);
-        let classes = if item.seen { "item" } else { "item unseen" };
-        let author = if item.author.is_empty() {
-            "".to_string()
-        } else {
-            format!("{} - ", escape(&item.author, 32))
-        };
-        let timestring = item.retrieved.format("%Y-%m-%d %H:%M:%S");
-
-        ln!(b, r#"  <div class="{classes}">"#)?;
-        ln!(b, r#"    <a class="title" href="{link}">{author}{title}</a>"#)?;
-        ln!(b, r#"    <br />"#)?;
-        ln!(b, r#"    <div class="date">{timestring}</div>"#)?;
-        ln!(b, r#"    <br />"#)?;
-        ln!(b, r#"    <div class="summary">{summary}</div>"#)?;
-        ln!(b, r#"  </div>"#)?;
-        ln!(b, r#"  <hr />"#)?;
-    }
-    ln!(b, r#"</div>"#)?;
-    Ok(())
+    let items = items
+        .into_iter()
+        .map(|item| ItemCtx {
+            id: item
+                .item_id
+                .expect("get_feed_items_by_item_id() item_id was None"),
+            link: safe_link(&item.link),
+            title: snip(&item.title, 256),
+            author: snip(&item.author, 32),
+            summary: snip(&item.summary, 4096),
+            date: item.retrieved.format("%Y-%m-%d %H:%M:%S").to_string(),
+            seen: item.seen,
+            starred: item.starred,
+            important: item.important,
+            tags: item.tags,
+            suppressed_by: item.suppressed_by,
+            seen_after: !peek || item.seen,
+            status: "",
+            history: false,
+        })
+        .collect();
+    Ok(items)
 }
 
-#[rustfmt::skip]
 async fn gen_page(
-    b: &mut String,
+    templates: &Templates,
+    urls: &Urls,
     conn: &mut DbConn,
+    config: &Config,
     query: &Query,
     formfields: Option<&FormFields>,
-) -> ah::Result<()> {
+) -> ah::Result<String> {
     let mut wake_feedsd = false;
-
-    ln!(b, r#"<!DOCTYPE HTML>"#)?;
-    ln!(b, r#"<html lang="en">"#)?;
-    ln!(b, r#"<head>"#)?;
-    ln!(b, r#"  <title>My Feeds</title>"#)?;
-    ln!(b, r#"  <link rel="stylesheet" type="text/css" href="/feeds/style.css">"#)?;
-    ln!(b, r#"  <link rel="icon" type="image/png" href="/feeds/icon.png">"#)?;
-    ln!(b, r#"  <meta http-equiv="Content-Type" content="text/html; charset=UTF-8">"#)?;
-    ln!(b, r#"  <meta name="generator" content="feedreader (Rust variant)">"#)?;
-    ln!(b, r#"</head>"#)?;
-    ln!(b, r#"<body>"#)?;
+    let mut refresh = None;
 
     if let Some(formfields) = formfields {
         if let Some(add_href) = formfields.get_one("add") {
-            conn.add_feed(add_href).await
+            conn.add_feed(add_href)
+                .await
                 .context("Database: Add feed")?;
             wake_feedsd = true;
         }
         if let Some(del_ids) = formfields.get_list_i64("del") {
-            conn.delete_feeds(&del_ids).await
+            conn.delete_feeds(&del_ids)
+                .await
                 .context("Database: Delete feeds")?;
         }
-        if let Some(unseen_id) = formfields.get_one("unseen") {
-            conn.item_set_unseen(unseen_id).await
+        if let Some(seen_ids) = formfields.get_list("seen") {
+            conn.items_set_seen(seen_ids, true)
+                .await
+                .context("Database: Set seen")?;
+        }
+        if let Some(unseen_ids) = formfields.get_list("unseen") {
+            conn.items_set_seen(unseen_ids, false)
+                .await
                 .context("Database: Set unseen")?;
         }
+        if let Some(star_ids) = formfields.get_list("star") {
+            conn.items_set_starred(star_ids, true)
+                .await
+                .context("Database: Set starred")?;
+        }
+        if let Some(unstar_ids) = formfields.get_list("unstar") {
+            conn.items_set_starred(unstar_ids, false)
+                .await
+                .context("Database: Set unstarred")?;
+        }
+        if let Some(feed_ids) = formfields.get_list_i64("feedseen") {
+            for feed_id in feed_ids {
+                conn.feed_set_seen(Some(feed_id))
+                    .await
+                    .context("Database: Set feed seen")?;
+            }
+        }
+        if let Some(feed_ids) = formfields.get_list_i64("refresh") {
+            let outcomes = refresh_feeds_now(config, &feed_ids).await;
+            refresh = Some(RefreshCtx {
+                new_items: outcomes.iter().map(|o| o.new_items).sum(),
+                error: outcomes.into_iter().find_map(|o| o.error),
+            });
+        }
     }
 
     let feed_id = query.get_i64("id");
     let item_id = query.get("itemid");
-    // After marking an item unseen via POST, render in peek mode so the item
+    let offset = query.get_usize("offset").unwrap_or(0);
+    // After changing the state of an item via POST, render in peek mode so the item
     // list fetch does not immediately re-mark everything as seen.
-    let unseen_action = formfields
-        .and_then(|ff| ff.get_one("unseen"))
-        .map(|v| !v.is_empty())
+    let seen_action = formfields
+        .map(|ff| {
+            ["seen", "unseen", "star", "unstar", "refresh"]
+                .iter()
+                .any(|name| ff.get_one(name).map(|v| !v.is_empty()).unwrap_or(false))
+        })
         .unwrap_or(false);
-    let peek = query.get("peek").map(|v| v == "1").unwrap_or(false) || unseen_action;
+    let peek = query.get("peek").map(|v| v == "1").unwrap_or(false) || seen_action;
 
-    let feeds_ext = gen_feed_list(b, conn, feed_id, peek).await?;
+    let (feeds, feeds_ext) = gen_feed_list(conn, config, feed_id, peek).await?;
 
+    let mut items = None;
+    let mut history = None;
+    let mut nav = NavCtx::default();
     if let Some(feed_id) = feed_id {
         if let Some(item_id) = &item_id {
-            gen_item_history_list(b, conn, feed_id, item_id, peek).await?;
+            history = Some(gen_item_history_list(conn, feed_id, item_id, peek).await?);
         } else {
-            gen_item_list(b, conn, feed_id, peek).await?;
+            let (i, n) = gen_item_list(conn, config, feed_id, peek, offset).await?;
+            items = Some(i);
+            nav = n;
         }
     }
 
-    let rev_static = feeds_ext.feed_update_revision & i32::MAX as i64;
-    ln!(b, r#"<div hidden id="feed_update_revision_dynamic">NaN</div>"#)?;
-    ln!(b, r#"<div hidden id="feed_update_revision_static">{rev_static}</div>"#)?;
-    ln!(b, r#"<script type="text/javascript">"#)?;
-    ln!(b, "{}", TEMPLATE_JS.trim())?;
-    ln!(b, r#"</script>"#)?;
-
-    ln!(b, r#"</body>"#)?;
-    ln!(b, r#"</html>"#)?;
+    let ctx = PageCtx {
+        feeds,
+        feed_id,
+        items,
+        history,
+        offset,
+        nav,
+        flags: FlagsCtx {
+            peek,
+            per_item_seen: config.ui.per_item_seen,
+            server_sent_events: config.ui.server_sent_events,
+        },
+        urls,
+        feed_update_revision: mask_rev(feeds_ext.feed_update_revision),
+        refresh,
+    };
+    let body = templates.render("page.html", &ctx)?;
 
     if wake_feedsd {
-        wakeup_feedsd().await;
+        wakeup_feedsd(config).await;
     }
 
-    Ok(())
+    Ok(body)
+}
+
+#[derive(Serialize)]
+struct ItemStateResult {
+    items: Vec<String>,
+    value: bool,
+}
+
+/// Parse the requested export format and item selection from the query.
+fn export_params(query: &Query) -> ah::Result<(ExportFormat, ItemFilter)> {
+    let format = query.get("format").unwrap_or("atom").parse()?;
+    let search = query
+        .get("q")
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty());
+    let filter = ItemFilter {
+        feed_ids: query.get_i64_list("feed")?,
+        starred: query.get_i64("starred").unwrap_or_default() != 0,
+        tag: query
+            .get("tag")
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty()),
+        search,
+        limit: Some(query.get_usize("limit").unwrap_or(EXPORT_DEFAULT_LIMIT)).filter(|l| *l > 0),
+        ..Default::default()
+    };
+    Ok((format, filter))
+}
+
+async fn gen_export(
+    urls: &Urls,
+    conn: &mut DbConn,
+    format: ExportFormat,
+    filter: &ItemFilter,
+) -> ah::Result<String> {
+    let items = get_export_items(conn, filter).await?;
+    let meta = ExportMeta {
+        title: export_title(filter, &items),
+        self_url: urls.absolute(&format!("/export?{}", export_query(format, filter))),
+        home_url: urls.absolute(""),
+        generated: Utc::now(),
+    };
+    export(format, &meta, &items)
+}
+
+/// Handle a lightweight item state change request from the JavaScript UI.
+///
+/// The form fields are `item` (one or more item IDs) and `value` (`1` or `0`).
+async fn api_item_state(
+    conn: &mut DbConn,
+    path: &str,
+    formfields: &FormFields,
+) -> ah::Result<String> {
+    let items: Vec<String> = formfields
+        .get_list("item")
+        .map(|l| l.to_vec())
+        .unwrap_or_default();
+    let value = match formfields.get_one("value").map(|v| v.trim()) {
+        Some("1") => true,
+        Some("0") => false,
+        _ => return Err(err!("API: Invalid 'value' form field.")),
+    };
+
+    match path {
+        "/api/seen" => conn
+            .items_set_seen(&items, value)
+            .await
+            .context("Database: Set seen")?,
+        "/api/starred" => conn
+            .items_set_starred(&items, value)
+            .await
+            .context("Database: Set starred")?,
+        path => return Err(err!("API path '{path}' is not supported.")),
+    }
+
+    serde_json::to_string(&ItemStateResult { items, value }).context("Serialize API result")
 }
 
 #[derive(PartialEq, Eq, Copy, Clone)]
@@ -317,11 +545,20 @@ pub struct PageGenResult {
 
 pub struct PageGen<'a> {
     db: &'a Db,
+    config: &'a Config,
+    templates: Templates,
+    urls: Urls,
 }
 
 impl<'a> PageGen<'a> {
-    pub async fn new(db: &'a Db) -> ah::Result<Self> {
-        Ok(Self { db })
+    pub async fn new(db: &'a Db, config: &'a Config, urls: Urls) -> ah::Result<Self> {
+        let templates = Templates::new(config.ui.template_dir.as_deref());
+        Ok(Self {
+            db,
+            config,
+            templates,
+            urls,
+        })
     }
 
     pub async fn get(
@@ -334,12 +571,17 @@ impl<'a> PageGen<'a> {
             "" | "/" | "/index.htm" | "/index.html" => {
                 let body = match get_body {
                     GetBody::Yes => {
-                        let mut body = String::with_capacity(BODY_PREALLOC);
                         let mut conn = self.db.open().await.context("Open database")?;
-                        gen_page(&mut body, &mut conn, query, None)
-                            .await
-                            .context("Generate page (GET)")?;
-                        body
+                        gen_page(
+                            &self.templates,
+                            &self.urls,
+                            &mut conn,
+                            self.config,
+                            query,
+                            None,
+                        )
+                        .await
+                        .context("Generate page (GET)")?
                     }
                     GetBody::No => "".to_string(),
                 };
@@ -354,7 +596,7 @@ impl<'a> PageGen<'a> {
                     GetBody::Yes => {
                         let mut conn = self.db.open().await.context("Open database")?;
                         let rev = conn.get_feed_update_revision().await?;
-                        format!("{}", rev & i32::MAX as i64)
+                        format!("{}", mask_rev(rev))
                     }
                     GetBody::No => "".to_string(),
                 };
@@ -364,10 +606,101 @@ impl<'a> PageGen<'a> {
                     mime: MIME_TEXT.to_string(),
                 })
             }
+            "/feed_update_wait" => {
+                let body = match get_body {
+                    GetBody::Yes => {
+                        let mut conn = self.db.open().await.context("Open database")?;
+                        wait_feed_update_rev(&mut conn, query.get_i64("rev"), LONGPOLL_TIMEOUT)
+                            .await?;
+                        let update = get_feed_update(&mut conn).await?;
+                        serde_json::to_string(&update).context("Serialize feed update")?
+                    }
+                    GetBody::No => "".to_string(),
+                };
+
+                Ok(PageGenResult {
+                    body,
+                    mime: MIME_JSON.to_string(),
+                })
+            }
+            "/export" => {
+                let (format, filter) = export_params(query)?;
+                let body = match get_body {
+                    GetBody::Yes => {
+                        let mut conn = self.db.open().await.context("Open database")?;
+                        gen_export(&self.urls, &mut conn, format, &filter)
+                            .await
+                            .context("Generate exported feed")?
+                    }
+                    GetBody::No => "".to_string(),
+                };
+
+                Ok(PageGenResult {
+                    body,
+                    mime: format.mime().to_string(),
+                })
+            }
             path => Err(err!("Path '{path}' is not supported.")),
         }
     }
 
+    /// Check whether `path` is a streaming path that must be handled by [Self::stream].
+    pub fn is_stream(&self, path: &str) -> bool {
+        path == "/feed_update_events"
+    }
+
+    pub fn stream_mime(&self) -> &'static str {
+        MIME_EVENT_STREAM
+    }
+
+    /// Generate a Server-Sent-Events stream of feed updates.
+    ///
+    /// The stream is written to `out` and ends after a timeout
+    /// or when writing to `out` fails (client disconnected).
+    pub async fn stream(
+        &mut self,
+        path: &str,
+        query: &Query,
+        last_event_id: Option<&str>,
+        out: &mut impl Write,
+    ) -> ah::Result<()> {
+        if !self.is_stream(path) {
+            return Err(err!("Path '{path}' is not a stream."));
+        }
+
+        let mut conn = self.db.open().await.context("Open database")?;
+        let mut client_rev = query
+            .get_i64("rev")
+            .or_else(|| last_event_id.and_then(|id| id.trim().parse().ok()));
+
+        let deadline = Instant::now() + EVENTS_TIMEOUT;
+        if write!(out, "retry: 2000\n\n")
+            .and_then(|_| out.flush())
+            .is_err()
+        {
+            return Ok(());
+        }
+        while Instant::now() < deadline {
+            let rev = wait_feed_update_rev(&mut conn, client_rev, EVENTS_KEEPALIVE).await?;
+            let event = if client_rev == Some(rev) {
+                ": keepalive\n\n".to_string()
+            } else {
+                client_rev = Some(rev);
+                let update = get_feed_update(&mut conn).await?;
+                let data = serde_json::to_string(&update).context("Serialize feed update")?;
+                format!("id: {rev}\nevent: update\ndata: {data}\n\n")
+            };
+            if out
+                .write_all(event.as_bytes())
+                .and_then(|_| out.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn post(
         &mut self,
         path: &str,
@@ -376,19 +709,163 @@ impl<'a> PageGen<'a> {
     ) -> ah::Result<PageGenResult> {
         match path {
             "" | "/" | "/index.htm" | "/index.html" => {
-                let mut body = String::with_capacity(BODY_PREALLOC);
                 let mut conn = self.db.open().await.context("Open database")?;
-                gen_page(&mut body, &mut conn, query, Some(formfields))
-                    .await
-                    .context("Generate page (POST)")?;
+                let body = gen_page(
+                    &self.templates,
+                    &self.urls,
+                    &mut conn,
+                    self.config,
+                    query,
+                    Some(formfields),
+                )
+                .await
+                .context("Generate page (POST)")?;
                 Ok(PageGenResult {
                     body,
                     mime: MIME_HTML.to_string(),
                 })
             }
+            "/api/seen" | "/api/starred" => {
+                let mut conn = self.db.open().await.context("Open database")?;
+                let body = api_item_state(&mut conn, path, formfields).await?;
+                Ok(PageGenResult {
+                    body,
+                    mime: MIME_JSON.to_string(),
+                })
+            }
             path => Err(err!("Path '{path}' is not supported.")),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util;
+
+    #[test]
+    fn test_is_safe_link() {
+        assert!(is_safe_link("https://example.com/a:b"));
+        assert!(is_safe_link("HTTP://example.com"));
+        assert!(is_safe_link("/relative/a:b"));
+        assert!(is_safe_link("?a=b:c"));
+        assert!(is_safe_link(""));
+        assert!(!is_safe_link("javascript:alert(1)"));
+        assert!(!is_safe_link(" JaVa\tScRiPt:alert(1)"));
+        assert!(!is_safe_link("data:text/html,<script>"));
+        assert!(!is_safe_link("vbscript:x"));
+    }
+
+    #[tokio::test]
+    async fn test_render_hostile_item() {
+        let db = Db::new_memory("test_render_hostile_item").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed("https://a.example").await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let feed = feedsdb::Feed {
+            title: "<b onclick=\"alert('f')\">".to_string(),
+            ..feeds[0].clone()
+        };
+        let item = |n: i64, link: &str| feedsdb::Item {
+            item_id: Some(format!("id{n}")),
+            author: "<i>author</i>".to_string(),
+            summary: "<img src=x onerror=\"alert('s')\">".to_string(),
+            tags: vec!["<tag>".to_string()],
+            suppressed_by: Some("<rule>".to_string()),
+            ..test_util::item(
+                &format!("item{n}"),
+                &format!("\"><script>alert({n})</script>"),
+                link,
+            )
+        };
+        let items = [
+            item(1, "javascript:alert(1)"),
+            item(2, " JaVa\tScRiPt:alert(2)"),
+            item(3, "https://example.com/?a=1&b=\"2\""),
+        ];
+        conn.update_feed(&feed, &items, None, false).await.unwrap();
+
+        let templates = Templates::new(None);
+        let urls = Urls {
+            script: "/x\"</script><script>alert('u')</script>".to_string(),
+            ..Urls::new(&Default::default(), "/cgi-bin/feeds", "")
+        };
+        let query = Query::parse(&format!("id={}", feed.feed_id.unwrap())).unwrap();
+        let body = gen_page(
+            &templates,
+            &urls,
+            &mut conn,
+            &Config::default(),
+            &query,
+            None,
+        )
+        .await
+        .unwrap();
+
+        // The only script element is the one of the page itself.
+        assert_eq!(body.matches("<script").count(), 1);
+        assert_eq!(body.matches("</script>").count(), 1);
+        assert!(!body.contains("<b "));
+        assert!(!body.contains("<i>"));
+        assert!(!body.contains("<img"));
+        assert!(!body.contains("<tag>"));
+        assert!(!body.contains("<rule>"));
+        assert!(!body.to_ascii_lowercase().contains("javascript:"));
+        assert!(body.contains("&lt;script&gt;alert(1)&lt;"));
+        assert!(body.contains("&lt;img src=x onerror=&quot;alert("));
+        assert!(
+            body.contains("href=\"https:&#x2f;&#x2f;example.com&#x2f;?a=1&amp;b=&quot;2&quot;\"")
+        );
+        assert!(body.contains("data-link=\"\""));
+    }
+
+    #[tokio::test]
+    async fn test_wait_feed_update_rev() {
+        let db = Db::new_memory("test_wait_feed_update_rev").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed("https://a.example").await.unwrap();
+        let rev = mask_rev(conn.get_feed_update_revision().await.unwrap());
+
+        // Clients without or with an outdated revision get the current one at once.
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let r = wait_feed_update_rev(&mut conn, None, timeout).await;
+        assert_eq!(r.unwrap(), rev);
+        let r = wait_feed_update_rev(&mut conn, Some(rev - 1), timeout).await;
+        assert_eq!(r.unwrap(), rev);
+        assert!(start.elapsed() < REV_POLL_INTERVAL);
+
+        // Up-to-date clients wait until the timeout expired.
+        let start = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let r = wait_feed_update_rev(&mut conn, Some(rev), timeout).await;
+        assert_eq!(r.unwrap(), rev);
+        assert!(start.elapsed() >= timeout);
+
+        // ... or until a feed update happened.
+        let mut updater_conn = db.open().await.unwrap();
+        let updater = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let (feeds, _) = updater_conn.get_feeds(None).await.unwrap();
+            let feed = feedsdb::Feed {
+                updated_items: 3,
+                ..feeds[0].clone()
+            };
+            updater_conn
+                .update_feed(&feed, &[], None, true)
+                .await
+                .unwrap();
+        });
+        let timeout = Duration::from_secs(60);
+        let r = wait_feed_update_rev(&mut conn, Some(rev), timeout).await;
+        assert_eq!(r.unwrap(), rev + 1);
+        updater.await.unwrap();
+
+        let update = get_feed_update(&mut conn).await.unwrap();
+        assert_eq!(update.rev, rev + 1);
+        assert_eq!(update.feeds.len(), 1);
+        assert_eq!(update.feeds[0].updated_items, 3);
+    }
+}
+
 // vim: ts=4 sw=4 expandtab
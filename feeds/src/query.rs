@@ -37,6 +37,21 @@ impl Query {
         })
     }
 
+    /// Get all IDs from a comma separated list.
+    pub fn get_i64_list(&self, key: &str) -> ah::Result<Vec<i64>> {
+        let Some(v) = self.qs.get_str(key) else {
+            return Ok(vec![]);
+        };
+        v.split(',')
+            .map(|id| id.trim())
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                id.parse()
+                    .map_err(|_| err!("Invalid ID '{id}' in query '{key}'"))
+            })
+            .collect()
+    }
+
     pub fn get(&self, key: &str) -> Option<&str> {
         self.qs.get_str(key)
     }
@@ -49,6 +64,21 @@ impl Query {
             .ok()
             .flatten()
     }
+
+    pub fn get_usize(&self, key: &str) -> Option<usize> {
+        self.qs
+            .get_str(key)
+            .map(|v| v.trim().parse())
+            .transpose()
+            .ok()
+            .flatten()
+    }
+}
+
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.qs)
+    }
 }
 
 // vim: ts=4 sw=4 expandtab
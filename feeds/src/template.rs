@@ -0,0 +1,124 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow::{self as ah, Context as _};
+use minijinja::{Environment, Error, ErrorKind};
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+
+/// The compiled-in default templates.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("page.html", include_str!("templates/page.html")),
+    ("feed_list.html", include_str!("templates/feed_list.html")),
+    ("item_list.html", include_str!("templates/item_list.html")),
+    (
+        "item_history.html",
+        include_str!("templates/item_history.html"),
+    ),
+    ("script.js", include_str!("templates/script.js")),
+];
+
+fn default_template(name: &str) -> Option<&'static str> {
+    DEFAULT_TEMPLATES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, t)| *t)
+}
+
+/// Load the template `name` from `dir`.
+/// Falls back to the compiled-in default, if the file does not exist.
+fn load_template(dir: Option<&Path>, name: &str) -> Result<Option<String>, Error> {
+    // Do not allow templates to escape from the template directory.
+    let valid = Path::new(name)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)));
+    if !valid {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Invalid template name '{name}'"),
+        ));
+    }
+    if let Some(dir) = dir {
+        let path = dir.join(name);
+        match std::fs::read_to_string(&path) {
+            Ok(t) => return Ok(Some(t)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("Failed to read template '{}'", path.display()),
+                )
+                .with_source(e));
+            }
+        }
+    }
+    Ok(default_template(name).map(|t| t.to_string()))
+}
+
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Create the template environment.
+    ///
+    /// Templates in `dir` override the compiled-in default templates with the same name.
+    pub fn new(dir: Option<&Path>) -> Self {
+        let dir: Option<PathBuf> = dir.map(|d| d.to_path_buf());
+        let mut env = Environment::new();
+        env.set_loader(move |name| load_template(dir.as_deref(), name));
+        Self { env }
+    }
+
+    pub fn render(&self, name: &str, ctx: impl Serialize) -> ah::Result<String> {
+        self.env
+            .get_template(name)
+            .with_context(|| format!("Load template '{name}'"))?
+            .render(ctx)
+            .with_context(|| format!("Render template '{name}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_dir() {
+        let base = std::env::temp_dir().join(format!("feeds-template-{}", std::process::id()));
+        let dir = base.join("templates");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(base.join("secret.html"), "SECRET").unwrap();
+        std::fs::write(dir.join("feed_list.html"), "OVERRIDE").unwrap();
+        std::fs::write(dir.join("escape.html"), r#"{% include "../secret.html" %}"#).unwrap();
+
+        let templates = Templates::new(Some(&dir));
+        // Overrides take precedence over the defaults.
+        assert_eq!(templates.render("feed_list.html", ()).unwrap(), "OVERRIDE");
+        // Templates outside of the template directory are rejected.
+        assert!(templates.render("../secret.html", ()).is_err());
+        assert!(templates.render("escape.html", ()).is_err());
+        assert!(templates.render("/etc/passwd", ()).is_err());
+        assert!(Templates::new(None).render("../page.html", ()).is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,278 @@
+// -*- coding: utf-8 -*-
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+function show_updates_available() {
+    var elem_feed_table_head_ext = document.getElementById("feed_table_head_ext");
+    if (elem_feed_table_head_ext && elem_feed_table_head_ext.hasAttribute("hidden")) {
+        elem_feed_table_head_ext.textContent += "(UPDATES AVAILABLE)";
+        elem_feed_table_head_ext.style.color = "red";
+        elem_feed_table_head_ext.style.fontWeight = "bold";
+        elem_feed_table_head_ext.removeAttribute("hidden");
+    }
+}
+
+// Update the feed list counters from a feed update.
+// Returns false, if the feed list structure changed and cannot be updated live.
+function apply_feed_update(update) {
+    var rows = document.querySelectorAll("tr[data-feed-id]");
+    if (rows.length != update.feeds.length) {
+        return false;
+    }
+    for (var i = 0; i < update.feeds.length; i++) {
+        var feed = update.feeds[i];
+        var row = document.querySelector('tr[data-feed-id="' + feed.id + '"]');
+        if (!row) {
+            return false;
+        }
+        var elem_count = row.querySelector(".updated_items");
+        if (elem_count) {
+            elem_count.textContent = feed.updated_items > 0 ? " (" + feed.updated_items + ")" : "";
+        }
+        var elem_title = row.querySelector(".feed_title > span");
+        if (elem_title) {
+            elem_title.classList.toggle("new_items", feed.updated_items > 0);
+            elem_title.classList.toggle("disabled", feed.disabled);
+        }
+    }
+    return true;
+}
+
+function handle_feed_update(update) {
+    var elem_rev_static = document.getElementById("feed_update_revision_static");
+    var elem_rev_dynamic = document.getElementById("feed_update_revision_dynamic");
+    if (!elem_rev_static || !elem_rev_dynamic) {
+        return false;
+    }
+    elem_rev_dynamic.textContent = update.rev;
+    if (update.rev == parseInt(elem_rev_static.textContent)) {
+        return true;
+    }
+    if (!apply_feed_update(update)) {
+        show_updates_available();
+        return false;
+    }
+    elem_rev_static.textContent = update.rev;
+    return true;
+}
+
+function current_rev() {
+    var elem_rev_static = document.getElementById("feed_update_revision_static");
+    return elem_rev_static ? parseInt(elem_rev_static.textContent) : NaN;
+}
+
+// Long-polling: The request blocks on the server until the revision changed.
+function send_feed_update_wait_request() {
+    var request = new XMLHttpRequest();
+    request.open('GET', {{ urls.script|tojson }} + '/feed_update_wait?rev=' + current_rev());
+
+    request.onreadystatechange = function() {
+        if (request.readyState == 4) { // Done
+            var again = true;
+            var delay = 10000;
+
+            if (request.status == 200) { // Ok
+                try {
+                    again = handle_feed_update(JSON.parse(request.responseText));
+                    delay = 1000;
+                } catch (e) {
+                    again = true;
+                }
+            }
+
+            if (again) {
+                setTimeout(send_feed_update_wait_request, delay);
+            }
+        }
+    };
+
+    request.send(null);
+}
+
+// Server-Sent-Events: The server pushes revision changes.
+function start_feed_update_events() {
+    var source = new EventSource({{ urls.script|tojson }} + '/feed_update_events?rev=' + current_rev());
+
+    source.addEventListener("update", function(event) {
+        var ok = false;
+        try {
+            ok = handle_feed_update(JSON.parse(event.data));
+        } catch (e) {
+            ok = false;
+        }
+        if (!ok) {
+            source.close();
+        }
+    });
+}
+
+function start_feed_updates() {
+    var elem_mode = document.getElementById("feed_update_mode");
+    var mode = elem_mode ? elem_mode.textContent.trim() : "wait";
+
+    if (mode == "events" && typeof EventSource !== "undefined") {
+        start_feed_update_events();
+    } else {
+        send_feed_update_wait_request();
+    }
+}
+
+start_feed_updates();
+
+// Keyboard navigation.
+
+var current_item = -1;
+
+function all_items() {
+    return document.querySelectorAll("#item_list .item[data-item-id]");
+}
+
+function select_item(index) {
+    var items = all_items();
+    if (items.length == 0) {
+        return null;
+    }
+    index = Math.max(0, Math.min(items.length - 1, index));
+    if (current_item >= 0 && current_item < items.length) {
+        items[current_item].classList.remove("current");
+    }
+    current_item = index;
+    items[index].classList.add("current");
+    items[index].scrollIntoView({block: "nearest"});
+    return items[index];
+}
+
+function selected_item() {
+    var items = all_items();
+    if (current_item >= 0 && current_item < items.length) {
+        return items[current_item];
+    }
+    return select_item(0);
+}
+
+// Send an item state change to the server and update the item on success.
+function send_item_state(item, api, value, on_success) {
+    var data = new FormData();
+    data.append("item", item.dataset.itemId);
+    data.append("value", value ? "1" : "0");
+
+    var request = new XMLHttpRequest();
+    request.open('POST', {{ urls.script|tojson }} + '/api/' + api);
+    request.onreadystatechange = function() {
+        if (request.readyState == 4 && request.status == 200) { // Done and Ok
+            on_success();
+        }
+    };
+    request.send(data);
+}
+
+function update_item_button(item, names, texts, state) {
+    for (var i = 0; i < names.length; i++) {
+        var button = item.querySelector('button[name="' + names[i] + '"]');
+        if (button) {
+            button.name = names[state ? 1 : 0];
+            button.innerHTML = texts[state ? 1 : 0];
+            return;
+        }
+    }
+}
+
+function toggle_item_seen(item) {
+    var seen = item.dataset.seen != "1";
+    send_item_state(item, "seen", seen, function() {
+        item.dataset.seen = seen ? "1" : "0";
+        item.classList.toggle("unseen", !seen);
+        update_item_button(item, ["seen", "unseen"], ["seen", "unseen"], seen);
+    });
+}
+
+function toggle_item_starred(item) {
+    var starred = item.dataset.starred != "1";
+    send_item_state(item, "starred", starred, function() {
+        item.dataset.starred = starred ? "1" : "0";
+        item.classList.toggle("starred", starred);
+        update_item_button(item, ["star", "unstar"], ["&#9734;", "&#9733;"], starred);
+    });
+}
+
+// Navigate to the next (direction = 1) or previous (direction = -1) unread feed.
+function goto_unread_feed(direction) {
+    var rows = Array.prototype.slice.call(document.querySelectorAll("tr[data-feed-id]"));
+    var elem_item_list = document.getElementById("item_list");
+    var active_id = elem_item_list ? elem_item_list.dataset.feedId : null;
+    var start = rows.findIndex(function(row) { return row.dataset.feedId == active_id; });
+    for (var i = 1; i <= rows.length; i++) {
+        var index = ((start < 0 ? (direction > 0 ? -1 : 0) : start) + i * direction + rows.length) % rows.length;
+        var row = rows[index];
+        if (row.querySelector(".new_items") && row.dataset.feedId != active_id) {
+            window.location.href = {{ urls.script|tojson }} + "?id=" + row.dataset.feedId;
+            return;
+        }
+    }
+}
+
+function toggle_help() {
+    var elem_help = document.getElementById("keyboard_help");
+    if (elem_help) {
+        elem_help.toggleAttribute("hidden");
+    }
+}
+
+function handle_key(event) {
+    if (event.ctrlKey || event.altKey || event.metaKey) {
+        return;
+    }
+    var target = event.target;
+    if (target && (target.tagName == "INPUT" || target.tagName == "TEXTAREA")) {
+        return;
+    }
+    var item;
+    switch (event.key) {
+    case "j":
+        select_item(current_item + 1);
+        break;
+    case "k":
+        select_item(current_item - 1);
+        break;
+    case "o":
+        item = selected_item();
+        if (item && item.dataset.link) {
+            window.open(item.dataset.link, "_blank", "noopener");
+        }
+        break;
+    case "u":
+        item = selected_item();
+        if (item) {
+            toggle_item_seen(item);
+        }
+        break;
+    case "s":
+        item = selected_item();
+        if (item) {
+            toggle_item_starred(item);
+        }
+        break;
+    case "n":
+        goto_unread_feed(1);
+        break;
+    case "p":
+        goto_unread_feed(-1);
+        break;
+    case "?":
+        toggle_help();
+        break;
+    case "Escape":
+        var elem_help = document.getElementById("keyboard_help");
+        if (elem_help) {
+            elem_help.setAttribute("hidden", "");
+        }
+        break;
+    default:
+        return;
+    }
+    event.preventDefault();
+}
+
+document.addEventListener("keydown", handle_key);
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,243 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use feedscfg::ConfigUi;
+use serde::Serialize;
+
+const DEFAULT_SCRIPT: &str = "/cgi-bin/feeds";
+const RESOURCES_DIR: &str = "/feeds";
+
+/// Normalize an URL path: Single leading slash, no trailing slash.
+/// The root path is represented by an empty string.
+fn normalize(path: &str) -> String {
+    let path = path.trim().trim_end_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else if path.starts_with('/') || path.contains("://") {
+        path.to_string()
+    } else {
+        format!("/{path}")
+    }
+}
+
+/// Get the mount point of the feedreader from the CGI script path.
+///
+/// `/cgi-bin/feeds` is mounted at the root and `/foo/cgi-bin/feeds` is mounted at `/foo`.
+/// Scripts outside of a `cgi-bin` directory are mounted at their parent directory.
+fn mount_point(script: &str) -> &str {
+    if let Some(pos) = script.rfind("/cgi-bin/") {
+        &script[..pos]
+    } else if let Some(pos) = script.rfind('/') {
+        &script[..pos]
+    } else {
+        ""
+    }
+}
+
+/// The URLs of the web user interface.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Urls {
+    /// The CGI script. All links and form actions are based on this.
+    pub script: String,
+    /// The directory of the static resources.
+    pub resources: String,
+    pub stylesheet: String,
+    pub icon: String,
+    /// Scheme and host of the server, e.g. `https://example.com`.
+    /// Empty, if unknown.
+    pub origin: String,
+}
+
+impl Urls {
+    /// Derive the URLs from the CGI `SCRIPT_NAME` and an optional
+    /// `X-Forwarded-Prefix` of a reverse proxy.
+    ///
+    /// The URLs from the configuration take precedence.
+    /// The `X-Forwarded-Prefix` is ignored, unless `trust-forwarded-headers` is enabled.
+    pub fn new(config: &ConfigUi, script_name: &str, forwarded_prefix: &str) -> Self {
+        let prefix = if config.trust_forwarded_headers {
+            normalize(forwarded_prefix)
+        } else {
+            String::new()
+        };
+
+        let script = match &config.script_url {
+            Some(url) => normalize(url),
+            None => {
+                let script_name = normalize(script_name);
+                if script_name.is_empty() {
+                    format!("{prefix}{DEFAULT_SCRIPT}")
+                } else {
+                    format!("{prefix}{script_name}")
+                }
+            }
+        };
+
+        let resources = match &config.resource_url {
+            Some(url) => normalize(url),
+            None => format!("{}{RESOURCES_DIR}", mount_point(&script)),
+        };
+
+        Self {
+            stylesheet: format!("{resources}/style.css"),
+            icon: format!("{resources}/icon.png"),
+            script,
+            resources,
+            origin: String::new(),
+        }
+    }
+
+    /// Set the origin from the CGI `HTTPS` and `HTTP_HOST` variables
+    /// and the `X-Forwarded-Proto` of a reverse proxy.
+    ///
+    /// The `X-Forwarded-Proto` is ignored, unless `trust-forwarded-headers` is enabled.
+    pub fn with_origin(
+        mut self,
+        config: &ConfigUi,
+        https: &str,
+        forwarded_proto: &str,
+        host: &str,
+    ) -> Self {
+        let https = https.eq_ignore_ascii_case("on")
+            || (config.trust_forwarded_headers && forwarded_proto.eq_ignore_ascii_case("https"));
+        let host = host.trim();
+        if !host.is_empty() {
+            let scheme = if https { "https" } else { "http" };
+            self.origin = format!("{scheme}://{host}");
+        }
+        self
+    }
+
+    /// Make an absolute URL from a path below the script URL.
+    pub fn absolute(&self, path: &str) -> String {
+        if self.script.contains("://") {
+            format!("{}{path}", self.script)
+        } else {
+            format!("{}{}{path}", self.origin, self.script)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(script_name: &str, forwarded_prefix: &str) -> Urls {
+        Urls::new(&ConfigUi::default(), script_name, forwarded_prefix)
+    }
+
+    fn trusted() -> ConfigUi {
+        ConfigUi {
+            trust_forwarded_headers: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_mount() {
+        let u = urls("/cgi-bin/feeds", "");
+        assert_eq!(u.script, "/cgi-bin/feeds");
+        assert_eq!(u.resources, "/feeds");
+        assert_eq!(u.stylesheet, "/feeds/style.css");
+        assert_eq!(u.icon, "/feeds/icon.png");
+
+        // No SCRIPT_NAME from the web server.
+        assert_eq!(urls("", ""), u);
+    }
+
+    #[test]
+    fn test_nested_mount() {
+        let u = urls("/apps/reader/cgi-bin/feeds", "");
+        assert_eq!(u.script, "/apps/reader/cgi-bin/feeds");
+        assert_eq!(u.resources, "/apps/reader/feeds");
+        assert_eq!(u.stylesheet, "/apps/reader/feeds/style.css");
+
+        let u = urls("/apps/reader/feeds.cgi", "");
+        assert_eq!(u.script, "/apps/reader/feeds.cgi");
+        assert_eq!(u.resources, "/apps/reader/feeds");
+
+        let u = urls("/apps/reader/cgi-bin/feeds/", "");
+        assert_eq!(u.script, "/apps/reader/cgi-bin/feeds");
+    }
+
+    #[test]
+    fn test_reverse_proxy() {
+        let u = Urls::new(&trusted(), "/cgi-bin/feeds", "/proxy/sub/");
+        assert_eq!(u.script, "/proxy/sub/cgi-bin/feeds");
+        assert_eq!(u.resources, "/proxy/sub/feeds");
+        assert_eq!(u.icon, "/proxy/sub/feeds/icon.png");
+        let u = u.with_origin(&trusted(), "", "HTTPS", "example.com");
+        assert_eq!(
+            u.absolute(""),
+            "https://example.com/proxy/sub/cgi-bin/feeds"
+        );
+
+        // The forwarded headers are ignored, unless they are trusted.
+        let u = urls("/cgi-bin/feeds", "/proxy/sub/");
+        assert_eq!(u, urls("/cgi-bin/feeds", ""));
+        let u = u.with_origin(&ConfigUi::default(), "", "https", "example.com");
+        assert_eq!(u.absolute(""), "http://example.com/cgi-bin/feeds");
+    }
+
+    #[test]
+    fn test_config_override() {
+        let config = ConfigUi {
+            script_url: Some("/reader/".to_string()),
+            resource_url: Some("https://static.example.com/feeds/".to_string()),
+            ..Default::default()
+        };
+        let u = Urls::new(&config, "/cgi-bin/feeds", "/ignored");
+        assert_eq!(u.script, "/reader");
+        assert_eq!(u.resources, "https://static.example.com/feeds");
+        assert_eq!(u.stylesheet, "https://static.example.com/feeds/style.css");
+
+        let config = ConfigUi {
+            script_url: Some("/x/cgi-bin/feeds".to_string()),
+            ..Default::default()
+        };
+        let u = Urls::new(&config, "/cgi-bin/feeds", "");
+        assert_eq!(u.resources, "/x/feeds");
+    }
+
+    #[test]
+    fn test_absolute() {
+        let u = urls("/cgi-bin/feeds", "");
+        assert_eq!(u.absolute("/export"), "/cgi-bin/feeds/export");
+        let config = ConfigUi::default();
+        let u = u.with_origin(&config, "on", "", "example.com");
+        assert_eq!(
+            u.absolute("/export"),
+            "https://example.com/cgi-bin/feeds/export"
+        );
+        let u = u.with_origin(&config, "", "", "example.com:8080");
+        assert_eq!(u.absolute(""), "http://example.com:8080/cgi-bin/feeds");
+
+        let config = ConfigUi {
+            script_url: Some("https://reader.example.com/feeds".to_string()),
+            ..Default::default()
+        };
+        let u = Urls::new(&config, "/cgi-bin/feeds", "").with_origin(&config, "", "", "internal");
+        assert_eq!(
+            u.absolute("/export"),
+            "https://reader.example.com/feeds/export"
+        );
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -17,35 +17,94 @@
 //
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use anyhow::{self as ah, Context as _};
-use nix::{
-    sys::signal::{Signal, kill},
-    unistd::Pid,
-};
-use tokio::fs::read_to_string;
-
-/// Get the PID of the `feedsd` daemon process.
-async fn get_feedsd_pid() -> ah::Result<Pid> {
-    let pid = read_to_string("/run/feedsd/feedsd.pid")
-        .await
-        .context("Read PID-file of 'feedsd' daemon")?;
-    let pid: i32 = pid
-        .trim()
-        .parse()
-        .context("Parse 'feedsd' PID-file string to number")?;
-    Ok(Pid::from_raw(pid))
+use feedscfg::Config;
+use feedsctl::{DEFAULT_SOCKET, RefreshOutcome, Request, Response, request};
+use std::{path::Path, time::Duration};
+
+/// The path of the `feedsd` control socket.
+fn control_socket(config: &Config) -> &Path {
+    config
+        .ui
+        .control_socket
+        .as_deref()
+        .unwrap_or(Path::new(DEFAULT_SOCKET))
 }
 
-pub async fn wakeup_feedsd() {
-    let pid = match get_feedsd_pid().await {
-        Ok(pid) => pid,
-        Err(e) => {
-            log::warn!("Failed to get feedsd pid: {e:?}");
-            return;
+/// Ask the `feedsd` daemon to refresh the feeds that are due.
+pub async fn wakeup_feedsd(config: &Config) {
+    let timeout = Duration::from_secs(3);
+    match request(control_socket(config), &Request::Wakeup, timeout).await {
+        Ok(resp) => {
+            if let Err(e) = resp.into_result() {
+                log::warn!("Failed to wake up feedsd: {e:?}");
+            }
         }
+        Err(e) => log::warn!("Failed to wake up feedsd: {e:?}"),
+    }
+}
+
+/// Ask the `feedsd` daemon to refresh the feeds now and wait for the outcome.
+pub async fn refresh_feeds_now(config: &Config, feed_ids: &[i64]) -> Vec<RefreshOutcome> {
+    let timeout = Duration::from_secs(60);
+    let req = Request::RefreshFeeds {
+        feed_ids: feed_ids.to_vec(),
+    };
+    let error = match request(control_socket(config), &req, timeout).await {
+        Ok(Response::Refreshed { feeds }) => return feeds,
+        Ok(Response::Error { message }) => message,
+        Ok(_) => "Unexpected response from feedsd.".to_string(),
+        Err(e) => format!("{e:#}"),
     };
-    if let Err(e) = kill(pid, Signal::SIGHUP) {
-        log::warn!("Failed to send SIGHUP to feedsd: {e:?}");
+    log::warn!("Failed to refresh feeds: {error}");
+    feed_ids
+        .iter()
+        .map(|feed_id| RefreshOutcome::error(*feed_id, &error))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsctl::{read_message, write_message};
+    use tokio::{io::BufReader, net::UnixListener};
+
+    #[tokio::test]
+    async fn test_control_socket_config() {
+        let dir = std::env::temp_dir().join(format!("feeds-wakeup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("feedsd.sock");
+        let config =
+            Config::parse_str(&format!("[ui]\ncontrol-socket = '{}'\n", path.display())).unwrap();
+        assert_eq!(control_socket(&config), path);
+        assert_eq!(
+            control_socket(&Config::default()),
+            Path::new(DEFAULT_SOCKET)
+        );
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (r, mut w) = stream.into_split();
+            let req: Request = read_message(&mut BufReader::new(r)).await.unwrap().unwrap();
+            let resp = Response::Refreshed {
+                feeds: vec![RefreshOutcome {
+                    feed_id: 7,
+                    new_items: 3,
+                    error: None,
+                }],
+            };
+            write_message(&mut w, &resp).await.unwrap();
+            req
+        });
+        let outcomes = refresh_feeds_now(&config, &[7]).await;
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].new_items, 3);
+        assert_eq!(
+            server.await.unwrap(),
+            Request::RefreshFeeds { feed_ids: vec![7] }
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
 
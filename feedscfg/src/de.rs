@@ -0,0 +1,234 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Deserialization helpers for the configuration values.
+
+use regex::Regex;
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, Unexpected, Visitor},
+};
+use std::{fmt, num::NonZeroUsize, path::PathBuf, time::Duration};
+
+pub(crate) const DAYS_TO_SECS: u64 = 24 * 60 * 60;
+
+/// Parse a duration such as `90s`, `10m`, `1h30m`, `180d` or `2w`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let mut secs: u64 = 0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return None;
+        }
+        let value: u64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+        let unit = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let factor = match &rest[..unit] {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => DAYS_TO_SECS,
+            "w" => 7 * DAYS_TO_SECS,
+            _ => return None,
+        };
+        secs = secs.checked_add(value.checked_mul(factor)?)?;
+        rest = rest[unit..].trim_start();
+    }
+    Some(Duration::from_secs(secs))
+}
+
+/// A duration either as integer in units of `unit_secs` or as duration string.
+struct DurationVisitor {
+    unit_secs: u64,
+    nonzero: bool,
+}
+
+impl DurationVisitor {
+    fn check<E: de::Error>(&self, d: Duration, unexp: Unexpected<'_>) -> Result<Duration, E> {
+        if self.nonzero && d.is_zero() {
+            Err(E::invalid_value(unexp, self))
+        } else {
+            Ok(d)
+        }
+    }
+}
+
+impl Visitor<'_> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = if self.unit_secs == DAYS_TO_SECS {
+            "days"
+        } else {
+            "seconds"
+        };
+        let nonzero = if self.nonzero { "non-zero " } else { "" };
+        write!(
+            f,
+            "a {nonzero}number of {unit} or a duration such as \"90s\", \"10m\", \"1h30m\" or \"180d\""
+        )
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Duration, E> {
+        let unexp = Unexpected::Signed(v);
+        match u64::try_from(v)
+            .ok()
+            .and_then(|v| v.checked_mul(self.unit_secs))
+        {
+            Some(secs) => self.check(Duration::from_secs(secs), unexp),
+            None => Err(E::invalid_value(unexp, &self)),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Duration, E> {
+        let unexp = Unexpected::Unsigned(v);
+        match v.checked_mul(self.unit_secs) {
+            Some(secs) => self.check(Duration::from_secs(secs), unexp),
+            None => Err(E::invalid_value(unexp, &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Duration, E> {
+        let unexp = Unexpected::Str(v);
+        match parse_duration(v) {
+            Some(d) => self.check(d, unexp),
+            None => Err(E::invalid_value(unexp, &self)),
+        }
+    }
+}
+
+/// A duration in seconds or a duration string.
+pub fn secs<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    d.deserialize_any(DurationVisitor {
+        unit_secs: 1,
+        nonzero: false,
+    })
+}
+
+/// A non-zero duration in seconds or a duration string.
+pub fn nonzero_secs<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    d.deserialize_any(DurationVisitor {
+        unit_secs: 1,
+        nonzero: true,
+    })
+}
+
+/// A duration in days or a duration string.
+pub fn days<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    d.deserialize_any(DurationVisitor {
+        unit_secs: DAYS_TO_SECS,
+        nonzero: false,
+    })
+}
+
+/// A number between 0 and 1.
+pub fn fraction<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
+    let v = f64::deserialize(d)?;
+    if (0.0..=1.0).contains(&v) {
+        Ok(v)
+    } else {
+        Err(de::Error::invalid_value(
+            Unexpected::Float(v),
+            &"a number between 0 and 1",
+        ))
+    }
+}
+
+/// An integer where 0 means None.
+pub fn opt_nonzero<'de, D: Deserializer<'de>>(d: D) -> Result<Option<NonZeroUsize>, D::Error> {
+    Ok(NonZeroUsize::new(usize::deserialize(d)?))
+}
+
+/// A trimmed string where the empty string means None.
+pub fn opt_string<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    let s = String::deserialize(d)?;
+    let s = s.trim();
+    Ok((!s.is_empty()).then(|| s.to_string()))
+}
+
+/// A path where the empty string means None.
+pub fn opt_path<'de, D: Deserializer<'de>>(d: D) -> Result<Option<PathBuf>, D::Error> {
+    Ok(opt_string(d)?.map(PathBuf::from))
+}
+
+/// A single string or an array of strings.
+pub fn string_or_vec<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged, expecting = "a string or an array of strings")]
+    enum StringOrVec {
+        String(String),
+        Vec(Vec<String>),
+    }
+    Ok(match StringOrVec::deserialize(d)? {
+        StringOrVec::String(s) => vec![s],
+        StringOrVec::Vec(v) => v,
+    })
+}
+
+fn new_regex<E: de::Error>(s: &str) -> Result<Regex, E> {
+    Regex::new(s).map_err(|e| E::custom(format!("invalid regex: {e}")))
+}
+
+pub fn regex<'de, D: Deserializer<'de>>(d: D) -> Result<Regex, D::Error> {
+    new_regex(&String::deserialize(d)?)
+}
+
+pub fn opt_regex<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Regex>, D::Error> {
+    regex(d).map(Some)
+}
+
+pub fn regex_vec<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|s| new_regex(s))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let secs = |s| parse_duration(s).map(|d| d.as_secs());
+        assert_eq!(secs("90s"), Some(90));
+        assert_eq!(secs("10m"), Some(600));
+        assert_eq!(secs("1h30m"), Some(5400));
+        assert_eq!(secs(" 1h 30m "), Some(5400));
+        assert_eq!(secs("180d"), Some(180 * DAYS_TO_SECS));
+        assert_eq!(secs("2w"), Some(14 * DAYS_TO_SECS));
+        assert_eq!(secs("0s"), Some(0));
+        assert_eq!(secs(""), None);
+        assert_eq!(secs("10"), None);
+        assert_eq!(secs("m"), None);
+        assert_eq!(secs("10x"), None);
+        assert_eq!(secs("-10m"), None);
+        assert_eq!(secs("1.5h"), None);
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -17,87 +17,58 @@
 //
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+mod de;
+mod notify;
+mod rules;
+mod ser;
+
+use crate::de::DAYS_TO_SECS;
+pub use crate::{
+    de::parse_duration,
+    notify::{ConfigAlert, ConfigNotifyChannel, ConfigWebhook, DEFAULT_SENDMAIL, NotifyKind},
+    rules::{ConfigRule, RuleAction, RuleCondition, RuleField, RuleInput},
+    ser::format_duration,
+};
 use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::format::StrftimeItems;
 use regex::Regex;
-use std::{num::NonZeroUsize, path::Path, time::Duration};
-use toml::{Table, Value};
-
-const DAYS_TO_SECS: u64 = 24 * 60 * 60;
-
-fn parse_bool(name: &str, value: &Value) -> ah::Result<bool> {
-    match value {
-        Value::Boolean(b) => Ok(*b),
-        _ => Err(err!("Configuration entry '{name}' invalid boolean.")),
-    }
-}
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeMap as _};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroUsize,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use toml::{
+    Table, Value,
+    de::{DeTable, DeValue},
+};
 
-fn parse_regex(name: &str, value: &Value) -> ah::Result<Regex> {
-    if let Value::String(s) = value {
-        match Regex::new(s) {
-            Ok(re) => Ok(re),
-            Err(e) => Err(err!("Configuration entry '{name}' invalid regex: {e}")),
-        }
-    } else {
-        Err(err!(
-            "Configuration entry '{name}' array element is not a string."
-        ))
-    }
-}
-
-fn parse_regex_array(name: &str, value: &Value) -> ah::Result<Vec<Regex>> {
-    let mut ret = vec![];
-    if let Value::Array(array) = value {
-        for value in array {
-            ret.push(parse_regex(name, value)?);
-        }
-    } else {
-        return Err(err!("Configuration entry '{name}' is not an array."));
-    }
-    Ok(ret)
-}
-
-fn parse_duration_secs(name: &str, value: &Value) -> ah::Result<Duration> {
-    match value {
-        Value::Integer(secs) if secs >= &0 => Ok(Duration::from_secs(*secs as u64)),
-        _ => Err(err!("Configuration entry '{name}' invalid integer.")),
-    }
-}
+/// The default location of the configuration file.
+pub const DEFAULT_CONFIG_FILE: &str = "/opt/feedreader/etc/feedreader/feedreader.conf";
 
-fn parse_duration_days(name: &str, value: &Value) -> ah::Result<Duration> {
-    match value {
-        Value::Integer(days) if days >= &0 => {
-            Ok(Duration::from_secs((*days as u64) * DAYS_TO_SECS))
-        }
-        _ => Err(err!("Configuration entry '{name}' invalid integer.")),
-    }
-}
+/// The environment variable that overrides the location of the configuration file.
+pub const CONFIG_FILE_ENV: &str = "FEEDREADER_CONFIG";
 
-fn parse_f64(name: &str, value: &Value) -> ah::Result<f64> {
-    match value {
-        Value::Float(val) => Ok(*val),
-        Value::Integer(val) => Ok(*val as f64),
-        _ => Err(err!(
-            "Configuration entry '{name}' invalid floating point number."
-        )),
-    }
-}
+/// The environment variable that enables the strict mode.
+/// In strict mode a missing configuration file is an error.
+pub const CONFIG_STRICT_ENV: &str = "FEEDREADER_CONFIG_STRICT";
 
-fn parse_usize(name: &str, value: &Value) -> ah::Result<usize> {
-    match value {
-        Value::Integer(val) if val >= &0 => Ok(*val as usize),
-        _ => Err(err!("Configuration entry '{name}' invalid integer.")),
-    }
-}
+/// The directory of drop-in configuration fragments, next to the configuration file.
+pub const CONFIG_DROPIN_DIR: &str = "conf.d";
 
-fn parse_nonzerousize(name: &str, value: &Value) -> ah::Result<NonZeroUsize> {
-    parse_usize(name, value).and_then(|v| match v.try_into() {
-        Ok(v) => Ok(v),
-        Err(_) => Err(err!("Configuration entry '{name}' invalid integer.")),
-    })
-}
+/// The top level key that turns unknown keys into warnings instead of errors.
+pub const ALLOW_UNKNOWN_KEYS: &str = "allow-unknown-keys";
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ConfigNet {
+    #[serde(
+        rename = "timeout-secs",
+        deserialize_with = "de::nonzero_secs",
+        serialize_with = "ser::duration"
+    )]
     pub timeout: Duration,
     pub concurrency: NonZeroUsize,
 }
@@ -111,10 +82,22 @@ impl Default for ConfigNet {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ConfigDb {
+    #[serde(
+        rename = "refresh-interval-secs",
+        deserialize_with = "de::nonzero_secs",
+        serialize_with = "ser::duration"
+    )]
     pub refresh_interval: Duration,
+    #[serde(deserialize_with = "de::fraction")]
     pub refresh_slack: f64,
+    #[serde(
+        rename = "gc-age-offset-days",
+        deserialize_with = "de::days",
+        serialize_with = "ser::duration"
+    )]
     pub gc_age_offset: Duration,
     pub highlight_updated_items: bool,
 }
@@ -130,115 +113,1216 @@ impl Default for ConfigDb {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ConfigNoHighlighting {
+    #[serde(deserialize_with = "de::regex_vec", serialize_with = "ser::regex_vec")]
     pub title: Vec<Regex>,
+    #[serde(deserialize_with = "de::regex_vec", serialize_with = "ser::regex_vec")]
     pub summary: Vec<Regex>,
+    #[serde(deserialize_with = "de::regex_vec", serialize_with = "ser::regex_vec")]
     pub url: Vec<Regex>,
     pub set_seen: bool,
+    #[serde(rename = "feed")]
+    pub feeds: Vec<ConfigNoHighlightingFeed>,
+}
+
+/// Find the first regex that matches `s`.
+/// Returns the name of the matching rule.
+fn highlight_re_matches(set: &str, name: &str, s: &str, res: &[Regex]) -> Option<String> {
+    let re = res.iter().find(|re| re.is_match(s))?;
+    let rule = format!("{set}/{name}/{re}");
+    log::debug!("no-highlighting rule {rule} matches '{s}'.");
+    Some(rule)
+}
+
+impl ConfigNoHighlighting {
+    /// Check the global and the feed specific no-highlighting rules.
+    /// Returns the name of the rule that suppresses the highlighting of the item.
+    pub fn matching_rule(&self, input: &RuleInput<'_>) -> Option<String> {
+        let global = highlight_re_matches("global", "title", input.title, &self.title)
+            .or_else(|| highlight_re_matches("global", "summary", input.summary, &self.summary))
+            .or_else(|| highlight_re_matches("global", "url", input.url, &self.url));
+        if global.is_some() {
+            return global;
+        }
+        self.feeds
+            .iter()
+            .filter(|f| f.applies_to(input.feed_id, input.feed_href))
+            .find_map(|f| {
+                highlight_re_matches(&f.name, "title", input.title, &f.title)
+                    .or_else(|| highlight_re_matches(&f.name, "summary", input.summary, &f.summary))
+                    .or_else(|| highlight_re_matches(&f.name, "url", input.url, &f.url))
+            })
+    }
+}
+
+/// No-highlighting rules that only apply to specific feeds.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawNoHighlightingFeed", rename_all = "kebab-case")]
+pub struct ConfigNoHighlightingFeed {
+    pub name: String,
+    /// Applies to feeds with a matching URL.
+    #[serde(
+        serialize_with = "ser::opt_regex",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub href: Option<Regex>,
+    /// Applies to the feed with this ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feed_id: Option<i64>,
+    #[serde(serialize_with = "ser::regex_vec")]
+    pub title: Vec<Regex>,
+    #[serde(serialize_with = "ser::regex_vec")]
+    pub summary: Vec<Regex>,
+    #[serde(serialize_with = "ser::regex_vec")]
+    pub url: Vec<Regex>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawNoHighlightingFeed {
+    #[serde(default)]
+    name: String,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    href: Option<Regex>,
+    feed_id: Option<i64>,
+    #[serde(default, deserialize_with = "de::regex_vec")]
+    title: Vec<Regex>,
+    #[serde(default, deserialize_with = "de::regex_vec")]
+    summary: Vec<Regex>,
+    #[serde(default, deserialize_with = "de::regex_vec")]
+    url: Vec<Regex>,
+}
+
+impl TryFrom<RawNoHighlightingFeed> for ConfigNoHighlightingFeed {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawNoHighlightingFeed) -> ah::Result<Self> {
+        if raw.href.is_none() && raw.feed_id.is_none() {
+            return Err(err!(
+                "No-highlighting feed rules: Either 'href' or 'feed-id' is required."
+            ));
+        }
+        Ok(Self {
+            name: raw.name,
+            href: raw.href,
+            feed_id: raw.feed_id,
+            title: raw.title,
+            summary: raw.summary,
+            url: raw.url,
+        })
+    }
+}
+
+impl ConfigNoHighlightingFeed {
+    /// Check whether the rules apply to the feed.
+    pub fn applies_to(&self, feed_id: Option<i64>, href: &str) -> bool {
+        if let Some(id) = self.feed_id
+            && feed_id == Some(id)
+        {
+            return true;
+        }
+        if let Some(re) = &self.href
+            && re.is_match(href)
+        {
+            return true;
+        }
+        false
+    }
+}
+
+/// The item field that a rewrite rule extracts the date from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewriteField {
+    Id,
+    Link,
+    Title,
+}
+
+/// The format of a date extracted by a rewrite rule.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum RewriteDateFormat {
+    /// Decimal Unix timestamp in seconds.
+    Unix,
+    /// Hexadecimal Unix timestamp in seconds.
+    UnixHex,
+    /// A `chrono` strftime format string.
+    Chrono(String),
+}
+
+impl Serialize for RewriteDateFormat {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Unix => s.serialize_str("unix"),
+            Self::UnixHex => s.serialize_str("unix-hex"),
+            Self::Chrono(fmt) => s.serialize_str(fmt),
+        }
+    }
+}
+
+impl TryFrom<String> for RewriteDateFormat {
+    type Error = ah::Error;
+
+    fn try_from(format: String) -> ah::Result<Self> {
+        match format.trim() {
+            "unix" => Ok(Self::Unix),
+            "unix-hex" => Ok(Self::UnixHex),
+            fmt => {
+                if StrftimeItems::new(fmt).parse().is_err() {
+                    return Err(err!("Invalid date format '{fmt}'."));
+                }
+                Ok(Self::Chrono(fmt.to_string()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigRewriteDate {
+    /// The field to extract the date from.
+    pub field: RewriteField,
+    /// The first capture group (or the whole match) is the date string.
+    pub regex: Regex,
+    pub format: RewriteDateFormat,
+    /// Value to XOR with a Unix timestamp.
+    pub xor: i64,
+    /// Replace the date from the feed. Otherwise only items without date are rewritten.
+    pub replace: bool,
+    /// Drop the item, if the date can't be extracted.
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawRewrite")]
+pub struct ConfigRewrite {
+    pub name: String,
+    /// The rule only applies to feeds with a matching href. All feeds, if None.
+    pub feed: Option<Regex>,
+    pub date: Option<ConfigRewriteDate>,
+    pub title: Option<(Regex, String)>,
+    pub link: Option<(Regex, String)>,
+    pub author_default: Option<String>,
+}
+
+impl ConfigRewrite {
+    /// The built-in rules that are always active.
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            // blog.fefe.de does not have dates in the feed.
+            // The item id contains the XOR obfuscated Unix timestamp.
+            Self {
+                name: "builtin-fefe".to_string(),
+                feed: Some(Regex::new(r"^https?://blog\.fefe\.de/").unwrap()),
+                date: Some(ConfigRewriteDate {
+                    field: RewriteField::Id,
+                    regex: Regex::new(r"[?&]ts=([0-9a-fA-F]+)$").unwrap(),
+                    format: RewriteDateFormat::UnixHex,
+                    xor: 0xfefec0de,
+                    replace: false,
+                    required: true,
+                }),
+                title: None,
+                link: None,
+                author_default: None,
+            },
+        ]
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawRewrite {
+    #[serde(default)]
+    name: String,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    feed: Option<Regex>,
+    date_from: Option<RewriteField>,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    date_regex: Option<Regex>,
+    date_format: Option<RewriteDateFormat>,
+    #[serde(default)]
+    date_xor: i64,
+    #[serde(default)]
+    date_replace: bool,
+    #[serde(default)]
+    date_required: bool,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    title_regex: Option<Regex>,
+    title_replace: Option<String>,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    link_regex: Option<Regex>,
+    link_replace: Option<String>,
+    #[serde(default, deserialize_with = "de::opt_string")]
+    author_default: Option<String>,
+}
+
+impl Serialize for ConfigRewrite {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(None)?;
+        map.serialize_entry("name", &self.name)?;
+        if let Some(feed) = &self.feed {
+            map.serialize_entry("feed", feed.as_str())?;
+        }
+        if let Some(date) = &self.date {
+            map.serialize_entry("date-from", &date.field)?;
+            map.serialize_entry("date-regex", date.regex.as_str())?;
+            map.serialize_entry("date-format", &date.format)?;
+            map.serialize_entry("date-xor", &date.xor)?;
+            map.serialize_entry("date-replace", &date.replace)?;
+            map.serialize_entry("date-required", &date.required)?;
+        }
+        if let Some((re, rep)) = &self.title {
+            map.serialize_entry("title-regex", re.as_str())?;
+            map.serialize_entry("title-replace", rep)?;
+        }
+        if let Some((re, rep)) = &self.link {
+            map.serialize_entry("link-regex", re.as_str())?;
+            map.serialize_entry("link-replace", rep)?;
+        }
+        if let Some(author) = &self.author_default {
+            map.serialize_entry("author-default", author)?;
+        }
+        map.end()
+    }
+}
+
+impl TryFrom<RawRewrite> for ConfigRewrite {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawRewrite) -> ah::Result<Self> {
+        let date = match (raw.date_from, raw.date_regex, raw.date_format) {
+            (Some(field), Some(regex), Some(format)) => Some(ConfigRewriteDate {
+                field,
+                regex,
+                format,
+                xor: raw.date_xor,
+                replace: raw.date_replace,
+                required: raw.date_required,
+            }),
+            (None, None, None) => None,
+            _ => {
+                return Err(err!(
+                    "Rewrite rule: date-from, date-regex and date-format \
+                     must be specified together."
+                ));
+            }
+        };
+        let title = match (raw.title_regex, raw.title_replace) {
+            (Some(re), Some(rep)) => Some((re, rep)),
+            (None, None) => None,
+            _ => {
+                return Err(err!(
+                    "Rewrite rule: title-regex and title-replace \
+                     must be specified together."
+                ));
+            }
+        };
+        let link = match (raw.link_regex, raw.link_replace) {
+            (Some(re), Some(rep)) => Some((re, rep)),
+            (None, None) => None,
+            _ => {
+                return Err(err!(
+                    "Rewrite rule: link-regex and link-replace \
+                     must be specified together."
+                ));
+            }
+        };
+        Ok(Self {
+            name: raw.name,
+            feed: raw.feed,
+            date,
+            title,
+            link,
+            author_default: raw.author_default,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigDigest {
+    pub enabled: bool,
+    #[serde(
+        rename = "interval-secs",
+        deserialize_with = "de::nonzero_secs",
+        serialize_with = "ser::duration"
+    )]
+    pub interval: Duration,
+    #[serde(
+        deserialize_with = "de::opt_string",
+        serialize_with = "ser::opt_string"
+    )]
+    pub from: Option<String>,
+    #[serde(deserialize_with = "de::string_or_vec")]
+    pub to: Vec<String>,
+    pub subject: String,
+    pub sendmail: Vec<String>,
+    /// Write the digest to this Maildir instead of sending it via sendmail.
+    #[serde(deserialize_with = "de::opt_path", serialize_with = "ser::opt_path")]
+    pub maildir: Option<PathBuf>,
+    /// Maximum number of items in one digest.
+    pub max_items: NonZeroUsize,
+}
+
+impl Default for ConfigDigest {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(DAYS_TO_SECS),
+            from: None,
+            to: vec![],
+            subject: "feedreader digest".to_string(),
+            sendmail: DEFAULT_SENDMAIL.iter().map(|s| s.to_string()).collect(),
+            maildir: None,
+            max_items: NonZeroUsize::new(500).unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigUi {
+    pub per_item_seen: bool,
+    #[serde(
+        deserialize_with = "de::opt_nonzero",
+        serialize_with = "ser::opt_nonzero"
+    )]
+    pub items_per_page: Option<NonZeroUsize>,
+    pub server_sent_events: bool,
+    #[serde(deserialize_with = "de::opt_path", serialize_with = "ser::opt_path")]
+    pub template_dir: Option<PathBuf>,
+    #[serde(
+        deserialize_with = "de::opt_string",
+        serialize_with = "ser::opt_string"
+    )]
+    pub script_url: Option<String>,
+    #[serde(
+        deserialize_with = "de::opt_string",
+        serialize_with = "ser::opt_string"
+    )]
+    pub resource_url: Option<String>,
+    pub trust_forwarded_headers: bool,
+    /// The control socket of feedsd. The feedsctl default, if None.
+    #[serde(deserialize_with = "de::opt_path", serialize_with = "ser::opt_path")]
+    pub control_socket: Option<PathBuf>,
+}
+
+impl Default for ConfigUi {
+    fn default() -> Self {
+        Self {
+            per_item_seen: false,
+            items_per_page: NonZeroUsize::new(100),
+            server_sent_events: false,
+            template_dir: None,
+            script_url: None,
+            resource_url: None,
+            trust_forwarded_headers: false,
+            control_socket: None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+/// The resolved configuration.
+///
+/// It serializes to the configuration file format, including all defaults.
+/// The built-in rewrite rules are not serialized.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Config {
     pub net: ConfigNet,
     pub db: ConfigDb,
     pub no_highlighting: ConfigNoHighlighting,
+    #[serde(serialize_with = "serialize_rewrite")]
+    pub rewrite: Vec<ConfigRewrite>,
+    #[serde(rename = "rule")]
+    pub rules: Vec<ConfigRule>,
+    #[serde(rename = "notify-channel")]
+    pub notify_channels: Vec<ConfigNotifyChannel>,
+    #[serde(rename = "alert")]
+    pub alerts: Vec<ConfigAlert>,
+    #[serde(rename = "webhook")]
+    pub webhooks: Vec<ConfigWebhook>,
+    pub digest: ConfigDigest,
+    pub ui: ConfigUi,
+    /// The settings as written in the configuration file.
+    #[serde(skip)]
+    source: Table,
 }
 
-impl Config {
-    fn new() -> Self {
-        Default::default()
+/// Serialize the configured rewrite rules without the built-in rules.
+fn serialize_rewrite<S: Serializer>(rewrite: &[ConfigRewrite], s: S) -> Result<S::Ok, S::Error> {
+    let builtin = ConfigRewrite::builtin().len();
+    s.collect_seq(rewrite.iter().skip(builtin))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            net: Default::default(),
+            db: Default::default(),
+            no_highlighting: Default::default(),
+            rewrite: ConfigRewrite::builtin(),
+            rules: vec![],
+            notify_channels: vec![],
+            alerts: vec![],
+            webhooks: vec![],
+            digest: Default::default(),
+            ui: Default::default(),
+            source: Table::new(),
+        }
     }
+}
 
-    pub fn parse_default_file() -> ah::Result<Self> {
-        Self::parse_file(Path::new("/opt/feedreader/etc/feedreader/feedreader.conf"))
+impl Config {
+    /// Check whether a new item shall be highlighted.
+    pub fn should_highlight(&self, input: &RuleInput<'_>) -> bool {
+        self.no_highlighting.matching_rule(input).is_none()
     }
 
-    pub fn parse_file(path: &Path) -> ah::Result<Self> {
-        let s = if path.exists() {
-            std::fs::read_to_string(path).context("Read configuration file")?
+    /// Load the configuration file and merge its drop-in fragments.
+    ///
+    /// The fragments are the `*.conf` files in the [CONFIG_DROPIN_DIR] next to the
+    /// configuration file. They are merged in lexical order of their file names.
+    /// If `strict` is false, then a missing configuration file is treated as empty.
+    pub fn load(path: &Path, strict: bool) -> ah::Result<Self> {
+        let mut table = if path.exists() {
+            read_document(path)?
+        } else if strict {
+            return Err(err!(
+                "Configuration file '{}' does not exist.",
+                path.display()
+            ));
         } else {
-            "".to_string()
+            Table::new()
         };
-        Self::parse_str(&s)
+
+        let dropin_dir = path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(CONFIG_DROPIN_DIR);
+        if dropin_dir.is_dir() {
+            let mut fragments: Vec<PathBuf> = std::fs::read_dir(&dropin_dir)
+                .with_context(|| format!("Read directory '{}'", dropin_dir.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "conf"))
+                .collect();
+            fragments.sort();
+            for fragment in &fragments {
+                merge_table(&mut table, read_document(fragment)?);
+            }
+        }
+
+        Self::from_table(table).with_context(|| format!("Configuration file '{}'", path.display()))
     }
 
     pub fn parse_str(s: &str) -> ah::Result<Self> {
-        let table: Table = toml::from_str(s).context("Parse configuration file")?;
-        let mut config = Config::new();
+        Self::from_table(parse_document("<string>", s)?)
+    }
 
-        for (name, value) in &table {
-            if name == "net"
-                && let Value::Table(t) = value
-            {
-                for (name, value) in t {
-                    if name == "timeout-secs" {
-                        config.net.timeout = parse_duration_secs(name, value)?;
-                        continue;
-                    }
-                    if name == "concurrency" {
-                        config.net.concurrency = parse_nonzerousize(name, value)?;
-                        continue;
-                    }
-                    log::warn!("Ignoring configuration entry: {name} = {value:?}");
-                }
-                continue;
+    /// Build the configuration from the checked and merged documents.
+    fn from_table(table: Table) -> ah::Result<Self> {
+        let raw: RawConfig = Value::Table(table.clone()).try_into()?;
+
+        let mut config = Self {
+            net: raw.net,
+            db: raw.db,
+            no_highlighting: raw.no_highlighting,
+            rewrite: raw.rewrite,
+            rules: raw.rule,
+            notify_channels: raw.notify_channel,
+            alerts: raw.alert,
+            webhooks: raw.webhook,
+            digest: raw.digest,
+            ui: raw.ui,
+            source: table,
+        };
+        default_names(&mut config.no_highlighting.feeds, "feed", |f| &mut f.name);
+        default_names(&mut config.rewrite, "rewrite", |r| &mut r.name);
+        default_names(&mut config.rules, "rule", |r| &mut r.name);
+        default_names(&mut config.notify_channels, "channel", |c| &mut c.name);
+        default_names(&mut config.alerts, "alert", |a| &mut a.name);
+        default_names(&mut config.webhooks, "webhook", |w| &mut w.name);
+        config.rewrite.splice(0..0, ConfigRewrite::builtin());
+
+        if config.digest.enabled
+            && config.digest.maildir.is_none()
+            && (config.digest.to.is_empty() || config.digest.sendmail.is_empty())
+        {
+            return Err(err!(
+                "Configuration section 'digest' needs either 'to' or 'maildir'."
+            ));
+        }
+        notify::check_alert_channels(&config.notify_channels, &config.alerts, &config.webhooks)?;
+        Ok(config)
+    }
+
+    /// Get the settings that differ between this and the `new` configuration.
+    ///
+    /// Returns one `key: old -> new` line per changed setting.
+    /// The values of the webhook URLs and HTTP headers and of the notification
+    /// and sendmail commands may contain secrets. They are replaced by `(redacted)`.
+    pub fn diff(&self, new: &Config) -> Vec<String> {
+        let mut old_settings = BTreeMap::new();
+        let mut new_settings = BTreeMap::new();
+        flatten_settings("", &self.source, &mut old_settings);
+        flatten_settings("", &new.source, &mut new_settings);
+
+        let keys: BTreeSet<&String> = old_settings.keys().chain(new_settings.keys()).collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let old = old_settings.get(key);
+                let new = new_settings.get(key);
+                (old != new).then(|| {
+                    let show = |v: Option<&String>| match v {
+                        None => "(default)".to_string(),
+                        Some(_) if is_secret_setting(key) => "(redacted)".to_string(),
+                        Some(v) => v.clone(),
+                    };
+                    format!("{key}: {} -> {}", show(old), show(new))
+                })
+            })
+            .collect()
+    }
+}
+
+/// The configuration as written in the configuration files.
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct RawConfig {
+    /// Evaluated by [parse_document] before the document is deserialized.
+    #[serde(rename = "allow-unknown-keys")]
+    _allow_unknown_keys: bool,
+    net: ConfigNet,
+    db: ConfigDb,
+    no_highlighting: ConfigNoHighlighting,
+    rewrite: Vec<ConfigRewrite>,
+    rule: Vec<ConfigRule>,
+    notify_channel: Vec<ConfigNotifyChannel>,
+    alert: Vec<ConfigAlert>,
+    webhook: Vec<ConfigWebhook>,
+    digest: ConfigDigest,
+    ui: ConfigUi,
+}
+
+/// Name the unnamed entries after their index.
+fn default_names<T>(entries: &mut [T], prefix: &str, name: impl Fn(&mut T) -> &mut String) {
+    for (index, entry) in entries.iter_mut().enumerate() {
+        let name = name(entry);
+        if name.is_empty() {
+            *name = format!("{prefix}-{index}");
+        }
+    }
+}
+
+/// Format the start of the `span` in the `text` as `name:line:column`.
+fn location(name: &str, text: &str, span: &Range<usize>) -> String {
+    let before = text.get(..span.start).unwrap_or(text);
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    format!("{name}:{line}:{column}")
+}
+
+/// Remove the key at the `span` from the document.
+/// Returns the name of the removed key.
+fn remove_key(table: &mut DeTable<'_>, span: &Range<usize>) -> Option<String> {
+    if let Some(key) = table.keys().find(|k| k.span() == *span).cloned() {
+        table.remove(&key);
+        return Some(key.into_inner().into_owned());
+    }
+    table.iter_mut().find_map(|(_, v)| match v.get_mut() {
+        DeValue::Table(t) => remove_key(t, span),
+        DeValue::Array(a) => a.iter_mut().find_map(|v| match v.get_mut() {
+            DeValue::Table(t) => remove_key(t, span),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// Parse and check one configuration document.
+///
+/// The errors cite the `name` of the document, the line and the column.
+/// Unknown keys are errors, unless the document sets [ALLOW_UNKNOWN_KEYS].
+/// Then they are logged and removed from the returned table.
+fn parse_document(name: &str, text: &str) -> ah::Result<Table> {
+    let located = |e: toml::de::Error| match e.span() {
+        Some(span) => err!("{}: {}", location(name, text, &span), e.message().trim()),
+        None => err!("{name}: {}", e.message().trim()),
+    };
+
+    let mut doc = DeTable::parse(text).map_err(located)?;
+    let allow_unknown_keys = doc.get_ref().iter().any(|(k, v)| {
+        k.get_ref() == ALLOW_UNKNOWN_KEYS && matches!(v.get_ref(), DeValue::Boolean(true))
+    });
+
+    loop {
+        match RawConfig::deserialize(toml::Deserializer::from(doc.clone())) {
+            Ok(_) => break,
+            Err(e) if allow_unknown_keys && e.message().starts_with("unknown field") => {
+                let Some(span) = e.span() else {
+                    return Err(located(e));
+                };
+                let Some(key) = remove_key(doc.get_mut(), &span) else {
+                    return Err(located(e));
+                };
+                log::warn!(
+                    "{}: Ignoring unknown configuration key '{key}'.",
+                    location(name, text, &span)
+                );
             }
+            Err(e) => return Err(located(e)),
+        }
+    }
+    Table::deserialize(toml::Deserializer::from(doc)).map_err(located)
+}
+
+/// Read and check a configuration file.
+fn read_document(path: &Path) -> ah::Result<Table> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Read configuration file '{}'", path.display()))?;
+    parse_document(&path.display().to_string(), &text)
+}
 
-            if name == "db"
-                && let Value::Table(t) = value
+/// Merge the `fragment` into the `base` table.
+///
+/// Tables are merged recursively and arrays of tables, such as `[[rule]]`,
+/// are appended. All other values of the fragment replace the base values.
+fn merge_table(base: &mut Table, fragment: Table) {
+    for (name, value) in fragment {
+        match (base.get_mut(&name), value) {
+            (Some(Value::Table(b)), Value::Table(f)) => merge_table(b, f),
+            (Some(Value::Array(b)), Value::Array(f))
+                if b.iter().chain(f.iter()).all(Value::is_table) =>
             {
-                for (name, value) in t {
-                    if name == "refresh-interval-secs" {
-                        config.db.refresh_interval = parse_duration_secs(name, value)?;
-                        continue;
-                    }
-                    if name == "refresh-slack" {
-                        config.db.refresh_slack = parse_f64(name, value)?;
-                        continue;
-                    }
-                    if name == "gc-age-offset-days" {
-                        config.db.gc_age_offset = parse_duration_days(name, value)?;
-                        continue;
-                    }
-                    if name == "highlight-updated-items" {
-                        config.db.highlight_updated_items = parse_bool(name, value)?;
-                        continue;
-                    }
-                    log::warn!("Ignoring configuration entry: {name} = {value:?}");
-                }
-                continue;
+                b.extend(f);
             }
+            (_, value) => {
+                base.insert(name, value);
+            }
+        }
+    }
+}
 
-            if name == "no-highlighting"
-                && let Value::Table(t) = value
-            {
-                for (name, value) in t {
-                    if name == "title" {
-                        config.no_highlighting.title = parse_regex_array(name, value)?;
-                        //TODO print them to log
-                        continue;
-                    }
-                    if name == "summary" {
-                        config.no_highlighting.summary = parse_regex_array(name, value)?;
-                        continue;
-                    }
-                    if name == "url" {
-                        config.no_highlighting.url = parse_regex_array(name, value)?;
-                        continue;
-                    }
-                    if name == "set-seen" {
-                        config.no_highlighting.set_seen = parse_bool(name, value)?;
-                        continue;
+/// Check whether the value of the flattened setting `key` may contain secrets.
+fn is_secret_setting(key: &str) -> bool {
+    const SECRET_SETTINGS: &[&str] = &[
+        "webhook[*].url",
+        "webhook[*].headers",
+        "notify-channel[*].url",
+        "notify-channel[*].headers",
+        "notify-channel[*].command",
+        "notify-channel[*].sendmail",
+        "digest.sendmail",
+    ];
+    // Match all entries of arrays of tables.
+    let key = key
+        .split('.')
+        .map(|name| match name.split_once('[') {
+            Some((name, _)) => format!("{name}[*]"),
+            None => name.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+    SECRET_SETTINGS
+        .iter()
+        .any(|s| key == *s || key.strip_prefix(s).is_some_and(|k| k.starts_with('.')))
+}
+
+/// Flatten the `table` into dotted keys and their values.
+///
+/// Arrays of tables, such as `[[rule]]`, are indexed as `rule[0]`.
+fn flatten_settings(prefix: &str, table: &Table, out: &mut BTreeMap<String, String>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{prefix}.{name}")
+        };
+        match value {
+            Value::Table(t) => flatten_settings(&key, t, out),
+            Value::Array(a) if !a.is_empty() && a.iter().all(Value::is_table) => {
+                for (i, v) in a.iter().enumerate() {
+                    if let Value::Table(t) = v {
+                        flatten_settings(&format!("{key}[{i}]"), t, out);
                     }
-                    log::warn!("Ignoring configuration entry: {name} = {value:?}");
                 }
-                continue;
             }
+            v => {
+                out.insert(key, v.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            log::warn!("Ignoring configuration entry: {name} = {value:?}");
+    fn input<'a>(
+        feed_id: i64,
+        feed_href: &'a str,
+        title: &'a str,
+        summary: &'a str,
+        url: &'a str,
+    ) -> RuleInput<'a> {
+        RuleInput {
+            title,
+            summary,
+            url,
+            author: "",
+            feed_id: Some(feed_id),
+            feed_title: "",
+            feed_href,
+            categories: &[],
         }
-        Ok(config)
+    }
+
+    #[test]
+    fn test_no_highlighting_feed() {
+        let config = Config::parse_str(
+            r#"
+            [no-highlighting]
+            url = [ '/shorts/' ]
+
+            [[no-highlighting.feed]]
+            name = 'channel'
+            href = 'youtube\.com/.*channel_id=abc'
+            title = [ '(?i)\btrailer\b' ]
+
+            [[no-highlighting.feed]]
+            feed-id = 42
+            summary = [ 'sponsored' ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.no_highlighting.feeds.len(), 2);
+
+        let channel = "https://www.youtube.com/feeds/videos.xml?channel_id=abc";
+        let other = "https://news.example.com/feed";
+        let blog = "https://blog.example.com/feed";
+        let nh = &config.no_highlighting;
+
+        let trailer = input(1, channel, "New Trailer", "s", "https://youtube.com/watch");
+        assert_eq!(
+            nh.matching_rule(&trailer).as_deref(),
+            Some(r"channel/title/(?i)\btrailer\b")
+        );
+        assert!(!config.should_highlight(&trailer));
+        let trailer = input(2, other, "New Trailer", "s", "https://news.example.com/1");
+        assert_eq!(nh.matching_rule(&trailer), None);
+        assert!(config.should_highlight(&trailer));
+
+        let sponsored = input(
+            42,
+            blog,
+            "Post",
+            "This is sponsored",
+            "https://blog.example.com/1",
+        );
+        assert_eq!(
+            nh.matching_rule(&sponsored).as_deref(),
+            Some("feed-1/summary/sponsored")
+        );
+        let sponsored = input(
+            2,
+            other,
+            "Post",
+            "This is sponsored",
+            "https://blog.example.com/1",
+        );
+        assert_eq!(nh.matching_rule(&sponsored), None);
+
+        // The global rules apply to all feeds.
+        let short = input(2, other, "Video", "s", "https://www.youtube.com/shorts/1");
+        assert_eq!(
+            nh.matching_rule(&short).as_deref(),
+            Some("global/url//shorts/")
+        );
+
+        // Either href or feed-id is required.
+        assert!(Config::parse_str("[[no-highlighting.feed]]\ntitle = [ 'x' ]\n").is_err());
+    }
+
+    #[test]
+    fn test_alerts() {
+        let config = Config::parse_str(
+            r#"
+            [[notify-channel]]
+            name = 'hook'
+            type = 'webhook'
+            url = 'http://127.0.0.1:8080/hook'
+            rate-limit = 3
+            rate-limit-window-secs = 60
+
+            [[notify-channel]]
+            name = 'mail'
+            type = 'sendmail'
+            to = 'team@example.com'
+
+            [[alert]]
+            name = 'product'
+            keywords = [ 'Feedreader', 'feeds-rs' ]
+            channels = [ 'hook' ]
+
+            [[alert]]
+            name = 'release'
+            feed = 'github\.com'
+            condition = { field = 'title', regex = '^v\d+' }
+            "#,
+        )
+        .unwrap();
+
+        let hook = &config.notify_channels[0];
+        assert_eq!(
+            hook.kind,
+            NotifyKind::Webhook {
+                url: "http://127.0.0.1:8080/hook".to_string()
+            }
+        );
+        assert_eq!(hook.rate_limit, 3);
+        assert_eq!(hook.rate_window, Duration::from_secs(60));
+        let mail = &config.notify_channels[1];
+        assert_eq!(
+            mail.kind,
+            NotifyKind::Sendmail {
+                sendmail: DEFAULT_SENDMAIL.iter().map(|s| s.to_string()).collect(),
+                from: None,
+                to: vec!["team@example.com".to_string()],
+            }
+        );
+        assert_eq!(mail.rate_limit, 10);
+
+        let product = &config.alerts[0];
+        assert_eq!(product.channels, ["hook"]);
+        let feed = "https://news.example.com/feed";
+        assert!(product.matches(&input(1, feed, "About feedreader", "", "")));
+        assert!(product.matches(&input(1, feed, "News", "We use feeds-rs.", "")));
+        assert!(!product.matches(&input(1, feed, "feedreaders", "", "")));
+
+        let release = &config.alerts[1];
+        assert!(release.channels.is_empty());
+        let github = "https://github.com/mbuesch/feedreaders/releases.atom";
+        assert!(release.matches(&input(1, github, "v1.2", "", "")));
+        assert!(!release.matches(&input(1, feed, "v1.2", "", "")));
+
+        // Unknown channel.
+        assert!(
+            Config::parse_str("[[alert]]\nkeywords = [ 'x' ]\nchannels = [ 'nope' ]\n").is_err()
+        );
+        // Incomplete channels.
+        assert!(Config::parse_str("[[notify-channel]]\ntype = 'webhook'\n").is_err());
+        assert!(Config::parse_str("[[notify-channel]]\ntype = 'sendmail'\n").is_err());
+        assert!(Config::parse_str("[[notify-channel]]\ntype = 'pager'\n").is_err());
+        // Alert without condition.
+        assert!(Config::parse_str("[[alert]]\nname = 'x'\n").is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("feedscfg-load-{}", std::process::id()));
+        let dropin = dir.join(CONFIG_DROPIN_DIR);
+        std::fs::create_dir_all(&dropin).unwrap();
+        let path = dir.join("feedreader.conf");
+
+        // A missing file is only an error in strict mode.
+        assert!(Config::load(&path, true).is_err());
+        let config = Config::load(&path, false).unwrap();
+        assert_eq!(
+            config.db.refresh_interval,
+            ConfigDb::default().refresh_interval
+        );
+
+        std::fs::write(
+            &path,
+            "[db]\nrefresh-interval-secs = 3600\ngc-age-offset-days = 10\n\n\
+             [[rule]]\nname = 'main'\ncondition = { field = 'title', regex = 'a' }\n\
+             actions = [ 'seen' ]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dropin.join("20-b.conf"),
+            "[db]\nrefresh-interval-secs = 60\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dropin.join("10-a.conf"),
+            "[db]\nrefresh-interval-secs = 600\n\n\
+             [[rule]]\nname = 'dropin'\ncondition = { field = 'title', regex = 'b' }\n\
+             actions = [ 'seen' ]\n",
+        )
+        .unwrap();
+        // Ignored: Not a .conf file.
+        std::fs::write(dropin.join("30-c.conf.orig"), "[db\n").unwrap();
+
+        let config = Config::load(&path, true).unwrap();
+        assert_eq!(config.db.refresh_interval, Duration::from_secs(60));
+        assert_eq!(
+            config.db.gc_age_offset,
+            Duration::from_secs(10 * DAYS_TO_SECS)
+        );
+        let names: Vec<&str> = config.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["main", "dropin"]);
+
+        // Errors name the broken file.
+        std::fs::write(dropin.join("40-d.conf"), "[db\n").unwrap();
+        let e = format!("{:#}", Config::load(&path, true).unwrap_err());
+        assert!(e.contains("40-d.conf"), "{e}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strict() {
+        // Errors cite the line and the column.
+        let e = Config::parse_str("[db]\nrefresh-intervall-secs = 60\n")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            e.starts_with("<string>:2:1: unknown field `refresh-intervall-secs`"),
+            "{e}"
+        );
+        let e = Config::parse_str("[[rule]]\nname = 'x'\ncondition = { field = 'title', regex = '(' }\nactions = [ 'seen' ]\n")
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("<string>:3:40: invalid regex"), "{e}");
+        let e = Config::parse_str("[db]\nrefresh-slack = 1.5\n")
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("<string>:2:17: invalid value"), "{e}");
+        assert!(e.contains("between 0 and 1"), "{e}");
+        let e = Config::parse_str("[net]\ntimeout-secs = '10 parsecs'\n")
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("<string>:2:16: invalid value"), "{e}");
+        assert!(Config::parse_str("[db]\nrefresh-interval-secs = 0\n").is_err());
+        assert!(Config::parse_str("[ui]\nitems-per-page = -1\n").is_err());
+        assert!(Config::parse_str("[[alert]]\nkeywords = [ 'x' ]\ncolor = 'red'\n").is_err());
+        assert!(
+            Config::parse_str(
+                "[[rule]]\ncondition = { field = 'title', regex = 'x', flags = 'i' }\n\
+                 actions = [ 'seen' ]\n"
+            )
+            .is_err()
+        );
+
+        // Unknown keys can be allowed.
+        let config = Config::parse_str(
+            r#"
+            allow-unknown-keys = true
+            color = 'red'
+
+            [db]
+            refresh-intervall-secs = 60
+            refresh-slack = 0.5
+
+            [[rule]]
+            name = 'x'
+            condition = { field = 'title', regex = 'x', flags = 'i' }
+            actions = [ 'seen' ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.db.refresh_slack, 0.5);
+        assert_eq!(config.rules[0].name, "x");
+        assert!(!config.source.contains_key("color"));
+        // But they are still checked.
+        assert!(Config::parse_str("allow-unknown-keys = true\n[db]\nrefresh-slack = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_example_config() {
+        Config::parse_str(include_str!("../../feedreader.conf")).unwrap();
+    }
+
+    #[test]
+    fn test_durations() {
+        let config = Config::parse_str(
+            r#"
+            [net]
+            timeout-secs = "30s"
+
+            [db]
+            refresh-interval-secs = "1h30m"
+            gc-age-offset-days = "2w"
+
+            [digest]
+            interval-secs = 3600
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.net.timeout, Duration::from_secs(30));
+        assert_eq!(config.db.refresh_interval, Duration::from_secs(5400));
+        assert_eq!(
+            config.db.gc_age_offset,
+            Duration::from_secs(14 * DAYS_TO_SECS)
+        );
+        assert_eq!(config.digest.interval, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let config = Config::parse_str(
+            r#"
+            [db]
+            refresh-interval-secs = 5400
+
+            [no-highlighting]
+            title = [ '(?i)\btrailer\b' ]
+
+            [[no-highlighting.feed]]
+            feed-id = 42
+            summary = [ 'sponsored' ]
+
+            [[rewrite]]
+            date-from = "title"
+            date-regex = '\((\d+)\)$'
+            date-format = "unix"
+            link-regex = '^http://'
+            link-replace = 'https://'
+
+            [[rule]]
+            condition = { not = { any = [ { field = 'title', keyword = 'Rust' } ] } }
+            actions = [ 'seen', 'tag:other' ]
+
+            [[notify-channel]]
+            name = 'mail'
+            type = 'sendmail'
+            to = 'me@example.com'
+
+            [[alert]]
+            keywords = [ 'feedreader' ]
+            channels = [ 'mail' ]
+
+            [[webhook]]
+            url = 'http://127.0.0.1/hook'
+            headers = { Authorization = 'Bearer x' }
+            "#,
+        )
+        .unwrap();
+        let toml = toml::to_string(&config).unwrap();
+        assert!(toml.contains("refresh-interval-secs = \"1h30m\""), "{toml}");
+        assert!(toml.contains("gc-age-offset-days = \"180d\""), "{toml}");
+        assert!(toml.contains("name = \"rule-0\""), "{toml}");
+        assert!(!toml.contains("builtin-fefe"), "{toml}");
+
+        // The serialized configuration is a valid configuration file.
+        let reparsed = Config::parse_str(&toml).unwrap();
+        assert_eq!(toml::to_string(&reparsed).unwrap(), toml);
+        assert_eq!(reparsed.rewrite.len(), config.rewrite.len());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Config::parse_str(
+            r#"
+            [db]
+            refresh-interval-secs = 3600
+            gc-age-offset-days = 180
+
+            [[rule]]
+            name = 'a'
+            condition = { field = 'title', regex = 'x' }
+            actions = [ 'seen' ]
+            "#,
+        )
+        .unwrap();
+        let new = Config::parse_str(
+            r#"
+            [db]
+            refresh-interval-secs = 600
+
+            [[rule]]
+            name = 'a'
+            condition = { field = 'title', regex = 'y' }
+            actions = [ 'seen' ]
+
+            [ui]
+            per-item-seen = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            old.diff(&new),
+            [
+                "db.gc-age-offset-days: 180 -> (default)",
+                "db.refresh-interval-secs: 3600 -> 600",
+                "rule[0].condition.regex: \"x\" -> \"y\"",
+                "ui.per-item-seen: (default) -> true",
+            ]
+        );
+        assert!(old.diff(&old).is_empty());
+
+        let new = Config::parse_str(
+            r#"
+            [db]
+            refresh-interval-secs = 3600
+            gc-age-offset-days = 180
+
+            [[rule]]
+            name = 'a'
+            condition = { field = 'title', regex = 'x' }
+            actions = [ 'seen' ]
+
+            [[notify-channel]]
+            name = 'script'
+            type = 'command'
+            command = [ '/bin/alert', '--token=secret' ]
+
+            [[webhook]]
+            url = 'https://chat.example.com/hooks/secret'
+            headers = { Authorization = 'Bearer secret' }
+
+            [digest]
+            sendmail = [ '/usr/sbin/sendmail', '-t' ]
+            "#,
+        )
+        .unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            [
+                "digest.sendmail: (default) -> (redacted)",
+                "notify-channel[0].command: (default) -> (redacted)",
+                "notify-channel[0].name: (default) -> \"script\"",
+                "notify-channel[0].type: (default) -> \"command\"",
+                "webhook[0].headers.Authorization: (default) -> (redacted)",
+                "webhook[0].url: (default) -> (redacted)",
+            ]
+        );
+        assert!(!diff.iter().any(|d| d.contains("secret")));
+
+        // Other URL patterns are shown.
+        let new = Config::parse_str(
+            r#"
+            [db]
+            refresh-interval-secs = 3600
+            gc-age-offset-days = 180
+
+            [[rule]]
+            name = 'a'
+            condition = { field = 'title', regex = 'x' }
+            actions = [ 'seen' ]
+
+            [no-highlighting]
+            url = [ '/shorts/' ]
+
+            [[no-highlighting.feed]]
+            href = 'example'
+            url = [ '/ads/' ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            old.diff(&new),
+            [
+                "no-highlighting.feed[0].href: (default) -> \"example\"",
+                "no-highlighting.feed[0].url: (default) -> [\"/ads/\"]",
+                "no-highlighting.url: (default) -> [\"/shorts/\"]",
+            ]
+        );
     }
 }
 
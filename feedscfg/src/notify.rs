@@ -0,0 +1,376 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{
+    de,
+    rules::{RuleCondition, RuleField, RuleInput, keyword_regex},
+    ser,
+};
+use anyhow::{self as ah, format_err as err};
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeMap as _};
+use std::{collections::BTreeMap, num::NonZeroUsize, time::Duration};
+
+/// The default sendmail command. The recipients are taken from the mail headers.
+pub const DEFAULT_SENDMAIL: &[&str] = &["/usr/sbin/sendmail", "-t", "-i"];
+
+/// How a notification is delivered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyKind {
+    /// Run a local command with the item fields in environment variables.
+    Command { command: Vec<String> },
+    /// POST a JSON document to an URL.
+    ///
+    /// Like all notification channels, this is sent once, right away and rate limited.
+    /// It is not stored in the outbox and not retried. See [ConfigWebhook] for that.
+    Webhook { url: String },
+    /// Send a mail via the local sendmail binary.
+    Sendmail {
+        sendmail: Vec<String>,
+        from: Option<String>,
+        to: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawNotifyChannel")]
+pub struct ConfigNotifyChannel {
+    pub name: String,
+    pub kind: NotifyKind,
+    /// Maximum number of notifications per `rate_window`. Unlimited, if 0.
+    pub rate_limit: usize,
+    pub rate_window: Duration,
+}
+
+impl Serialize for ConfigNotifyChannel {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(None)?;
+        map.serialize_entry("name", &self.name)?;
+        match &self.kind {
+            NotifyKind::Command { command } => {
+                map.serialize_entry("type", "command")?;
+                map.serialize_entry("command", command)?;
+            }
+            NotifyKind::Webhook { url } => {
+                map.serialize_entry("type", "webhook")?;
+                map.serialize_entry("url", url)?;
+            }
+            NotifyKind::Sendmail { sendmail, from, to } => {
+                map.serialize_entry("type", "sendmail")?;
+                map.serialize_entry("sendmail", sendmail)?;
+                if let Some(from) = from {
+                    map.serialize_entry("from", from)?;
+                }
+                map.serialize_entry("to", to)?;
+            }
+        }
+        map.serialize_entry("rate-limit", &self.rate_limit)?;
+        map.serialize_entry(
+            "rate-limit-window-secs",
+            &ser::format_duration(self.rate_window),
+        )?;
+        map.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ChannelType {
+    Command,
+    Webhook,
+    Sendmail,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawNotifyChannel {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: ChannelType,
+    command: Option<Vec<String>>,
+    url: Option<String>,
+    sendmail: Option<Vec<String>>,
+    from: Option<String>,
+    #[serde(default, deserialize_with = "de::string_or_vec")]
+    to: Vec<String>,
+    #[serde(default = "default_rate_limit")]
+    rate_limit: usize,
+    #[serde(
+        rename = "rate-limit-window-secs",
+        default = "default_rate_window",
+        deserialize_with = "de::nonzero_secs"
+    )]
+    rate_window: Duration,
+}
+
+fn default_rate_limit() -> usize {
+    10
+}
+
+fn default_rate_window() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+impl TryFrom<RawNotifyChannel> for ConfigNotifyChannel {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawNotifyChannel) -> ah::Result<Self> {
+        let kind = match raw.kind {
+            ChannelType::Command => {
+                let Some(command) = raw.command.filter(|c| !c.is_empty()) else {
+                    return Err(err!("Notification channel without 'command'."));
+                };
+                NotifyKind::Command { command }
+            }
+            ChannelType::Webhook => {
+                let Some(url) = raw.url.filter(|u| !u.trim().is_empty()) else {
+                    return Err(err!("Notification channel without 'url'."));
+                };
+                NotifyKind::Webhook { url }
+            }
+            ChannelType::Sendmail => {
+                if raw.to.is_empty() {
+                    return Err(err!("Notification channel without 'to' address."));
+                }
+                let sendmail = raw
+                    .sendmail
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| DEFAULT_SENDMAIL.iter().map(|s| s.to_string()).collect());
+                NotifyKind::Sendmail {
+                    sendmail,
+                    from: raw.from,
+                    to: raw.to,
+                }
+            }
+        };
+        Ok(Self {
+            name: raw.name,
+            kind,
+            rate_limit: raw.rate_limit,
+            rate_window: raw.rate_window,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawAlert")]
+pub struct ConfigAlert {
+    pub name: String,
+    /// The alert only applies to feeds with a matching URL. All feeds, if None.
+    #[serde(
+        serialize_with = "ser::opt_regex",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub feed: Option<Regex>,
+    pub condition: RuleCondition,
+    /// The names of the notification channels. All channels, if empty.
+    pub channels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawAlert {
+    #[serde(default)]
+    name: String,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    feed: Option<Regex>,
+    condition: Option<RuleCondition>,
+    keywords: Option<Vec<String>>,
+    #[serde(default)]
+    channels: Vec<String>,
+}
+
+impl TryFrom<RawAlert> for ConfigAlert {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawAlert) -> ah::Result<Self> {
+        let mut conditions: Vec<RuleCondition> = raw.condition.into_iter().collect();
+        if let Some(keywords) = raw.keywords {
+            // A keyword in the title or in the summary.
+            let mut any = vec![];
+            for keyword in keywords {
+                let re = keyword_regex(&keyword)?;
+                any.push(RuleCondition::Match(RuleField::Title, re.clone()));
+                any.push(RuleCondition::Match(RuleField::Summary, re));
+            }
+            conditions.push(RuleCondition::Any(any));
+        }
+        let condition = match conditions.len() {
+            0 => return Err(err!("Alert without 'keywords' and without 'condition'.")),
+            1 => conditions.pop().unwrap(),
+            _ => RuleCondition::All(conditions),
+        };
+        Ok(Self {
+            name: raw.name,
+            feed: raw.feed,
+            condition,
+            channels: raw.channels,
+        })
+    }
+}
+
+impl ConfigAlert {
+    /// Check whether the alert matches an item of the feed.
+    pub fn matches(&self, input: &RuleInput<'_>) -> bool {
+        if let Some(feed) = &self.feed
+            && !feed.is_match(input.feed_href)
+        {
+            return false;
+        }
+        self.condition.matches(input)
+    }
+}
+
+/// A webhook that receives all new highlighted items.
+///
+/// The requests are stored in the outbox and retried until they are delivered.
+/// In contrast to the alert notification channel of type webhook,
+/// it is not rate limited and its items are batched per refresh round.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawWebhook", rename_all = "kebab-case")]
+pub struct ConfigWebhook {
+    pub name: String,
+    pub url: String,
+    /// Additional HTTP request headers.
+    #[serde(serialize_with = "serialize_headers")]
+    pub headers: Vec<(String, String)>,
+    /// Template of the JSON payload. The plain item list, if None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// The webhook only receives items of feeds with a matching URL. All feeds, if None.
+    #[serde(
+        serialize_with = "ser::opt_regex",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub feed: Option<Regex>,
+    /// Maximum number of items per request.
+    pub max_items: NonZeroUsize,
+    /// Give up after this number of failed delivery attempts.
+    pub max_attempts: usize,
+    /// The delay before the first retry. It is doubled with each retry.
+    #[serde(rename = "backoff-secs", serialize_with = "ser::duration")]
+    pub backoff: Duration,
+}
+
+fn serialize_headers<S: Serializer>(headers: &[(String, String)], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_map(headers.iter().map(|(k, v)| (k, v)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawWebhook {
+    #[serde(default)]
+    name: String,
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    template: Option<String>,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    feed: Option<Regex>,
+    #[serde(default = "default_webhook_max_items")]
+    max_items: NonZeroUsize,
+    #[serde(default = "default_webhook_max_attempts")]
+    max_attempts: usize,
+    #[serde(
+        rename = "backoff-secs",
+        default = "default_webhook_backoff",
+        deserialize_with = "de::secs"
+    )]
+    backoff: Duration,
+}
+
+fn default_webhook_max_items() -> NonZeroUsize {
+    NonZeroUsize::new(50).unwrap()
+}
+
+fn default_webhook_max_attempts() -> usize {
+    8
+}
+
+fn default_webhook_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl TryFrom<RawWebhook> for ConfigWebhook {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawWebhook) -> ah::Result<Self> {
+        if raw.url.trim().is_empty() {
+            return Err(err!("Webhook without 'url'."));
+        }
+        if raw.max_attempts == 0 {
+            return Err(err!("Webhook 'max-attempts' must not be 0."));
+        }
+        if let Some(template) = &raw.template {
+            minijinja::Environment::new()
+                .template_from_str(template)
+                .map_err(|e| err!("Webhook has an invalid template: {e}"))?;
+        }
+        Ok(Self {
+            name: raw.name,
+            url: raw.url,
+            headers: raw.headers.into_iter().collect(),
+            template: raw.template,
+            feed: raw.feed,
+            max_items: raw.max_items,
+            max_attempts: raw.max_attempts,
+            backoff: raw.backoff,
+        })
+    }
+}
+
+impl ConfigWebhook {
+    /// Check whether the webhook receives items of the feed.
+    pub fn applies_to(&self, feed_href: &str) -> bool {
+        self.feed.as_ref().is_none_or(|f| f.is_match(feed_href))
+    }
+}
+
+/// Check that all channels referenced by the alerts exist
+/// and that the webhook names are unique.
+pub(crate) fn check_alert_channels(
+    channels: &[ConfigNotifyChannel],
+    alerts: &[ConfigAlert],
+    webhooks: &[ConfigWebhook],
+) -> ah::Result<()> {
+    for (i, a) in webhooks.iter().enumerate() {
+        if webhooks[..i].iter().any(|b| b.name == a.name) {
+            return Err(err!("Duplicate webhook '{}'.", a.name));
+        }
+    }
+    for (i, a) in channels.iter().enumerate() {
+        if channels[..i].iter().any(|b| b.name == a.name) {
+            return Err(err!("Duplicate notification channel '{}'.", a.name));
+        }
+    }
+    for alert in alerts {
+        for name in &alert.channels {
+            if !channels.iter().any(|c| &c.name == name) {
+                return Err(err!(
+                    "Alert '{}': Unknown notification channel '{name}'.",
+                    alert.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// vim: ts=4 sw=4 expandtab
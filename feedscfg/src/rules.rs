@@ -0,0 +1,327 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{de, ser};
+use anyhow::{self as ah, format_err as err};
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeMap as _};
+
+/// The item property that a rule condition is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleField {
+    Title,
+    Summary,
+    Url,
+    Author,
+    /// The feed title and the feed URL.
+    Feed,
+    /// The categories of the item.
+    Category,
+}
+
+/// The item properties that rule conditions are matched against.
+#[derive(Debug, Clone, Copy)]
+pub struct RuleInput<'a> {
+    pub title: &'a str,
+    pub summary: &'a str,
+    pub url: &'a str,
+    pub author: &'a str,
+    pub feed_id: Option<i64>,
+    pub feed_title: &'a str,
+    pub feed_href: &'a str,
+    pub categories: &'a [String],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawCondition")]
+pub enum RuleCondition {
+    /// The regex matches the field.
+    Match(RuleField, Regex),
+    /// All conditions match.
+    All(Vec<RuleCondition>),
+    /// At least one condition matches.
+    Any(Vec<RuleCondition>),
+    /// The condition does not match.
+    Not(Box<RuleCondition>),
+}
+
+impl RuleCondition {
+    pub fn matches(&self, input: &RuleInput<'_>) -> bool {
+        match self {
+            Self::Match(field, re) => match field {
+                RuleField::Title => re.is_match(input.title),
+                RuleField::Summary => re.is_match(input.summary),
+                RuleField::Url => re.is_match(input.url),
+                RuleField::Author => re.is_match(input.author),
+                RuleField::Feed => re.is_match(input.feed_title) || re.is_match(input.feed_href),
+                RuleField::Category => input.categories.iter().any(|c| re.is_match(c)),
+            },
+            Self::All(conds) => conds.iter().all(|c| c.matches(input)),
+            Self::Any(conds) => conds.iter().any(|c| c.matches(input)),
+            Self::Not(cond) => !cond.matches(input),
+        }
+    }
+}
+
+impl Serialize for RuleCondition {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(None)?;
+        match self {
+            Self::Match(field, re) => {
+                map.serialize_entry("field", field)?;
+                map.serialize_entry("regex", re.as_str())?;
+            }
+            Self::All(conds) => map.serialize_entry("all", conds)?,
+            Self::Any(conds) => map.serialize_entry("any", conds)?,
+            Self::Not(cond) => map.serialize_entry("not", cond)?,
+        }
+        map.end()
+    }
+}
+
+/// A rule condition as written in the configuration file.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawCondition {
+    all: Option<Vec<RuleCondition>>,
+    any: Option<Vec<RuleCondition>>,
+    not: Option<Box<RuleCondition>>,
+    field: Option<RuleField>,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    regex: Option<Regex>,
+    keyword: Option<String>,
+}
+
+impl TryFrom<RawCondition> for RuleCondition {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawCondition) -> ah::Result<Self> {
+        match raw {
+            RawCondition {
+                all: Some(all),
+                any: None,
+                not: None,
+                field: None,
+                regex: None,
+                keyword: None,
+            } => Ok(Self::All(all)),
+            RawCondition {
+                all: None,
+                any: Some(any),
+                not: None,
+                field: None,
+                regex: None,
+                keyword: None,
+            } => Ok(Self::Any(any)),
+            RawCondition {
+                all: None,
+                any: None,
+                not: Some(not),
+                field: None,
+                regex: None,
+                keyword: None,
+            } => Ok(Self::Not(not)),
+            RawCondition {
+                all: None,
+                any: None,
+                not: None,
+                field: Some(field),
+                regex,
+                keyword,
+            } => match (regex, keyword) {
+                (Some(re), None) => Ok(Self::Match(field, re)),
+                (None, Some(kw)) => Ok(Self::Match(field, keyword_regex(&kw)?)),
+                _ => Err(err!("Condition needs either 'regex' or 'keyword'.")),
+            },
+            RawCondition {
+                all: None,
+                any: None,
+                not: None,
+                field: None,
+                ..
+            } => Err(err!("Condition needs 'all', 'any', 'not' or 'field'.")),
+            _ => Err(err!(
+                "Condition needs exactly one of 'all', 'any', 'not' or 'field'."
+            )),
+        }
+    }
+}
+
+/// Case insensitive match of a whole word or phrase.
+pub(crate) fn keyword_regex(keyword: &str) -> ah::Result<Regex> {
+    let keyword = keyword.trim();
+    if keyword.is_empty() {
+        return Err(err!("Configuration entry 'keyword' is empty."));
+    }
+    // Word boundaries only work next to word characters.
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(keyword.chars().next()) {
+        r"\b"
+    } else {
+        ""
+    };
+    let end = if is_word(keyword.chars().next_back()) {
+        r"\b"
+    } else {
+        ""
+    };
+    Ok(Regex::new(&format!(
+        "(?i){start}{}{end}",
+        regex::escape(keyword)
+    ))?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum RuleAction {
+    /// Do not store the item.
+    Drop,
+    /// Mark the item as seen.
+    Seen,
+    /// Star the item.
+    Star,
+    /// Add a tag to the item.
+    Tag(String),
+    /// Highlight the item as important.
+    Important,
+    /// Send a notification about the item.
+    Notify,
+}
+
+impl std::fmt::Display for RuleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Drop => write!(f, "drop"),
+            Self::Seen => write!(f, "seen"),
+            Self::Star => write!(f, "star"),
+            Self::Tag(tag) => write!(f, "tag:{tag}"),
+            Self::Important => write!(f, "important"),
+            Self::Notify => write!(f, "notify"),
+        }
+    }
+}
+
+impl Serialize for RuleAction {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for RuleAction {
+    type Error = ah::Error;
+
+    fn try_from(action: String) -> ah::Result<Self> {
+        let action = action.trim();
+        if let Some(tag) = action.strip_prefix("tag:") {
+            let tag = tag.trim();
+            if tag.is_empty() || tag.contains(',') {
+                return Err(err!("Invalid tag name '{tag}'."));
+            }
+            return Ok(Self::Tag(tag.to_string()));
+        }
+        match action {
+            "drop" => Ok(Self::Drop),
+            "seen" => Ok(Self::Seen),
+            "star" => Ok(Self::Star),
+            "important" => Ok(Self::Important),
+            "notify" => Ok(Self::Notify),
+            _ => Err(err!(
+                "Unknown action '{action}'. \
+                 Valid actions: drop, seen, star, tag:NAME, important, notify."
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawRule")]
+pub struct ConfigRule {
+    pub name: String,
+    /// The rule only applies to feeds with a matching URL.
+    #[serde(
+        serialize_with = "ser::opt_regex",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub feed: Option<Regex>,
+    /// The rule only applies to the feed with this ID.
+    /// All feeds, if neither `feed` nor `feed_id` is given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feed_id: Option<i64>,
+    pub condition: RuleCondition,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawRule {
+    #[serde(default)]
+    name: String,
+    #[serde(default, deserialize_with = "de::opt_regex")]
+    feed: Option<Regex>,
+    feed_id: Option<i64>,
+    condition: RuleCondition,
+    #[serde(default)]
+    actions: Vec<RuleAction>,
+}
+
+impl TryFrom<RawRule> for ConfigRule {
+    type Error = ah::Error;
+
+    fn try_from(raw: RawRule) -> ah::Result<Self> {
+        if raw.actions.is_empty() {
+            return Err(err!("Rule without 'actions'."));
+        }
+        Ok(Self {
+            name: raw.name,
+            feed: raw.feed,
+            feed_id: raw.feed_id,
+            condition: raw.condition,
+            actions: raw.actions,
+        })
+    }
+}
+
+impl ConfigRule {
+    /// Check whether the rule matches an item of the feed.
+    pub fn matches(&self, input: &RuleInput<'_>) -> bool {
+        self.applies_to(input.feed_id, input.feed_href) && self.condition.matches(input)
+    }
+
+    /// Check whether the rule applies to the feed.
+    /// A feed is selected, if either the `feed` regex or the `feed_id` matches.
+    pub fn applies_to(&self, feed_id: Option<i64>, href: &str) -> bool {
+        if self.feed.is_none() && self.feed_id.is_none() {
+            return true;
+        }
+        if let Some(id) = self.feed_id
+            && feed_id == Some(id)
+        {
+            return true;
+        }
+        if let Some(re) = &self.feed
+            && re.is_match(href)
+        {
+            return true;
+        }
+        false
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,96 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Serialization helpers that write the values in the configuration file format.
+
+use crate::de::DAYS_TO_SECS;
+use regex::Regex;
+use serde::Serializer;
+use std::{num::NonZeroUsize, path::Path, time::Duration};
+
+/// Format a duration as e.g. `90s`, `10m`, `1h30m` or `180d`.
+pub fn format_duration(d: Duration) -> String {
+    let mut secs = d.as_secs();
+    if secs == 0 {
+        return "0s".to_string();
+    }
+    let mut s = String::new();
+    for (unit, factor) in [("d", DAYS_TO_SECS), ("h", 60 * 60), ("m", 60), ("s", 1)] {
+        if secs >= factor {
+            s.push_str(&format!("{}{unit}", secs / factor));
+            secs %= factor;
+        }
+    }
+    s
+}
+
+pub fn duration<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format_duration(*d))
+}
+
+/// None is written as 0.
+pub fn opt_nonzero<S: Serializer>(v: &Option<NonZeroUsize>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(v.map_or(0, |v| v.get() as u64))
+}
+
+/// None is written as the empty string.
+pub fn opt_string<S: Serializer>(v: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(v.as_deref().unwrap_or(""))
+}
+
+/// None is written as the empty string.
+pub fn opt_path<S: Serializer, P: AsRef<Path>>(v: &Option<P>, s: S) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(p) => s.collect_str(&p.as_ref().display()),
+        None => s.serialize_str(""),
+    }
+}
+
+pub fn opt_regex<S: Serializer>(re: &Option<Regex>, s: S) -> Result<S::Ok, S::Error> {
+    match re {
+        Some(re) => s.serialize_some(re.as_str()),
+        None => s.serialize_none(),
+    }
+}
+
+pub fn regex_vec<S: Serializer>(res: &[Regex], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(res.iter().map(Regex::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::parse_duration;
+
+    #[test]
+    fn test_format_duration() {
+        let fmt = |secs| format_duration(Duration::from_secs(secs));
+        assert_eq!(fmt(0), "0s");
+        assert_eq!(fmt(90), "1m30s");
+        assert_eq!(fmt(600), "10m");
+        assert_eq!(fmt(5400), "1h30m");
+        assert_eq!(fmt(180 * DAYS_TO_SECS), "180d");
+        assert_eq!(fmt(DAYS_TO_SECS + 1), "1d1s");
+        for secs in [0, 1, 59, 61, 3599, 86399, 86401, 999_999] {
+            assert_eq!(parse_duration(&fmt(secs)), Some(Duration::from_secs(secs)));
+        }
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -17,8 +17,13 @@
 //
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+pub mod config;
+pub mod daemon;
+pub mod digest;
+pub mod export;
 pub mod getkv;
 pub mod list;
+pub mod rules;
 pub mod seen;
 
 // vim: ts=4 sw=4 expandtab
@@ -0,0 +1,68 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{ConfigCommand, ConfigShowOpts};
+use anyhow::{self as ah, Context as _, format_err as err};
+use feedscfg::Config;
+use std::path::Path;
+
+fn command_config_check(path: &Path, strict: bool) -> ah::Result<()> {
+    if !path.exists() && !strict {
+        log::warn!(
+            "Configuration file '{}' does not exist. Using the defaults.",
+            path.display()
+        );
+    }
+    let config = Config::load(path, strict)?;
+    println!(
+        "{}: OK ({} rule(s), {} alert(s), {} notification channel(s), {} webhook(s))",
+        path.display(),
+        config.rules.len(),
+        config.alerts.len(),
+        config.notify_channels.len(),
+        config.webhooks.len(),
+    );
+    Ok(())
+}
+
+fn command_config_show(path: &Path, strict: bool, opts: &ConfigShowOpts) -> ah::Result<()> {
+    let config = Config::load(path, strict)?;
+    let doc = match opts.format.trim() {
+        "toml" => toml::to_string(&config).context("Serialize configuration to TOML")?,
+        "json" => {
+            serde_json::to_string_pretty(&config).context("Serialize configuration to JSON")?
+        }
+        f => {
+            return Err(err!(
+                "Unknown configuration format '{f}'. Use toml or json."
+            ));
+        }
+    };
+    println!("{}", doc.trim_end());
+    Ok(())
+}
+
+pub fn command_config(path: &Path, strict: bool, command: &ConfigCommand) -> ah::Result<()> {
+    match command {
+        ConfigCommand::Check => command_config_check(path, strict),
+        ConfigCommand::Show(opts) => command_config_show(path, strict, opts),
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,88 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{Daemon, DaemonOpts};
+use anyhow::{self as ah, format_err as err};
+use feedsctl::{DaemonStatus, RefreshOutcome, Request, Response, request};
+use std::time::Duration;
+
+fn print_status(status: &DaemonStatus) {
+    println!("feedsd {} (pid {})", status.version, status.pid);
+    println!("  started        = {}", status.started);
+    println!("  config-loaded  = {}", status.config_loaded);
+    if let Some(last_refresh) = status.last_refresh {
+        println!("  last-refresh   = {last_refresh}");
+    }
+    if let Some(next_due) = status.next_due {
+        println!("  next-due       = {next_due}");
+    }
+    println!("  in-flight      = {}", status.in_flight.len());
+    for feed in &status.in_flight {
+        println!("    {}: {}", feed.feed_id, feed.href);
+    }
+    println!("  errors         = {}", status.errors.len());
+    for e in &status.errors {
+        println!("    {}: {}", e.feed_id, e.href);
+        println!("      {}: {}", e.time, e.error);
+    }
+}
+
+fn print_refreshed(feeds: &[RefreshOutcome]) -> ah::Result<()> {
+    for feed in feeds {
+        match &feed.error {
+            None => println!("Feed {}: {} new item(s)", feed.feed_id, feed.new_items),
+            Some(e) => println!("Feed {}: Error: {e}", feed.feed_id),
+        }
+    }
+    let failed = feeds.iter().filter(|f| f.error.is_some()).count();
+    if failed > 0 {
+        return Err(err!("Failed to refresh {failed} feed(s)."));
+    }
+    Ok(())
+}
+
+pub async fn command_daemon(opts: &DaemonOpts) -> ah::Result<()> {
+    let req = match &opts.command {
+        Daemon::Status => Request::Status,
+        Daemon::Wakeup => Request::Wakeup,
+        Daemon::Refresh { all: true, .. } => Request::RefreshAll,
+        Daemon::Refresh { ids, .. } if !ids.is_empty() => Request::RefreshFeeds {
+            feed_ids: ids.clone(),
+        },
+        Daemon::Refresh { .. } => return Err(err!("Either feed IDs or --all is required.")),
+        Daemon::Reload => Request::Reload,
+        Daemon::Shutdown => Request::Shutdown,
+    };
+    let timeout = Duration::from_secs(opts.timeout);
+    let resp = request(&opts.socket, &req, timeout).await?;
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&resp)?);
+        return resp.into_result().map(|_| ());
+    }
+    match resp.into_result()? {
+        Response::Ok { message } => println!("{message}"),
+        Response::Status(status) => print_status(&status),
+        Response::Refreshed { feeds } => return print_refreshed(&feeds),
+        Response::Error { .. } => unreachable!(),
+    }
+    Ok(())
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,46 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::DigestOpts;
+use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::Utc;
+use feedscfg::Config;
+use feedsdb::Db;
+use feedsfmt::digest::{digest_mail, get_digest_items, send_digest};
+
+pub async fn command_digest(db: &Db, config: &Config, opts: &DigestOpts) -> ah::Result<()> {
+    let mut conn = db.open().await.context("Open database")?;
+    let now = Utc::now();
+
+    if opts.stdout {
+        // Only print the digest. The time of the last digest is not updated.
+        let digest = get_digest_items(&config.digest, &mut conn).await?;
+        let mail = digest_mail(&config.digest, &digest, now);
+        print!("{}", mail.render().replace("\r\n", "\n"));
+    } else {
+        if config.digest.maildir.is_none() && config.digest.to.is_empty() {
+            return Err(err!("[digest]: Neither 'maildir' nor 'to' is configured."));
+        }
+        let count = send_digest(&config.digest, &mut conn, now, config.net.timeout).await?;
+        println!("Sent digest with {count} item(s).");
+    }
+    Ok(())
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,64 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::ExportFeedOpts;
+use anyhow::{self as ah, Context as _};
+use chrono::Utc;
+use feedsdb::{Db, ItemFilter};
+use feedsfmt::export::{
+    ExportFormat, ExportMeta, export, export_query, export_title, get_export_items,
+};
+
+pub async fn command_export_feed(db: &Db, opts: &ExportFeedOpts) -> ah::Result<()> {
+    let mut conn = db.open().await.context("Open database")?;
+
+    let format: ExportFormat = opts.format.parse()?;
+    let filter = ItemFilter {
+        feed_ids: opts.feed.clone(),
+        starred: opts.starred,
+        tag: opts.tag.clone(),
+        search: opts.search.clone(),
+        limit: Some(opts.limit).filter(|l| *l > 0),
+        ..Default::default()
+    };
+
+    let items = get_export_items(&mut conn, &filter)
+        .await
+        .context("Database: Get items")?;
+
+    let base_url = opts.base_url.trim_end_matches('/');
+    let meta = ExportMeta {
+        title: export_title(&filter, &items),
+        self_url: format!("{base_url}/export?{}", export_query(format, &filter)),
+        home_url: base_url.to_string(),
+        generated: Utc::now(),
+    };
+    let doc = export(format, &meta, &items)?;
+
+    if let Some(output) = &opts.output {
+        std::fs::write(output, doc.as_bytes())
+            .with_context(|| format!("Write '{}'", output.display()))?;
+    } else {
+        println!("{doc}");
+    }
+
+    Ok(())
+}
+
+// vim: ts=4 sw=4 expandtab
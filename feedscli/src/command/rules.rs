@@ -0,0 +1,368 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::RulesTestOpts;
+use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::Utc;
+use feedscfg::{Config, RuleInput};
+use feedsdb::{Db, Feed, ItemFilter};
+use feedsfmt::extract::{ExtractedItem, USER_AGENT, extract_items, feed_title, parse_feed};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// An item to test the rules against.
+struct TestItem {
+    feed_id: Option<i64>,
+    feed_title: String,
+    feed_href: String,
+    title: String,
+    summary: String,
+    url: String,
+    author: String,
+    categories: Vec<String>,
+}
+
+impl TestItem {
+    fn input(&self) -> RuleInput<'_> {
+        RuleInput {
+            title: &self.title,
+            summary: &self.summary,
+            url: &self.url,
+            author: &self.author,
+            feed_id: self.feed_id,
+            feed_title: &self.feed_title,
+            feed_href: &self.feed_href,
+            categories: &self.categories,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct RuleMatch {
+    rule: String,
+    actions: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct TestResult {
+    feed_id: Option<i64>,
+    feed_title: String,
+    title: String,
+    url: String,
+    highlight: bool,
+    /// The no-highlighting rule that suppresses the highlighting.
+    suppressed_by: Option<String>,
+    /// The matching `[[rule]]` entries.
+    rules: Vec<RuleMatch>,
+}
+
+impl TestResult {
+    fn matched(&self) -> bool {
+        self.suppressed_by.is_some() || !self.rules.is_empty()
+    }
+}
+
+async fn get_db_items(db: &Db, opts: &RulesTestOpts) -> ah::Result<Vec<TestItem>> {
+    let mut conn = db.open().await.context("Open database")?;
+    let (feeds, _) = conn.get_feeds(None).await.context("Database: Get feeds")?;
+    let feeds: HashMap<i64, Feed> = feeds
+        .into_iter()
+        .filter_map(|f| f.feed_id.map(|id| (id, f)))
+        .collect();
+    let filter = ItemFilter {
+        feed_ids: opts.feed.clone(),
+        limit: Some(opts.limit).filter(|l| *l > 0),
+        ..Default::default()
+    };
+    let items = conn
+        .get_items_filtered(&filter)
+        .await
+        .context("Database: Get items")?;
+    Ok(items
+        .into_iter()
+        .map(|i| {
+            let feed = i.feed_id.and_then(|id| feeds.get(&id));
+            TestItem {
+                feed_id: i.feed_id,
+                feed_title: feed.map(|f| f.title.clone()).unwrap_or_default(),
+                feed_href: feed.map(|f| f.href.clone()).unwrap_or_default(),
+                title: i.title,
+                summary: i.summary,
+                url: i.link,
+                author: i.author,
+                // The categories of the entry are stored as tags.
+                categories: i.tags,
+            }
+        })
+        .collect())
+}
+
+/// Extract the items of the feed document `feed_bytes` like feedsd does.
+fn feed_items(
+    config: &Config,
+    href: &str,
+    feed_bytes: &[u8],
+    limit: usize,
+) -> ah::Result<Vec<TestItem>> {
+    let parsed_feed =
+        parse_feed(href, feed_bytes).map_err(|e| err!("Failed to parse feed '{href}': {e}"))?;
+    let feed_title = feed_title(&parsed_feed).unwrap_or_default();
+
+    Ok(extract_items(config, href, &parsed_feed, Utc::now())
+        .into_iter()
+        .map(|ExtractedItem { item, categories }| TestItem {
+            feed_id: None,
+            feed_title: feed_title.to_string(),
+            feed_href: href.to_string(),
+            title: item.title,
+            summary: item.summary,
+            url: item.link,
+            author: item.author,
+            categories,
+        })
+        .take(if limit == 0 { usize::MAX } else { limit })
+        .collect())
+}
+
+async fn get_url_items(
+    db: &Db,
+    config: &Config,
+    href: &str,
+    limit: usize,
+) -> ah::Result<Vec<TestItem>> {
+    use reqwest::Client;
+
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .referer(false)
+        .timeout(config.net.timeout)
+        .build()
+        .context("Retrieve feed")?;
+    let resp = client.get(href).send().await.context("Retrieve feed")?;
+    if !resp.status().is_success() {
+        return Err(err!("Feed fetch error: {}", resp.status()));
+    }
+    let bytes = resp.bytes().await.context("Retrieve feed")?;
+
+    let mut items = feed_items(config, href, &bytes, limit)?;
+
+    // Rules with 'feed-id' apply, if the feed is in the database.
+    let mut conn = db.open().await.context("Open database")?;
+    let (feeds, _) = conn.get_feeds(None).await.context("Database: Get feeds")?;
+    if let Some(feed) = feeds.iter().find(|f| f.href == href) {
+        for item in &mut items {
+            item.feed_id = feed.feed_id;
+        }
+    }
+    Ok(items)
+}
+
+fn test_item(config: &Config, item: &TestItem) -> TestResult {
+    let input = item.input();
+    let suppressed_by = config.no_highlighting.matching_rule(&input);
+    let rules: Vec<RuleMatch> = config
+        .rules
+        .iter()
+        .filter(|r| r.matches(&input))
+        .map(|r| RuleMatch {
+            rule: r.name.clone(),
+            actions: r.actions.iter().map(|a| a.to_string()).collect(),
+        })
+        .collect();
+    TestResult {
+        feed_id: item.feed_id,
+        feed_title: item.feed_title.clone(),
+        title: item.title.clone(),
+        url: item.url.clone(),
+        highlight: config.should_highlight(&input),
+        suppressed_by,
+        rules,
+    }
+}
+
+pub async fn command_rules_test(db: &Db, config: &Config, opts: &RulesTestOpts) -> ah::Result<()> {
+    let items = if let Some(url) = &opts.url {
+        get_url_items(db, config, url, opts.limit).await?
+    } else {
+        get_db_items(db, opts).await?
+    };
+
+    let results: Vec<TestResult> = items
+        .iter()
+        .map(|i| test_item(config, i))
+        .filter(|r| opts.all || r.matched())
+        .collect();
+
+    if opts.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&results).context("Serialize results")?
+        );
+        return Ok(());
+    }
+
+    for r in &results {
+        let feed = match r.feed_id {
+            Some(id) => format!("{} [{id}]", r.feed_title),
+            None => r.feed_title.clone(),
+        };
+        println!("{feed}: {}", r.title);
+        if !r.url.is_empty() {
+            println!("    url: {}", r.url);
+        }
+        match &r.suppressed_by {
+            Some(rule) => println!("    no-highlighting: {rule}"),
+            None if r.highlight => println!("    highlighted"),
+            None => (),
+        }
+        for m in &r.rules {
+            println!("    rule '{}': {}", m.rule, m.actions.join(", "));
+        }
+    }
+    let matched = results.iter().filter(|r| r.matched()).count();
+    println!("{matched} of {} items matched a rule.", items.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::{Item, test_util};
+
+    const HREF: &str = "https://example.com/feed.xml";
+
+    const FEED: &[u8] = br#"<rss version="2.0"><channel><title>Example</title>
+        <item><title>[Ad] Rust released</title><link>https://example.com/1</link>
+            <category>Programming</category></item>
+        <item><title>Weather</title><link>https://example.com/2</link></item>
+        <item><title>Rust trailer</title><link>https://example.com/3</link></item>
+        </channel></rss>"#;
+
+    const CONFIG: &str = r#"
+        [no-highlighting]
+        title = [ '(?i)trailer' ]
+
+        [[rewrite]]
+        title-regex = '^\[Ad\]\s*'
+        title-replace = ''
+
+        [[rule]]
+        name = "rust"
+        condition = { field = "title", keyword = "rust" }
+        actions = [ "star", "tag:rust" ]
+
+        [[rule]]
+        name = "programming"
+        feed-id = 1
+        condition = { field = "category", regex = 'Programming' }
+        actions = [ "important" ]
+    "#;
+
+    fn opts() -> RulesTestOpts {
+        RulesTestOpts {
+            url: None,
+            feed: vec![],
+            limit: 0,
+            all: false,
+            json: false,
+        }
+    }
+
+    fn item(feed_item_id: &str, title: &str, tags: &[&str]) -> Item {
+        Item {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..test_util::item(feed_item_id, title, "")
+        }
+    }
+
+    #[test]
+    fn test_feed_items() {
+        let config = Config::parse_str(CONFIG).unwrap();
+        let items = feed_items(&config, HREF, FEED, 0).unwrap();
+        assert_eq!(items.len(), 3);
+        // The rewrite rules are applied like in feedsd.
+        assert_eq!(items[0].title, "Rust released");
+        assert_eq!(items[0].categories, ["Programming"]);
+        assert_eq!(items[0].feed_title, "Example");
+        assert_eq!(items[0].feed_href, HREF);
+
+        let results: Vec<TestResult> = items.iter().map(|i| test_item(&config, i)).collect();
+        assert_eq!(results[0].rules.len(), 1);
+        assert_eq!(results[0].rules[0].rule, "rust");
+        assert_eq!(results[0].rules[0].actions, ["star", "tag:rust"]);
+        assert!(results[0].highlight);
+        assert!(!results[1].matched());
+        assert_eq!(
+            results[2].suppressed_by.as_deref(),
+            Some("global/title/(?i)trailer")
+        );
+        assert!(!results[2].highlight);
+
+        assert_eq!(feed_items(&config, HREF, FEED, 2).unwrap().len(), 2);
+        assert!(feed_items(&config, HREF, b"<html>", 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_db_items() {
+        let db = Db::new_memory("test_rules_db_items").await.unwrap();
+        let config = Config::parse_str(CONFIG).unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed(HREF).await.unwrap();
+        conn.add_feed("https://other.org/feed").await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        for (i, mut feed) in feeds.into_iter().enumerate() {
+            feed.title = format!("Feed {i}");
+            let mut item = item(&format!("item-{i}"), "Rust news", &["Programming"]);
+            item.item_id = Some(item.make_id().await);
+            conn.update_feed(&feed, &[item], None, false).await.unwrap();
+        }
+
+        let items = get_db_items(&db, &opts()).await.unwrap();
+        assert_eq!(items.len(), 2);
+        let item = items.iter().find(|i| i.feed_id == Some(1)).unwrap();
+        assert_eq!(item.feed_href, HREF);
+        assert_eq!(item.categories, ["Programming"]);
+
+        // The feed-id rule only matches the items of feed 1.
+        for item in &items {
+            let result = test_item(&config, item);
+            let rules: Vec<&str> = result.rules.iter().map(|r| r.rule.as_str()).collect();
+            if item.feed_id == Some(1) {
+                assert_eq!(rules, ["rust", "programming"]);
+            } else {
+                assert_eq!(rules, ["rust"]);
+            }
+        }
+
+        let mut o = opts();
+        o.feed = vec![2];
+        let items = get_db_items(&db, &o).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].feed_href, "https://other.org/feed");
+
+        let mut o = opts();
+        o.limit = 1;
+        assert_eq!(get_db_items(&db, &o).await.unwrap().len(), 1);
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
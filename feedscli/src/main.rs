@@ -21,15 +21,30 @@
 
 mod command;
 
-use crate::command::{getkv::command_getkv, list::command_list, seen::command_seen};
+use crate::command::{
+    config::command_config, daemon::command_daemon, digest::command_digest,
+    export::command_export_feed, getkv::command_getkv, list::command_list,
+    rules::command_rules_test, seen::command_seen,
+};
 use anyhow::{self as ah, Context as _};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use feedscfg::Config;
 use feedsdb::Db;
-use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc, time::Duration};
 use tokio::runtime;
 
 #[derive(Parser, Debug, Clone)]
 struct Opts {
+    /// The configuration file.
+    ///
+    /// The `*.conf` files in the `conf.d` directory next to it are merged in lexical order.
+    #[arg(long, global = true, env = feedscfg::CONFIG_FILE_ENV, default_value = feedscfg::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+
+    /// Fail, if the configuration file does not exist.
+    #[arg(long, global = true, env = feedscfg::CONFIG_STRICT_ENV)]
+    strict_config: bool,
+
     /// The name of the database to use.
     #[arg(long, default_value = "feeds")]
     db: String,
@@ -63,6 +78,162 @@ enum Command {
     /// Get a value from the key-value-store.
     #[command(subcommand)]
     GetKv(GetKv),
+
+    /// Export items from the database as Atom, RSS or JSON Feed.
+    ExportFeed(ExportFeedOpts),
+
+    /// Test the highlighting and filter rules.
+    #[command(subcommand)]
+    Rules(Rules),
+
+    /// Send the digest of all unseen items since the last digest.
+    Digest(DigestOpts),
+
+    /// Control the running feedsd daemon.
+    Daemon(DaemonOpts),
+
+    /// Check or show the configuration.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommand {
+    /// Parse and validate the configuration file and its drop-in fragments.
+    ///
+    /// Exits with an error and the location of the problem, if the configuration is invalid.
+    Check,
+
+    /// Print the resolved configuration, including all defaults.
+    Show(ConfigShowOpts),
+}
+
+#[derive(Args, Debug, Clone)]
+struct ConfigShowOpts {
+    /// The output format: toml or json.
+    #[arg(long, default_value = "toml")]
+    format: String,
+}
+
+#[derive(Args, Debug, Clone)]
+struct DaemonOpts {
+    /// The path of the feedsd control socket.
+    #[arg(long, default_value = feedsctl::DEFAULT_SOCKET)]
+    socket: PathBuf,
+
+    /// Timeout in seconds for the response of feedsd.
+    #[arg(long, default_value = "120")]
+    timeout: u64,
+
+    /// Print the response as JSON.
+    #[arg(long)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Daemon,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Daemon {
+    /// Show the refresh status of the daemon.
+    Status,
+
+    /// Refresh the feeds that are due.
+    Wakeup,
+
+    /// Refresh feeds now, whether they are due or not.
+    Refresh {
+        /// The feed IDs to refresh. The command waits for the refresh to finish.
+        #[arg(conflicts_with = "all")]
+        ids: Vec<i64>,
+
+        /// Refresh all enabled feeds in the background.
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Reload the configuration file.
+    Reload,
+
+    /// Terminate the daemon.
+    Shutdown,
+}
+
+#[derive(Args, Debug, Clone)]
+struct DigestOpts {
+    /// Print the digest mail to stdout instead of sending it.
+    /// The time of the last digest is not updated.
+    #[arg(long)]
+    stdout: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Rules {
+    /// Run the rules of a configuration file over existing items
+    /// and print which items match which rule.
+    Test(RulesTestOpts),
+}
+
+#[derive(Args, Debug, Clone)]
+struct RulesTestOpts {
+    /// Fetch the items from this feed URL instead of the database.
+    #[arg(long)]
+    url: Option<String>,
+
+    /// Only test items from these feed IDs.
+    #[arg(long, value_delimiter = ',')]
+    feed: Vec<i64>,
+
+    /// The maximum number of items to test. 0 means unlimited.
+    #[arg(long, default_value = "1000")]
+    limit: usize,
+
+    /// Also print items that do not match any rule.
+    #[arg(long)]
+    all: bool,
+
+    /// Print the results as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+struct ExportFeedOpts {
+    /// The feed format: atom, rss or json.
+    #[arg(long, default_value = "atom")]
+    format: String,
+
+    /// Only export starred items.
+    #[arg(long)]
+    starred: bool,
+
+    /// Only export items from these feed IDs.
+    #[arg(long, value_delimiter = ',')]
+    feed: Vec<i64>,
+
+    /// Only export items with this tag.
+    ///
+    /// The tags of an item are the categories of the feed entry
+    /// and the tags added by the `tag:` action of a `[[rule]]`.
+    #[arg(long)]
+    tag: Option<String>,
+
+    /// Only export items that contain this text in the title or summary.
+    #[arg(long)]
+    search: Option<String>,
+
+    /// The maximum number of items. 0 means unlimited.
+    #[arg(long, default_value = "100")]
+    limit: usize,
+
+    /// The public URL of the feedreader CGI script.
+    /// This is used for the links in the generated feed.
+    #[arg(long, default_value = "http://localhost/cgi-bin/feeds")]
+    base_url: String,
+
+    /// Write the feed to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug, Clone, Copy)]
@@ -73,12 +244,29 @@ enum GetKv {
 async fn async_main(opts: Opts) -> ah::Result<()> {
     let opts = Arc::new(opts);
 
+    // The daemon and config commands do not access the database.
+    match &opts.command {
+        Command::Daemon(o) => return command_daemon(o).await,
+        Command::Config(c) => return command_config(&opts.config, opts.strict_config, c),
+        _ => (),
+    }
+
     let db = Db::new(&opts.db).await.context("Database")?;
 
     match &opts.command {
         Command::List => command_list(&db).await,
         Command::Seen { id } => command_seen(&db, id).await,
         Command::GetKv(kv) => command_getkv(&db, kv).await,
+        Command::ExportFeed(o) => command_export_feed(&db, o).await,
+        Command::Rules(Rules::Test(o)) => {
+            let config = Config::load(&opts.config, opts.strict_config)?;
+            command_rules_test(&db, &config, o).await
+        }
+        Command::Digest(o) => {
+            let config = Config::load(&opts.config, opts.strict_config)?;
+            command_digest(&db, &config, o).await
+        }
+        Command::Daemon(_) | Command::Config(_) => unreachable!(),
     }
 }
 
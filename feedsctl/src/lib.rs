@@ -0,0 +1,282 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Control protocol of the `feedsd` daemon.
+//!
+//! The daemon listens on a Unix domain socket.
+//! A client sends one request as a single line of JSON
+//! and receives one response as a single line of JSON.
+
+#![forbid(unsafe_code)]
+
+use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{path::Path, time::Duration};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt as _, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::UnixStream,
+};
+
+/// The default path of the control socket.
+pub const DEFAULT_SOCKET: &str = "/run/feedsd/feedsd.sock";
+
+/// The maximum length of one message line.
+const MAX_MESSAGE_LEN: u64 = 1024 * 1024;
+
+/// A request to the daemon.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    /// Refresh the feeds that are due.
+    /// The response is sent immediately.
+    Wakeup,
+    /// Refresh all enabled feeds, whether they are due or not.
+    /// The response is sent immediately.
+    RefreshAll,
+    /// Refresh the feeds, whether they are due or not.
+    /// The response is sent after the refresh has finished.
+    RefreshFeeds { feed_ids: Vec<i64> },
+    /// Get the daemon status.
+    Status,
+    /// Reload the configuration file.
+    Reload,
+    /// Terminate the daemon.
+    Shutdown,
+}
+
+/// The result of a requested feed refresh.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RefreshOutcome {
+    pub feed_id: i64,
+    /// The number of new highlighted items.
+    pub new_items: usize,
+    /// The error message, if the refresh failed.
+    pub error: Option<String>,
+}
+
+impl RefreshOutcome {
+    pub fn error(feed_id: i64, error: impl Into<String>) -> Self {
+        Self {
+            feed_id,
+            new_items: 0,
+            error: Some(error.into()),
+        }
+    }
+}
+
+/// A feed that is being refreshed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FeedRef {
+    pub feed_id: i64,
+    pub href: String,
+}
+
+/// The last refresh error of a feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FeedError {
+    pub feed_id: i64,
+    pub href: String,
+    pub time: DateTime<Utc>,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub version: String,
+    pub started: DateTime<Utc>,
+    /// The time the configuration has been loaded.
+    pub config_loaded: DateTime<Utc>,
+    /// The end of the last refresh round.
+    pub last_refresh: Option<DateTime<Utc>>,
+    /// The time the next feed is due.
+    pub next_due: Option<DateTime<Utc>>,
+    /// The feeds that are being refreshed right now.
+    pub in_flight: Vec<FeedRef>,
+    /// The feeds whose last refresh failed.
+    pub errors: Vec<FeedError>,
+}
+
+/// A response of the daemon.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum Response {
+    Ok { message: String },
+    Status(Box<DaemonStatus>),
+    Refreshed { feeds: Vec<RefreshOutcome> },
+    Error { message: String },
+}
+
+impl Response {
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self::Ok {
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::Error {
+            message: message.into(),
+        }
+    }
+
+    /// Convert an error response into an error.
+    pub fn into_result(self) -> ah::Result<Self> {
+        match self {
+            Self::Error { message } => Err(err!("feedsd: {message}")),
+            r => Ok(r),
+        }
+    }
+}
+
+/// Read one message line.
+///
+/// Returns None, if the peer closed the connection.
+pub async fn read_message<T, R>(r: &mut R) -> ah::Result<Option<T>>
+where
+    T: DeserializeOwned,
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    let len = r
+        .take(MAX_MESSAGE_LEN)
+        .read_line(&mut line)
+        .await
+        .context("Read control message")?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') {
+        return Err(err!("Control message is truncated or too long."));
+    }
+    let msg = serde_json::from_str(&line).context("Parse control message")?;
+    Ok(Some(msg))
+}
+
+/// Write one message line.
+pub async fn write_message<T, W>(w: &mut W, msg: &T) -> ah::Result<()>
+where
+    T: Serialize,
+    W: AsyncWrite + Unpin,
+{
+    let mut line = serde_json::to_string(msg).context("Serialize control message")?;
+    line.push('\n');
+    w.write_all(line.as_bytes())
+        .await
+        .context("Write control message")?;
+    w.flush().await.context("Write control message")
+}
+
+/// Send a request to the daemon listening on `socket` and wait for the response.
+pub async fn request(socket: &Path, req: &Request, timeout: Duration) -> ah::Result<Response> {
+    let fut = async {
+        let stream = UnixStream::connect(socket)
+            .await
+            .with_context(|| format!("Connect to feedsd socket '{}'", socket.display()))?;
+        let (r, mut w) = stream.into_split();
+        write_message(&mut w, req).await?;
+        let mut r = tokio::io::BufReader::new(r);
+        read_message(&mut r)
+            .await?
+            .ok_or_else(|| err!("feedsd closed the connection without response."))
+    };
+    tokio::time::timeout(timeout, fut)
+        .await
+        .map_err(|_| err!("Timeout waiting for feedsd."))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{io::BufReader, net::UnixListener};
+
+    #[test]
+    fn test_format() {
+        let req = Request::RefreshFeeds {
+            feed_ids: vec![42, 43],
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert_eq!(json, r#"{"request":"refresh-feeds","feed_ids":[42,43]}"#);
+        assert_eq!(serde_json::from_str::<Request>(&json).unwrap(), req);
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"request":"refresh-all"}"#).unwrap(),
+            Request::RefreshAll
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"request":"reboot"}"#).is_err());
+
+        let resp = Response::Refreshed {
+            feeds: vec![RefreshOutcome::error(42, "HTTP 404")],
+        };
+        let json = serde_json::to_string(&resp).unwrap();
+        assert_eq!(
+            json,
+            r#"{"response":"refreshed","feeds":[{"feed_id":42,"new_items":0,"error":"HTTP 404"}]}"#
+        );
+
+        let json = serde_json::to_string(&Response::error("nope")).unwrap();
+        assert_eq!(json, r#"{"response":"error","message":"nope"}"#);
+        assert!(Response::error("nope").into_result().is_err());
+        assert!(Response::ok("").into_result().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_request() {
+        let dir = std::env::temp_dir().join(format!("feedsctl-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("feedsd.sock");
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (r, mut w) = stream.into_split();
+            let mut r = BufReader::new(r);
+            let req: Request = read_message(&mut r).await.unwrap().unwrap();
+            let resp = Response::ok(format!("{req:?}"));
+            write_message(&mut w, &resp).await.unwrap();
+        });
+
+        let resp = request(&socket, &Request::Status, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(resp, Response::ok("Status"));
+        server.await.unwrap();
+
+        // Nobody is listening anymore.
+        std::fs::remove_file(&socket).unwrap();
+        assert!(
+            request(&socket, &Request::Status, Duration::from_secs(10))
+                .await
+                .is_err()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_too_long() {
+        let data = format!("{}\n", "x".repeat(MAX_MESSAGE_LEN as usize + 1));
+        let mut r = BufReader::new(data.as_bytes());
+        assert!(read_message::<Request, _>(&mut r).await.is_err());
+        let mut r = BufReader::new(&b""[..]);
+        assert!(read_message::<Request, _>(&mut r).await.unwrap().is_none());
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,202 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{
+    daemon::{Daemon, Wakeup},
+    refresh::refresh_feeds_now,
+};
+use anyhow::{self as ah, Context as _};
+use feedsctl::{Request, Response, read_message, write_message};
+use std::{
+    os::unix::fs::{FileTypeExt as _, PermissionsExt as _},
+    path::Path,
+    sync::Arc,
+};
+use tokio::{
+    io::BufReader,
+    net::{UnixListener, UnixStream},
+    task,
+};
+
+/// Handle one control request.
+async fn handle_request(daemon: &Daemon, req: Request) -> Response {
+    match req {
+        Request::Wakeup => {
+            daemon.wakeup(Wakeup::Due);
+            Response::ok("Refresh of due feeds triggered.")
+        }
+        Request::RefreshAll => {
+            daemon.wakeup(Wakeup::All);
+            Response::ok("Refresh of all feeds triggered.")
+        }
+        Request::RefreshFeeds { feed_ids } => match refresh_feeds_now(daemon, &feed_ids).await {
+            Ok(feeds) => Response::Refreshed { feeds },
+            Err(e) => Response::error(format!("{e:#}")),
+        },
+        Request::Status => Response::Status(Box::new(daemon.status.get())),
+        Request::Reload => match daemon.reload_config() {
+            Ok(changes) if changes.is_empty() => {
+                Response::ok("Configuration reloaded. No settings changed.")
+            }
+            Ok(changes) => Response::ok(format!(
+                "Configuration reloaded. Changed settings:\n{}",
+                changes.join("\n")
+            )),
+            Err(e) => {
+                log::error!("Reload failed. Keeping the current configuration: {e:?}");
+                Response::error(format!("{e:#}"))
+            }
+        },
+        Request::Shutdown => {
+            log::info!("Control socket: Terminating.");
+            daemon.exit(Ok(())).await;
+            Response::ok("Shutting down.")
+        }
+    }
+}
+
+/// Handle all requests of one connection.
+async fn handle_connection(daemon: Arc<Daemon>, stream: UnixStream) -> ah::Result<()> {
+    let (r, mut w) = stream.into_split();
+    let mut r = BufReader::new(r);
+    loop {
+        let resp = match read_message::<Request, _>(&mut r).await {
+            Ok(None) => break,
+            Ok(Some(req)) => {
+                log::debug!("Control request: {req:?}");
+                handle_request(&daemon, req).await
+            }
+            Err(e) => Response::error(format!("{e:#}")),
+        };
+        let is_error = matches!(resp, Response::Error { .. });
+        write_message(&mut w, &resp).await?;
+        if is_error {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Create the control socket.
+///
+/// A stale socket of a previous daemon instance is removed.
+pub fn bind_control_socket(path: &Path) -> ah::Result<UnixListener> {
+    if let Ok(meta) = std::fs::symlink_metadata(path)
+        && meta.file_type().is_socket()
+    {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Remove stale socket '{}'", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Create control socket '{}'", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))
+        .context("Set control socket permissions")?;
+    Ok(listener)
+}
+
+/// Accept connections on the control socket.
+pub async fn serve_control_socket(daemon: Arc<Daemon>, listener: UnixListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                task::spawn({
+                    let daemon = Arc::clone(&daemon);
+                    async move {
+                        if let Err(e) = handle_connection(daemon, stream).await {
+                            log::warn!("Control socket: {e:?}");
+                        }
+                    }
+                });
+            }
+            Err(e) => {
+                log::error!("Control socket: Accept failed: {e:?}");
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::ConfigFile;
+    use feedscfg::Config;
+    use feedsctl::RefreshOutcome;
+    use feedsdb::Db;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_control_socket() {
+        let db = Db::new_memory("test_control_socket").await.unwrap();
+        db.open().await.unwrap().init().await.unwrap();
+        let (exit_tx, mut exit_rx) = mpsc::channel(1);
+        let daemon = Arc::new(Daemon::new(
+            Arc::new(db),
+            Config::default(),
+            ConfigFile {
+                path: "/nonexistent/feedreader.conf".into(),
+                strict: false,
+            },
+            exit_tx,
+        ));
+
+        let dir = std::env::temp_dir().join(format!("feedsd-control-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("feedsd.sock");
+        let listener = bind_control_socket(&path).unwrap();
+        task::spawn(serve_control_socket(Arc::clone(&daemon), listener));
+        let request = async |req| {
+            feedsctl::request(&path, &req, Duration::from_secs(10))
+                .await
+                .unwrap()
+        };
+
+        let resp = request(Request::RefreshAll).await;
+        assert!(matches!(resp, Response::Ok { .. }));
+        assert_eq!(daemon.wait_wakeup().await, Wakeup::All);
+
+        let Response::Status(status) = request(Request::Status).await else {
+            panic!("Not a status response");
+        };
+        assert_eq!(status.pid, std::process::id());
+        assert!(status.in_flight.is_empty());
+
+        let resp = request(Request::RefreshFeeds {
+            feed_ids: vec![1234],
+        })
+        .await;
+        assert_eq!(
+            resp,
+            Response::Refreshed {
+                feeds: vec![RefreshOutcome::error(1234, "The feed does not exist.")]
+            }
+        );
+
+        let resp = request(Request::Shutdown).await;
+        assert!(matches!(resp, Response::Ok { .. }));
+        assert!(exit_rx.recv().await.unwrap().is_ok());
+
+        // A new daemon replaces the stale socket.
+        drop(bind_control_socket(&path).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
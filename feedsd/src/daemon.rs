@@ -0,0 +1,221 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{
+    metrics::Metrics,
+    notify::Notifier,
+    status::Status,
+    systemd::{systemd_notify_ready, systemd_notify_reloading},
+};
+use anyhow as ah;
+use chrono::Utc;
+use feedscfg::Config;
+use feedsdb::Db;
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+use tokio::sync::{Mutex, Notify, mpsc, watch};
+
+/// Which feeds the refresher shall refresh when woken up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Wakeup {
+    /// The feeds that are due.
+    Due = 1,
+    /// All enabled feeds.
+    All = 2,
+}
+
+/// No wakeup is pending.
+const WAKEUP_NONE: u8 = 0;
+
+/// The location of the configuration file.
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// Fail, if the file does not exist.
+    pub strict: bool,
+}
+
+/// The state shared between the tasks of the daemon.
+pub struct Daemon {
+    pub db: Arc<Db>,
+    /// The notifier keeps its rate limiting state over all refresh rounds.
+    pub notifier: Arc<Notifier>,
+    pub status: Arc<Status>,
+    pub metrics: Arc<Metrics>,
+    /// Held while the webhook outbox is delivered.
+    pub webhook_lock: Mutex<()>,
+    config: RwLock<Arc<Config>>,
+    config_file: ConfigFile,
+    /// The pending [Wakeup] or [WAKEUP_NONE].
+    wakeup_pending: AtomicU8,
+    wakeup_notify: Notify,
+    exit_tx: mpsc::Sender<ah::Result<()>>,
+    shutdown_tx: watch::Sender<bool>,
+}
+
+impl Daemon {
+    pub fn new(
+        db: Arc<Db>,
+        config: Config,
+        config_file: ConfigFile,
+        exit_tx: mpsc::Sender<ah::Result<()>>,
+    ) -> Self {
+        Self {
+            db,
+            notifier: Arc::new(Notifier::new()),
+            status: Arc::new(Status::new()),
+            metrics: Arc::new(Metrics::new()),
+            webhook_lock: Mutex::new(()),
+            config: RwLock::new(Arc::new(config)),
+            config_file,
+            wakeup_pending: AtomicU8::new(WAKEUP_NONE),
+            wakeup_notify: Notify::new(),
+            exit_tx,
+            shutdown_tx: watch::Sender::new(false),
+        }
+    }
+
+    /// Get the current configuration.
+    ///
+    /// A long running operation keeps using the configuration it started with.
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    /// Reload the configuration file.
+    ///
+    /// The new configuration is only used, if the file is fully valid.
+    /// Otherwise the current configuration is kept.
+    /// Returns the changed settings.
+    pub fn reload_config(&self) -> ah::Result<Vec<String>> {
+        if let Err(e) = systemd_notify_reloading() {
+            log::warn!("Notify systemd: {e:?}");
+        }
+        let result = self.do_reload_config();
+        if let Err(e) = systemd_notify_ready() {
+            log::warn!("Notify systemd: {e:?}");
+        }
+        result
+    }
+
+    fn do_reload_config(&self) -> ah::Result<Vec<String>> {
+        let new = Config::load(&self.config_file.path, self.config_file.strict)?;
+        let changes = self.config().diff(&new);
+        if changes.is_empty() {
+            log::info!("Configuration reloaded. No settings changed.");
+        } else {
+            log::info!("Configuration reloaded. Changed settings:");
+            for change in &changes {
+                log::info!("  {change}");
+            }
+        }
+        *self.config.write().unwrap() = Arc::new(new);
+        self.status.set_config_loaded(Utc::now());
+        Ok(changes)
+    }
+
+    /// Wake up the refresher.
+    ///
+    /// Wakeups that are not handled yet are merged.
+    /// [Wakeup::All] includes the due feeds, so it wins over [Wakeup::Due].
+    pub fn wakeup(&self, wakeup: Wakeup) {
+        self.wakeup_pending
+            .fetch_max(wakeup as u8, Ordering::AcqRel);
+        self.wakeup_notify.notify_one();
+    }
+
+    /// Wait for the next wakeup of the refresher.
+    pub async fn wait_wakeup(&self) -> Wakeup {
+        loop {
+            self.wakeup_notify.notified().await;
+            match self.wakeup_pending.swap(WAKEUP_NONE, Ordering::AcqRel) {
+                WAKEUP_NONE => (),
+                w if w == Wakeup::All as u8 => return Wakeup::All,
+                _ => return Wakeup::Due,
+            }
+        }
+    }
+
+    /// Start the shutdown of the daemon.
+    ///
+    /// No new feed refreshes are started after this.
+    pub fn begin_shutdown(&self) {
+        self.shutdown_tx.send_replace(true);
+    }
+
+    /// Get a receiver that changes to true, when the shutdown has begun.
+    pub fn shutdown_rx(&self) -> watch::Receiver<bool> {
+        self.shutdown_tx.subscribe()
+    }
+
+    /// Check whether the shutdown has begun.
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown_tx.borrow()
+    }
+
+    /// Terminate the daemon with the exit code.
+    pub async fn exit(&self, code: ah::Result<()>) {
+        let _ = self.exit_tx.send(code).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_wakeup() {
+        let db = Db::new_memory("test_wakeup").await.unwrap();
+        let (exit_tx, _exit_rx) = mpsc::channel(1);
+        let daemon = Daemon::new(
+            Arc::new(db),
+            Config::default(),
+            ConfigFile {
+                path: "/nonexistent/feedreader.conf".into(),
+                strict: false,
+            },
+            exit_tx,
+        );
+        let wait = async || {
+            timeout(Duration::from_millis(100), daemon.wait_wakeup())
+                .await
+                .ok()
+        };
+
+        daemon.wakeup(Wakeup::Due);
+        assert_eq!(wait().await, Some(Wakeup::Due));
+        assert_eq!(wait().await, None);
+
+        // Pending wakeups are merged and All wins.
+        daemon.wakeup(Wakeup::Due);
+        daemon.wakeup(Wakeup::All);
+        daemon.wakeup(Wakeup::Due);
+        assert_eq!(wait().await, Some(Wakeup::All));
+        assert_eq!(wait().await, None);
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,125 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow::{self as ah, Context as _};
+use chrono::{DateTime, Utc};
+use feedscfg::Config;
+use feedsdb::Db;
+use feedsfmt::digest::send_digest;
+use std::time::Duration;
+
+/// Calculate the time until the next digest is due.
+fn time_until_due(interval: Duration, last: DateTime<Utc>, now: DateTime<Utc>) -> Option<Duration> {
+    let elapsed = (now - last).to_std().unwrap_or_default();
+    interval.checked_sub(elapsed).filter(|d| !d.is_zero())
+}
+
+/// Send the digest, if it is due.
+///
+/// Returns the time until the next digest is due.
+pub async fn run_digest(config: &Config, db: &Db) -> ah::Result<Duration> {
+    let timeout = config.net.timeout;
+    let config = &config.digest;
+    let mut conn = db.open().await.context("Open database")?;
+    let now = Utc::now();
+    let Some(last) = conn.get_last_digest().await? else {
+        // First run. The first digest contains the items retrieved from now on.
+        conn.set_last_digest(now).await?;
+        return Ok(config.interval);
+    };
+    if let Some(remaining) = time_until_due(config.interval, last, now) {
+        return Ok(remaining);
+    }
+    let count = send_digest(config, &mut conn, now, timeout).await?;
+    log::info!("Digest: Sent {count} item(s).");
+    Ok(config.interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::{item, now};
+
+    #[test]
+    fn test_time_until_due() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let last = now();
+        assert_eq!(
+            time_until_due(day, last, last + Duration::from_secs(60 * 60)),
+            Some(Duration::from_secs(23 * 60 * 60))
+        );
+        assert_eq!(time_until_due(day, last, last + day), None);
+        assert_eq!(time_until_due(day, last, last + day * 2), None);
+        // The clock went backwards.
+        assert_eq!(
+            time_until_due(day, last, last - Duration::from_secs(1)),
+            Some(day)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_digest() {
+        let db = Db::new_memory("test_run_digest").await.unwrap();
+        db.open().await.unwrap().init().await.unwrap();
+        let maildir = std::env::temp_dir().join(format!("feedsd-digest-{}", std::process::id()));
+        let mut config = Config::default();
+        config.digest.enabled = true;
+        config.digest.maildir = Some(maildir.clone());
+        let interval = config.digest.interval;
+
+        // The first run only records the start time.
+        assert_eq!(run_digest(&config, &db).await.unwrap(), interval);
+        let last = db.open().await.unwrap().get_last_digest().await.unwrap();
+        assert!(last.is_some());
+
+        // Not due, yet.
+        let remaining = run_digest(&config, &db).await.unwrap();
+        assert!(remaining <= interval && !remaining.is_zero());
+
+        // Due. Empty digests are not delivered.
+        let past = Utc::now() - interval * 2;
+        db.open()
+            .await
+            .unwrap()
+            .set_last_digest(past)
+            .await
+            .unwrap();
+        assert_eq!(run_digest(&config, &db).await.unwrap(), interval);
+        let last2 = db.open().await.unwrap().get_last_digest().await.unwrap();
+        assert!(last2.unwrap() > past);
+        assert!(!maildir.join("new").exists());
+
+        // Due with a new item.
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed("https://example.com/feed").await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let mut i = item("1", "News", "https://example.com/1");
+        i.retrieved = Utc::now();
+        i.item_id = Some(i.make_id().await);
+        conn.update_feed(&feeds[0], &[i], None, false)
+            .await
+            .unwrap();
+        conn.set_last_digest(past).await.unwrap();
+        assert_eq!(run_digest(&config, &db).await.unwrap(), interval);
+        assert_eq!(std::fs::read_dir(maildir.join("new")).unwrap().count(), 1);
+        std::fs::remove_dir_all(&maildir).unwrap();
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
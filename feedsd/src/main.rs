@@ -19,35 +19,85 @@
 
 #![forbid(unsafe_code)]
 
+mod control;
+mod daemon;
+mod digest;
+mod metrics;
+mod notify;
+mod oneshot;
 mod refresh;
+mod rules;
+mod status;
+#[cfg(test)]
+mod stub_http;
 mod systemd;
+mod webhook;
 
-use crate::{refresh::refresh_feeds, systemd::systemd_notify_ready};
+use crate::{
+    control::{bind_control_socket, serve_control_socket},
+    daemon::{ConfigFile, Daemon, Wakeup},
+    digest::run_digest,
+    metrics::{bind_metrics_listener, serve_metrics},
+    oneshot::{dry_run, run_once},
+    refresh::refresh_feeds,
+    systemd::{
+        systemd_notify_ready, systemd_notify_status, systemd_notify_stopping,
+        systemd_notify_watchdog, systemd_watchdog_timeout,
+    },
+};
 use anyhow::{self as ah, Context as _, format_err as err};
 use clap::Parser;
-use feedscfg::Config;
+use feedscfg::{Config, format_duration};
 use feedsdb::Db;
-use std::{fs::OpenOptions, io::Write as _, num::NonZeroUsize, sync::Arc, time::Duration};
+use std::{
+    fs::OpenOptions, io::Write as _, num::NonZeroUsize, path::PathBuf, sync::Arc, time::Duration,
+};
 use tokio::{
     runtime,
     signal::unix::{SignalKind, signal},
     sync, task,
 };
 
+const PIDFILE: &str = "/run/feedsd/feedsd.pid";
+
+/// The PID-file. It is removed on drop.
+struct PidFile;
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(PIDFILE);
+    }
+}
+
 /// Create the PID-file in the /run subdirectory.
-fn make_pidfile() -> ah::Result<()> {
+fn make_pidfile() -> ah::Result<PidFile> {
     OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open("/run/feedsd/feedsd.pid")
+        .open(PIDFILE)
         .context("Open PID-file")?
         .write_all(format!("{}\n", std::process::id()).as_bytes())
-        .context("Write to PID-file")
+        .context("Write to PID-file")?;
+    Ok(PidFile)
+}
+
+fn parse_duration_arg(s: &str) -> Result<Duration, String> {
+    feedscfg::parse_duration(s).ok_or_else(|| format!("Invalid duration '{s}'."))
 }
 
 #[derive(Parser, Debug, Clone)]
 struct Opts {
+    /// The configuration file.
+    ///
+    /// The `*.conf` files in the `conf.d` directory next to it are merged in lexical order.
+    #[arg(long, env = feedscfg::CONFIG_FILE_ENV, default_value = feedscfg::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+
+    /// Fail, if the configuration file does not exist.
+    #[arg(long, env = feedscfg::CONFIG_STRICT_ENV)]
+    strict_config: bool,
+
     /// The name of the database to use.
     #[arg(long, default_value = "feeds")]
     db: String,
@@ -56,10 +106,46 @@ struct Opts {
     #[arg(long, default_value = "4")]
     worker_threads: NonZeroUsize,
 
+    /// The time to let in-flight feed refreshes finish on shutdown.
+    ///
+    /// The refreshes that did not finish in time are aborted.
+    #[arg(long, default_value = "30s", value_parser = parse_duration_arg)]
+    shutdown_grace: Duration,
+
     /// Do not create `/run/feedsd/feedsd.pid`.
     #[arg(long)]
     no_pidfile: bool,
 
+    /// The path of the control socket.
+    #[arg(long, default_value = feedsctl::DEFAULT_SOCKET)]
+    control_socket: PathBuf,
+
+    /// Do not create the control socket.
+    #[arg(long)]
+    no_control_socket: bool,
+
+    /// Serve Prometheus metrics at `http://<ADDR>/metrics`.
+    ///
+    /// For example `127.0.0.1:9477`. Disabled by default.
+    #[arg(long, value_name = "ADDR")]
+    metrics_listen: Option<String>,
+
+    /// Refresh the due feeds once and exit.
+    ///
+    /// Exits with an error status, if a feed failed to refresh.
+    #[arg(long, group = "oneshot")]
+    once: bool,
+
+    /// Fetch and parse the due feeds, print what a refresh would change and exit.
+    ///
+    /// Nothing is written to the database.
+    #[arg(long, group = "oneshot")]
+    dry_run: bool,
+
+    /// With `--once` or `--dry-run`: All enabled feeds instead of the due feeds.
+    #[arg(long, requires = "oneshot")]
+    all: bool,
+
     /// Enable `tokio-console` tracing support.
     ///
     /// See https://crates.io/crates/tokio-console
@@ -67,34 +153,69 @@ struct Opts {
     tokio_console: bool,
 }
 
+fn notify_status(status: &str) {
+    if let Err(e) = systemd_notify_status(status) {
+        log::warn!("Notify systemd: {e:?}");
+    }
+}
+
 #[must_use]
-async fn do_refresh(db: Arc<Db>, _opts: &Opts, config: Arc<Config>) -> (bool, Duration) {
+async fn do_refresh(daemon: &Daemon, wakeup: Wakeup) -> (bool, Duration) {
     log::info!("Refreshing...");
-    match refresh_feeds(config, db).await {
+    notify_status("Refreshing feeds");
+    daemon.status.begin_round();
+    let result = refresh_feeds(daemon, wakeup == Wakeup::All).await;
+    daemon.status.end_round();
+
+    let retry_dur = Duration::from_secs(60);
+    match result {
         Err(e) => {
             log::error!("{e:?}");
-            (false, Duration::from_secs(60))
+            notify_status(&format!(
+                "Refresh failed: {e:#}, next in {}",
+                format_duration(retry_dur)
+            ));
+            (false, retry_dur)
         }
-        Ok(sleep_dur) => {
-            log::info!("Refreshed. Sleeping {sleep_dur:?}.");
-            (true, sleep_dur)
+        Ok(summary) => {
+            let (ok, sleep_dur) = if summary.errors > 0 {
+                log::error!("Failed to refresh {} feed(s).", summary.errors);
+                (false, retry_dur)
+            } else {
+                log::info!("Refreshed. Sleeping {:?}.", summary.sleep_dur);
+                (true, summary.sleep_dur)
+            };
+            notify_status(&format!(
+                "Refreshed {} feeds, {} errors, next in {}",
+                summary.feeds,
+                summary.errors,
+                format_duration(sleep_dur)
+            ));
+            (ok, sleep_dur)
         }
     }
 }
 
 async fn async_main(opts: Opts) -> ah::Result<()> {
-    let opts = Arc::new(opts);
-    let config = Arc::new(Config::parse_default_file().context("Parse configuration file")?);
+    let config = Config::load(&opts.config, opts.strict_config)?;
 
-    // Create pid-file in /run.
-    if !opts.no_pidfile {
-        make_pidfile()?;
+    if opts.dry_run {
+        let db = Db::new(&opts.db).await.context("Database")?;
+        return dry_run(&config, &db, opts.all).await;
     }
 
+    // Create pid-file in /run.
+    let _pidfile = if opts.no_pidfile || opts.once {
+        None
+    } else {
+        Some(make_pidfile()?)
+    };
+
     // Register unix signal handlers.
     let mut sigterm = signal(SignalKind::terminate()).unwrap();
     let mut sigint = signal(SignalKind::interrupt()).unwrap();
     let mut sighup = signal(SignalKind::hangup()).unwrap();
+    let mut sigusr1 = signal(SignalKind::user_defined1()).unwrap();
 
     // Create async IPC channels.
     let (exit_sock_tx, mut exit_sock_rx) = sync::mpsc::channel(1);
@@ -110,11 +231,63 @@ async fn async_main(opts: Opts) -> ah::Result<()> {
         .await
         .context("Initialize database")?;
 
+    let daemon = Arc::new(Daemon::new(
+        db,
+        config,
+        ConfigFile {
+            path: opts.config.clone(),
+            strict: opts.strict_config,
+        },
+        exit_sock_tx,
+    ));
+
+    if opts.once {
+        return run_once(&daemon, opts.all).await;
+    }
+
+    // Task: Control socket.
+    if !opts.no_control_socket {
+        let listener = bind_control_socket(&opts.control_socket)?;
+        task::spawn(serve_control_socket(Arc::clone(&daemon), listener));
+    }
+
+    // Task: Metrics listener.
+    if let Some(addr) = &opts.metrics_listen {
+        let listener = bind_metrics_listener(addr).await?;
+        task::spawn(serve_metrics(Arc::clone(&daemon.metrics), listener));
+    }
+
     // Ready-signal to systemd.
     systemd_notify_ready().context("Notify systemd")?;
 
+    // Task: systemd watchdog.
+    if let Some(timeout) = systemd_watchdog_timeout() {
+        log::info!("systemd watchdog enabled. Timeout {timeout:?}.");
+        task::spawn({
+            let daemon = Arc::clone(&daemon);
+
+            async move {
+                let mut interval = tokio::time::interval(timeout / 2);
+                loop {
+                    interval.tick().await;
+                    // A refresh round that does not make progress is hung.
+                    // Let systemd restart the daemon.
+                    if daemon.status.is_stalled(timeout) {
+                        log::error!("Refresh round stalled. Stopping watchdog keep-alive.");
+                        continue;
+                    }
+                    if let Err(e) = systemd_notify_watchdog() {
+                        log::warn!("Notify systemd: {e:?}");
+                    }
+                }
+            }
+        });
+    }
+
     // Vacuum the database.
-    db.open()
+    daemon
+        .db
+        .open()
         .await
         .context("Open database")?
         .vacuum()
@@ -122,25 +295,57 @@ async fn async_main(opts: Opts) -> ah::Result<()> {
         .context("Vacuum database")?;
 
     // Task: DB refresher.
-    task::spawn({
-        let db = Arc::clone(&db);
-        let opts = Arc::clone(&opts);
-        let config = Arc::clone(&config);
+    let mut refresher = task::spawn({
+        let daemon = Arc::clone(&daemon);
+        let mut shutdown_rx = daemon.shutdown_rx();
 
         async move {
             let mut err_count = 0_u32;
+            let mut wakeup = Wakeup::Due;
             loop {
-                let (ok, sleep_dur) = do_refresh(Arc::clone(&db), &opts, Arc::clone(&config)).await;
+                let (ok, sleep_dur) = do_refresh(&daemon, wakeup).await;
+                if daemon.is_shutting_down() {
+                    break;
+                }
                 if ok {
                     err_count = err_count.saturating_sub(1);
                 } else {
                     err_count = err_count.saturating_add(3);
                     if err_count >= 9 {
                         let e = Err(err!("Too many errors. Bailing to systemd."));
-                        let _ = exit_sock_tx.send(e).await;
+                        daemon.exit(e).await;
                         break;
                     }
                 }
+                daemon.metrics.set_refresh_error_count(err_count);
+                wakeup = tokio::select! {
+                    _ = tokio::time::sleep(sleep_dur) => Wakeup::Due,
+                    w = daemon.wait_wakeup() => w,
+                    _ = shutdown_rx.wait_for(|s| *s) => break,
+                };
+            }
+        }
+    });
+
+    // Task: Digest mail.
+    task::spawn({
+        let daemon = Arc::clone(&daemon);
+
+        async move {
+            loop {
+                let config = daemon.config();
+                let sleep_dur = if !config.digest.enabled {
+                    // Check again after a configuration reload.
+                    Duration::from_secs(60)
+                } else {
+                    match run_digest(&config, &daemon.db).await {
+                        Ok(sleep_dur) => sleep_dur,
+                        Err(e) => {
+                            log::error!("Digest: {e:?}");
+                            Duration::from_secs(10 * 60)
+                        }
+                    }
+                };
                 tokio::time::sleep(sleep_dur).await;
             }
         }
@@ -161,7 +366,13 @@ async fn async_main(opts: Opts) -> ah::Result<()> {
             }
             _ = sighup.recv() => {
                 log::info!("SIGHUP: Triggering database refresh.");
-                let _ = do_refresh(Arc::clone(&db), &opts, Arc::clone(&config)).await;
+                daemon.wakeup(Wakeup::Due);
+            }
+            _ = sigusr1.recv() => {
+                log::info!("SIGUSR1: Reloading configuration.");
+                if let Err(e) = daemon.reload_config() {
+                    log::error!("Reload failed. Keeping the current configuration: {e:?}");
+                }
             }
             code = exit_sock_rx.recv() => {
                 exitcode = code.unwrap_or_else(|| Err(err!("Unknown error code.")));
@@ -169,6 +380,41 @@ async fn async_main(opts: Opts) -> ah::Result<()> {
             }
         }
     }
+    if let Err(e) = systemd_notify_stopping() {
+        log::warn!("Notify systemd: {e:?}");
+    }
+
+    // Let the in-flight feed refreshes finish, but do not start new ones.
+    daemon.begin_shutdown();
+    let in_flight = daemon.status.get().in_flight.len();
+    if in_flight > 0 {
+        log::info!(
+            "Waiting up to {:?} for {in_flight} in-flight feed refresh(es).",
+            opts.shutdown_grace
+        );
+    }
+    let finished = tokio::select! {
+        r = tokio::time::timeout(opts.shutdown_grace, &mut refresher) => r.is_ok(),
+        _ = sigterm.recv() => false,
+        _ = sigint.recv() => false,
+    };
+    if !finished {
+        // Dropping the refresh round aborts the remaining refreshes.
+        // A feed is either fully updated in the database or not at all.
+        for feed in daemon.status.get().in_flight {
+            log::warn!(
+                "Aborting the refresh of feed {}: {}",
+                feed.feed_id,
+                feed.href
+            );
+        }
+        refresher.abort();
+        let _ = refresher.await;
+    }
+
+    if !opts.no_control_socket {
+        let _ = std::fs::remove_file(&opts.control_socket);
+    }
     exitcode
 }
 
@@ -0,0 +1,385 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow::{self as ah, Context as _, format_err as err};
+use std::{collections::BTreeMap, fmt::Write as _, sync::Arc, sync::Mutex, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+    task,
+};
+
+/// Bucket bounds of the refresh round duration histogram, in seconds.
+const ROUND_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// Bucket bounds of the feed fetch duration histogram, in seconds.
+const FETCH_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// The maximum length of the request line and of each request header line.
+const MAX_LINE: usize = 8 * 1024;
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// The number of observations per bucket. Not cumulative.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = self.bounds.iter().position(|b| value <= *b) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+struct MetricsInner {
+    refresh_round: Histogram,
+    fetch: Histogram,
+    /// The duration of the last fetch of each feed, in seconds.
+    feed_fetch: BTreeMap<i64, f64>,
+    http_responses: BTreeMap<u16, u64>,
+    /// Fetches that did not get any HTTP response.
+    fetch_errors: u64,
+    downloaded_bytes: u64,
+    parse_failures: u64,
+    new_items: u64,
+    updated_items: u64,
+    refresh_error_count: u32,
+}
+
+/// The operational metrics of the daemon, in the Prometheus text format.
+pub struct Metrics {
+    inner: Mutex<MetricsInner>,
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(MetricsInner {
+                refresh_round: Histogram::new(ROUND_BUCKETS),
+                fetch: Histogram::new(FETCH_BUCKETS),
+                feed_fetch: BTreeMap::new(),
+                http_responses: BTreeMap::new(),
+                fetch_errors: 0,
+                downloaded_bytes: 0,
+                parse_failures: 0,
+                new_items: 0,
+                updated_items: 0,
+                refresh_error_count: 0,
+            }),
+        }
+    }
+
+    pub fn observe_refresh_round(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.refresh_round.observe(duration.as_secs_f64());
+    }
+
+    pub fn observe_fetch(&self, feed_id: Option<i64>, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let mut inner = self.inner.lock().unwrap();
+        inner.fetch.observe(secs);
+        if let Some(feed_id) = feed_id {
+            inner.feed_fetch.insert(feed_id, secs);
+        }
+    }
+
+    pub fn count_http_response(&self, status: u16) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .http_responses
+            .entry(status)
+            .or_default() += 1;
+    }
+
+    pub fn count_fetch_error(&self) {
+        self.inner.lock().unwrap().fetch_errors += 1;
+    }
+
+    pub fn count_downloaded_bytes(&self, bytes: usize) {
+        self.inner.lock().unwrap().downloaded_bytes += bytes as u64;
+    }
+
+    pub fn count_parse_failure(&self) {
+        self.inner.lock().unwrap().parse_failures += 1;
+    }
+
+    pub fn count_items(&self, new_items: u64, updated_items: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.new_items += new_items;
+        inner.updated_items += updated_items;
+    }
+
+    pub fn set_refresh_error_count(&self, err_count: u32) {
+        self.inner.lock().unwrap().refresh_error_count = err_count;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::with_capacity(4096);
+
+        let name = "feedsd_refresh_round_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Duration of the refresh rounds.",
+        );
+        inner.refresh_round.render(&mut out, name);
+
+        let name = "feedsd_fetch_duration_seconds";
+        header(&mut out, name, "histogram", "Duration of the feed fetches.");
+        inner.fetch.render(&mut out, name);
+
+        let name = "feedsd_feed_fetch_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "gauge",
+            "Duration of the last fetch of the feed.",
+        );
+        for (feed_id, secs) in &inner.feed_fetch {
+            let _ = writeln!(out, "{name}{{feed_id=\"{feed_id}\"}} {secs}");
+        }
+
+        let name = "feedsd_http_responses_total";
+        header(
+            &mut out,
+            name,
+            "counter",
+            "HTTP responses to feed fetches by status code.",
+        );
+        for (status, count) in &inner.http_responses {
+            let _ = writeln!(out, "{name}{{code=\"{status}\"}} {count}");
+        }
+
+        for (name, help, value) in [
+            (
+                "feedsd_fetch_errors_total",
+                "Feed fetches that failed without HTTP response.",
+                inner.fetch_errors,
+            ),
+            (
+                "feedsd_downloaded_bytes_total",
+                "Bytes of downloaded feed documents.",
+                inner.downloaded_bytes,
+            ),
+            (
+                "feedsd_parse_failures_total",
+                "Feed documents that failed to parse.",
+                inner.parse_failures,
+            ),
+            (
+                "feedsd_db_busy_retries_total",
+                "Database transactions that were retried, because the database was busy.",
+                feedsdb::busy_retries(),
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        let name = "feedsd_items_total";
+        header(&mut out, name, "counter", "Stored new and updated items.");
+        let _ = writeln!(out, "{name}{{status=\"new\"}} {}", inner.new_items);
+        let _ = writeln!(out, "{name}{{status=\"updated\"}} {}", inner.updated_items);
+
+        let name = "feedsd_refresh_error_count";
+        header(
+            &mut out,
+            name,
+            "gauge",
+            "Error count of the refresher. The daemon exits at 9.",
+        );
+        let _ = writeln!(out, "{name} {}", inner.refresh_error_count);
+
+        out
+    }
+}
+
+/// Read one line of at most [MAX_LINE] bytes.
+async fn read_line(stream: &mut BufReader<TcpStream>) -> ah::Result<String> {
+    let mut line = Vec::new();
+    let mut limited = (&mut *stream).take(MAX_LINE as u64);
+    limited.read_until(b'\n', &mut line).await?;
+    if !line.ends_with(b"\n") {
+        return Err(err!("Request line too long or truncated."));
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+/// Answer one HTTP request.
+async fn handle_connection(metrics: &Metrics, stream: TcpStream) -> ah::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let request = tokio::time::timeout(Duration::from_secs(10), async {
+        let request = read_line(&mut stream).await?;
+        // Skip the request headers.
+        while !read_line(&mut stream).await?.is_empty() {}
+        Ok::<_, ah::Error>(request)
+    })
+    .await
+    .context("Request timeout")??;
+
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", "Not found.\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "Method not allowed.\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    );
+    let mut stream = stream.into_inner();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Bind the metrics HTTP listener.
+pub async fn bind_metrics_listener(addr: &str) -> ah::Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Bind metrics listener '{addr}'"))
+}
+
+/// Serve the metrics at `/metrics`.
+pub async fn serve_metrics(metrics: Arc<Metrics>, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                task::spawn({
+                    let metrics = Arc::clone(&metrics);
+                    async move {
+                        if let Err(e) = handle_connection(&metrics, stream).await {
+                            log::debug!("Metrics listener: {e:?}");
+                        }
+                    }
+                });
+            }
+            Err(e) => {
+                log::error!("Metrics listener: Accept failed: {e:?}");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.observe_refresh_round(Duration::from_secs(3));
+        metrics.observe_fetch(Some(7), Duration::from_millis(300));
+        metrics.observe_fetch(None, Duration::from_secs(100));
+        metrics.count_http_response(200);
+        metrics.count_http_response(200);
+        metrics.count_http_response(404);
+        metrics.count_downloaded_bytes(1000);
+        metrics.count_items(3, 1);
+        metrics.set_refresh_error_count(6);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE feedsd_refresh_round_duration_seconds histogram\n"));
+        assert!(text.contains("feedsd_refresh_round_duration_seconds_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("feedsd_refresh_round_duration_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("feedsd_refresh_round_duration_seconds_count 1\n"));
+        assert!(text.contains("feedsd_fetch_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("feedsd_fetch_duration_seconds_bucket{le=\"30\"} 1\n"));
+        assert!(text.contains("feedsd_fetch_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("feedsd_feed_fetch_duration_seconds{feed_id=\"7\"} 0.3\n"));
+        assert!(text.contains("feedsd_http_responses_total{code=\"200\"} 2\n"));
+        assert!(text.contains("feedsd_http_responses_total{code=\"404\"} 1\n"));
+        assert!(text.contains("feedsd_downloaded_bytes_total 1000\n"));
+        assert!(text.contains("feedsd_parse_failures_total 0\n"));
+        assert!(text.contains("feedsd_items_total{status=\"new\"} 3\n"));
+        assert!(text.contains("feedsd_items_total{status=\"updated\"} 1\n"));
+        assert!(text.contains("feedsd_refresh_error_count 6\n"));
+        assert!(text.contains("feedsd_db_busy_retries_total "));
+    }
+
+    #[tokio::test]
+    async fn test_listener() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let metrics = Arc::new(Metrics::new());
+        metrics.count_parse_failure();
+        let listener = bind_metrics_listener("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        task::spawn(serve_metrics(metrics, listener));
+
+        let resp = reqwest::get(format!("{url}/metrics")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert!(
+            resp.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain; version=0.0.4")
+        );
+        let text = resp.text().await.unwrap();
+        assert!(text.contains("feedsd_parse_failures_total 1\n"));
+
+        let resp = reqwest::get(format!("{url}/")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
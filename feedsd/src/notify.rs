@@ -0,0 +1,463 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::Utc;
+use feedscfg::{Config, ConfigNotifyChannel, NotifyKind, RuleInput};
+use feedsdb::Item;
+use feedsfmt::mail::{Mail, run_command, sendmail};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A matching `[[alert]]` or a `[[rule]]` with the `notify` action.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alert {
+    pub name: String,
+    /// The names of the notification channels. All channels, if empty.
+    pub channels: Vec<String>,
+}
+
+/// Get all alerts that match the item `input`.
+///
+/// `notify_rules` are the names of the matching rules with the `notify` action.
+pub fn match_alerts(config: &Config, input: &RuleInput<'_>, notify_rules: &[String]) -> Vec<Alert> {
+    let mut alerts: Vec<Alert> = config
+        .alerts
+        .iter()
+        .filter(|a| a.matches(input))
+        .map(|a| Alert {
+            name: a.name.clone(),
+            channels: a.channels.clone(),
+        })
+        .collect();
+    alerts.extend(notify_rules.iter().map(|name| Alert {
+        name: name.clone(),
+        channels: vec![],
+    }));
+    alerts
+}
+
+/// A notification about a new item.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub alert: Alert,
+    pub feed_id: Option<i64>,
+    pub feed_title: String,
+    pub feed_href: String,
+    pub item: Item,
+}
+
+impl Notification {
+    /// The item fields as environment variables for the command channel.
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("FEEDREADER_ALERT", self.alert.name.clone()),
+            (
+                "FEEDREADER_FEED_ID",
+                self.feed_id.map(|id| id.to_string()).unwrap_or_default(),
+            ),
+            ("FEEDREADER_FEED_TITLE", self.feed_title.clone()),
+            ("FEEDREADER_FEED_URL", self.feed_href.clone()),
+            ("FEEDREADER_ITEM_TITLE", self.item.title.clone()),
+            ("FEEDREADER_ITEM_URL", self.item.link.clone()),
+            ("FEEDREADER_ITEM_AUTHOR", self.item.author.clone()),
+            (
+                "FEEDREADER_ITEM_PUBLISHED",
+                self.item.published.to_rfc3339(),
+            ),
+            ("FEEDREADER_ITEM_SUMMARY", self.item.summary.clone()),
+        ]
+    }
+
+    /// The JSON document for the webhook channel.
+    fn json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "alert": self.alert.name,
+            "feed": {
+                "id": self.feed_id,
+                "title": self.feed_title,
+                "url": self.feed_href,
+            },
+            "item": {
+                "title": self.item.title,
+                "url": self.item.link,
+                "author": self.item.author,
+                "published": self.item.published.to_rfc3339(),
+                "summary": self.item.summary,
+            },
+        })
+    }
+
+    /// The mail for the sendmail channel.
+    fn mail(&self, from: Option<&str>, to: &[String]) -> Mail {
+        let mut text = format!("{}\n", self.item.title);
+        if !self.item.link.is_empty() {
+            text.push_str(&format!("{}\n", self.item.link));
+        }
+        text.push_str(&format!(
+            "\nFeed: {} <{}>\nPublished: {}\n",
+            self.feed_title,
+            self.feed_href,
+            self.item.published.to_rfc2822()
+        ));
+        if !self.item.author.is_empty() {
+            text.push_str(&format!("Author: {}\n", self.item.author));
+        }
+        if !self.item.summary.trim().is_empty() {
+            text.push_str(&format!("\n{}\n", self.item.summary.trim()));
+        }
+        Mail {
+            from: from.map(|f| f.to_string()),
+            to: to.to_vec(),
+            subject: format!("[feedreader] {}: {}", self.alert.name, self.item.title),
+            date: Utc::now(),
+            text,
+            html: None,
+        }
+    }
+}
+
+/// Sends notifications to the configured channels.
+///
+/// The rate limiting state is kept over all refresh rounds.
+pub struct Notifier {
+    /// The send times within the rate limit window, per channel.
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self {
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check the rate limit of the `channel` and account for one notification.
+    fn rate_limit(&self, channel: &ConfigNotifyChannel, now: Instant) -> bool {
+        if channel.rate_limit == 0 {
+            return true;
+        }
+        let mut sent = self.sent.lock().expect("Notifier lock poisoned");
+        let times = sent.entry(channel.name.clone()).or_default();
+        while let Some(t) = times.front()
+            && now.duration_since(*t) >= channel.rate_window
+        {
+            times.pop_front();
+        }
+        if times.len() >= channel.rate_limit {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+
+    /// Send the notification to all channels of the alert.
+    ///
+    /// Errors are logged and do not abort the feed refresh.
+    pub async fn notify(&self, config: &Config, notification: &Notification) {
+        log::info!(
+            "Notification '{}': {}: {} <{}>",
+            notification.alert.name,
+            notification.feed_title,
+            notification.item.title,
+            notification.item.link
+        );
+        let channels = config.notify_channels.iter().filter(|c| {
+            notification.alert.channels.is_empty() || notification.alert.channels.contains(&c.name)
+        });
+        for channel in channels {
+            if !self.rate_limit(channel, Instant::now()) {
+                log::warn!(
+                    "Notification channel '{}': Rate limit exceeded. Dropping notification.",
+                    channel.name
+                );
+                continue;
+            }
+            if let Err(e) = send(config, channel, notification).await {
+                log::error!("Notification channel '{}': {e:?}", channel.name);
+            }
+        }
+    }
+}
+
+async fn send(
+    config: &Config,
+    channel: &ConfigNotifyChannel,
+    notification: &Notification,
+) -> ah::Result<()> {
+    let timeout = config.net.timeout;
+    match &channel.kind {
+        NotifyKind::Command { command } => {
+            run_command(command, &notification.env(), None, timeout).await
+        }
+        NotifyKind::Webhook { url } => {
+            post_webhook(&channel.name, url, &notification.json(), timeout).await
+        }
+        NotifyKind::Sendmail {
+            sendmail: command,
+            from,
+            to,
+        } => {
+            let mail = notification.mail(from.as_deref(), to).render();
+            sendmail(command, &mail, timeout).await
+        }
+    }
+}
+
+/// POST the `doc` to the webhook of the channel `name`.
+///
+/// The errors name the channel instead of the `url`, which may contain a secret.
+async fn post_webhook(
+    name: &str,
+    url: &str,
+    doc: &serde_json::Value,
+    timeout: Duration,
+) -> ah::Result<()> {
+    use reqwest::{Client, header::CONTENT_TYPE};
+
+    let client = Client::builder()
+        .user_agent(concat!("feedreader/", env!("CARGO_PKG_VERSION")))
+        .timeout(timeout)
+        .build()
+        .context("Webhook")?;
+    let resp = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(doc.to_string())
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)
+        .context("Webhook")?;
+    if !resp.status().is_success() {
+        return Err(err!("Webhook '{name}' failed: {}", resp.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub_http::stub_server;
+    use feedsdb::test_util::item;
+    use std::path::PathBuf;
+
+    fn notification() -> Notification {
+        Notification {
+            alert: Alert {
+                name: "product".to_string(),
+                channels: vec![],
+            },
+            feed_id: Some(7),
+            feed_title: "News".to_string(),
+            feed_href: "https://news.example.com/feed".to_string(),
+            item: Item {
+                feed_id: Some(7),
+                author: "Jane".to_string(),
+                summary: "Summary".to_string(),
+                ..item("1", "Feedreader released", "https://news.example.com/1")
+            },
+        }
+    }
+
+    fn channel(kind: NotifyKind, rate_limit: usize) -> ConfigNotifyChannel {
+        ConfigNotifyChannel {
+            name: "test".to_string(),
+            kind,
+            rate_limit,
+            rate_window: Duration::from_secs(60),
+        }
+    }
+
+    fn tmpfile(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("feedsd-notify-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let notifier = Notifier::new();
+        let ch = channel(
+            NotifyKind::Webhook {
+                url: "".to_string(),
+            },
+            2,
+        );
+        let t0 = Instant::now();
+        assert!(notifier.rate_limit(&ch, t0));
+        assert!(notifier.rate_limit(&ch, t0 + Duration::from_secs(30)));
+        assert!(!notifier.rate_limit(&ch, t0 + Duration::from_secs(59)));
+        assert!(notifier.rate_limit(&ch, t0 + Duration::from_secs(60)));
+        assert!(!notifier.rate_limit(&ch, t0 + Duration::from_secs(61)));
+        assert!(notifier.rate_limit(&ch, t0 + Duration::from_secs(90)));
+
+        let unlimited = channel(
+            NotifyKind::Webhook {
+                url: "".to_string(),
+            },
+            0,
+        );
+        for _ in 0..100 {
+            assert!(notifier.rate_limit(&unlimited, t0));
+        }
+    }
+
+    #[test]
+    fn test_match_alerts() {
+        let config = Config::parse_str(
+            r#"
+            [[notify-channel]]
+            name = 'hook'
+            type = 'webhook'
+            url = 'http://127.0.0.1/'
+
+            [[alert]]
+            name = 'product'
+            keywords = [ 'feedreader' ]
+            channels = [ 'hook' ]
+            "#,
+        )
+        .unwrap();
+        let n = notification();
+        let input = RuleInput {
+            title: &n.item.title,
+            summary: "",
+            url: "",
+            author: "",
+            feed_id: None,
+            feed_title: "",
+            feed_href: "",
+            categories: &[],
+        };
+        assert_eq!(
+            match_alerts(&config, &input, &["rule-0".to_string()]),
+            [
+                Alert {
+                    name: "product".to_string(),
+                    channels: vec!["hook".to_string()],
+                },
+                Alert {
+                    name: "rule-0".to_string(),
+                    channels: vec![],
+                },
+            ]
+        );
+        let input = RuleInput {
+            title: "Other",
+            ..input
+        };
+        assert!(match_alerts(&config, &input, &[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let (url, mut requests) = stub_server(&[200]).await;
+        let mut config = Config::default();
+        config
+            .notify_channels
+            .push(channel(NotifyKind::Webhook { url }, 1));
+        let notifier = Notifier::new();
+
+        notifier.notify(&config, &notification()).await;
+        let body: serde_json::Value =
+            serde_json::from_str(&requests.recv().await.unwrap().body).unwrap();
+        assert_eq!(body["alert"], "product");
+        assert_eq!(body["feed"]["id"], 7);
+        assert_eq!(body["feed"]["title"], "News");
+        assert_eq!(body["item"]["title"], "Feedreader released");
+        assert_eq!(body["item"]["url"], "https://news.example.com/1");
+        assert_eq!(body["item"]["published"], "2027-01-15T08:00:00+00:00");
+
+        // The second notification exceeds the rate limit.
+        notifier.notify(&config, &notification()).await;
+        assert!(requests.try_recv().is_err());
+
+        // Server errors are reported.
+        let (url, _requests) = stub_server(&[500]).await;
+        let ch = channel(NotifyKind::Webhook { url: url.clone() }, 0);
+        let e = send(&config, &ch, &notification()).await.unwrap_err();
+        assert!(e.to_string().contains(&format!("'{}'", ch.name)));
+        assert!(!e.to_string().contains(&url));
+    }
+
+    #[tokio::test]
+    async fn test_command() {
+        let path = tmpfile("command");
+        let ch = channel(
+            NotifyKind::Command {
+                command: vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    r#"printf '%s|%s|%s' "$FEEDREADER_ALERT" "$FEEDREADER_FEED_ID" "$FEEDREADER_ITEM_TITLE" > "$0""#
+                        .to_string(),
+                    path.display().to_string(),
+                ],
+            },
+            0,
+        );
+        send(&Config::default(), &ch, &notification())
+            .await
+            .unwrap();
+        let out = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(out, "product|7|Feedreader released");
+
+        let ch = channel(
+            NotifyKind::Command {
+                command: vec!["false".to_string()],
+            },
+            0,
+        );
+        assert!(
+            send(&Config::default(), &ch, &notification())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sendmail() {
+        let path = tmpfile("sendmail");
+        let ch = channel(
+            NotifyKind::Sendmail {
+                sendmail: vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    r#"cat > "$0""#.to_string(),
+                    path.display().to_string(),
+                ],
+                from: Some("feeds@example.com".to_string()),
+                to: vec!["team@example.com".to_string()],
+            },
+            0,
+        );
+        send(&Config::default(), &ch, &notification())
+            .await
+            .unwrap();
+        let mail = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(mail.starts_with("From: feeds@example.com\r\nTo: team@example.com\r\n"));
+        assert!(mail.contains("Subject: [feedreader] product: Feedreader released\r\n"));
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
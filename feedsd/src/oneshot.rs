@@ -0,0 +1,121 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! The one-shot modes `feedsd --once` and `feedsd --dry-run`.
+
+use crate::{
+    daemon::Daemon,
+    refresh::{DryRun, dry_run_feed, refresh_feeds},
+};
+use anyhow::{self as ah, Context as _, format_err as err};
+use feedscfg::Config;
+use feedsdb::{Db, Item, ItemStatus};
+
+/// Refresh the due feeds or, if `all` is true, all enabled feeds once.
+pub async fn run_once(daemon: &Daemon, all: bool) -> ah::Result<()> {
+    let summary = refresh_feeds(daemon, all).await?;
+    for e in daemon.status.get().errors {
+        eprintln!("Feed {} ({}): {}", e.feed_id, e.href, e.error);
+    }
+    println!(
+        "Refreshed {} feed(s): {} new item(s), {} error(s).",
+        summary.feeds, summary.new_items, summary.errors
+    );
+    if summary.errors > 0 {
+        return Err(err!("Failed to refresh {} feed(s).", summary.errors));
+    }
+    Ok(())
+}
+
+fn print_item(action: &str, item: &Item) {
+    let title = if item.title.is_empty() {
+        &item.link
+    } else {
+        &item.title
+    };
+    println!(
+        "  {action:<8}{}  {title}",
+        item.published.format("%Y-%m-%d %H:%M")
+    );
+}
+
+/// Fetch and parse the due feeds or, if `all` is true, all enabled feeds
+/// and print what a refresh would change.
+///
+/// Nothing is written to the database.
+/// The database is not upgraded, so it must have the current schema.
+pub async fn dry_run(config: &Config, db: &Db, all: bool) -> ah::Result<()> {
+    let mut conn = db.open().await.context("Open database")?;
+    conn.check_schema()
+        .await
+        .context("Run feedsd once without --dry-run to upgrade the database")?;
+    let feeds = if all {
+        conn.get_enabled_feeds()
+            .await
+            .context("Get enabled feeds")?
+    } else {
+        conn.get_feeds_due().await.context("Get feeds due")?
+    };
+    drop(conn);
+
+    let mut errors = 0;
+    for feed in &feeds {
+        println!("Feed {} ({}):", feed.feed_id.unwrap_or_default(), feed.href);
+        match dry_run_feed(config, db, feed).await {
+            Ok(DryRun::Items { items, gc_items }) => {
+                if items.is_empty() && gc_items.is_empty() {
+                    println!("  no changes");
+                }
+                for (status, item) in &items {
+                    match status {
+                        ItemStatus::New => print_item("new", item),
+                        ItemStatus::Updated => print_item("update", item),
+                        ItemStatus::Exists => (),
+                    }
+                }
+                for item in &gc_items {
+                    print_item("gc", item);
+                }
+            }
+            Ok(DryRun::MovedPermanently(Some(location))) => {
+                println!("  moved permanently; the feed would be changed to {location}");
+            }
+            Ok(DryRun::MovedPermanently(None)) => {
+                println!("  moved permanently without location; the feed would be disabled");
+            }
+            Ok(DryRun::Gone) => {
+                println!("  gone; the feed would be disabled");
+            }
+            Err(e) => {
+                errors += 1;
+                println!("  error: {e:#}");
+            }
+        }
+    }
+    println!(
+        "Checked {} feed(s), {errors} error(s). Nothing was written to the database.",
+        feeds.len()
+    );
+    if errors > 0 {
+        return Err(err!("Failed to fetch {errors} feed(s)."));
+    }
+    Ok(())
+}
+
+// vim: ts=4 sw=4 expandtab
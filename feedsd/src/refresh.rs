@@ -18,16 +18,27 @@
 //
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use crate::{
+    daemon::{Daemon, Wakeup},
+    metrics::Metrics,
+    notify::{Alert, Notification, Notifier, match_alerts},
+    rules::{RuleStats, match_rules},
+    webhook::{self, WebhookItem},
+};
 use anyhow::{self as ah, Context as _, format_err as err};
 use chrono::{DateTime, Utc};
 use feed_rs::model::Feed as ParsedFeed;
-use feedscfg::Config;
+use feedscfg::{Config, RuleInput};
+use feedsctl::RefreshOutcome;
 use feedsdb::{Db, DbConn, Feed, Item, ItemStatus};
+use feedsfmt::extract::{ExtractedItem, USER_AGENT, extract_items, feed_title, parse_feed};
 use rand::{prelude::*, rng};
-use regex::Regex;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::{
-    sync::Semaphore,
+    sync::{Semaphore, watch},
     task::{self, JoinSet},
 };
 
@@ -35,7 +46,7 @@ fn rand_interval(refresh_interval: Duration, slack_rel: f64) -> Duration {
     let slack = (refresh_interval.as_millis() as f64 * slack_rel) as u64;
     let a = refresh_interval.as_millis() as u64 - (slack / 2);
     let b = refresh_interval.as_millis() as u64 + (slack / 2);
-    Duration::from_millis(rng().random_range(a..b))
+    Duration::from_millis(rng().random_range(a..=b))
 }
 
 enum FeedResult {
@@ -44,23 +55,24 @@ enum FeedResult {
     Gone,
 }
 
-async fn get_feed(config: &Config, href: &str) -> ah::Result<FeedResult> {
-    use feed_rs::parser;
+async fn get_feed(config: &Config, metrics: &Metrics, href: &str) -> ah::Result<FeedResult> {
     use reqwest::{Client, StatusCode};
 
-    let user_agent = concat!(
-        "feedreader/",
-        env!("CARGO_PKG_VERSION"),
-        " (feedreader; Rust variant)"
-    );
     let client = Client::builder()
-        .user_agent(user_agent)
+        .user_agent(USER_AGENT)
         .referer(false)
         .timeout(config.net.timeout)
         .build()
         .context("Retrieve feed")?;
 
-    let feed_resp = client.get(href).send().await.context("Retrieve feed")?;
+    let feed_resp = match client.get(href).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            metrics.count_fetch_error();
+            return Err(e).context("Retrieve feed");
+        }
+    };
+    metrics.count_http_response(feed_resp.status().as_u16());
 
     match feed_resp.status() {
         StatusCode::OK => (),
@@ -85,139 +97,51 @@ async fn get_feed(config: &Config, href: &str) -> ah::Result<FeedResult> {
     }
 
     let feed_bytes = feed_resp.bytes().await.context("Retrieve feed")?;
+    metrics.count_downloaded_bytes(feed_bytes.len());
 
-    let feed = task::spawn_blocking(move || -> ah::Result<Box<ParsedFeed>> {
-        let parser = parser::Builder::new().build();
-        Ok(parser.parse(&*feed_bytes).map(Box::new)?)
+    let feed = task::spawn_blocking({
+        let href = href.to_string();
+        move || parse_feed(&href, &feed_bytes).map(Box::new)
     })
     .await?;
 
-    let feed = feed.map_err(|e| err!("Failed to parse feed '{href}': {e}"))?;
+    let feed = feed.map_err(|e| {
+        metrics.count_parse_failure();
+        err!("Failed to parse feed '{href}': {e}")
+    })?;
 
     //TODO: If a feed fails to parse too often, disable it.
 
     Ok(FeedResult::Feed(feed))
 }
 
-fn highlight_re_matches(name: &str, s: &str, re: &Regex) -> bool {
-    let matches = re.is_match(s);
-    if matches {
-        log::debug!("no-highlighting rule {name}/{re} matches '{s}'.");
-    }
-    matches
-}
-
-fn should_highlight(config: &Config, item: &Item) -> bool {
-    if config
-        .no_highlighting
-        .title
-        .iter()
-        .any(|re| highlight_re_matches("title", &item.title, re))
-    {
-        return false;
-    }
-    if config
-        .no_highlighting
-        .summary
-        .iter()
-        .any(|re| highlight_re_matches("summary", &item.summary, re))
-    {
-        return false;
-    }
-    if config
-        .no_highlighting
-        .url
-        .iter()
-        .any(|re| highlight_re_matches("url", &item.link, re))
-    {
-        return false;
-    }
-    true
-}
-
 struct FilteredItem {
     item: Item,
     status: ItemStatus,
     highlight: bool,
+    /// The matching alerts. Only sent for new items.
+    alerts: Vec<Alert>,
 }
 
 async fn get_items(
     config: &Config,
     conn: &mut DbConn,
+    feed: &Feed,
     parsed_feed: &ParsedFeed,
     now: DateTime<Utc>,
+    stats: &RuleStats,
 ) -> ah::Result<(Vec<FilteredItem>, DateTime<Utc>)> {
+    let href = &feed.href;
+    let feed_title = feed_title(parsed_feed).unwrap_or(&feed.title);
+
     let mut items = Vec::with_capacity(16);
     let mut oldest = now;
-    for parsed_entry in &parsed_feed.entries {
-        let feed_item_id = parsed_entry.id.clone();
-
-        let author = itertools::join(parsed_entry.authors.iter().map(|a| &a.name), ", ");
-
-        let title = parsed_entry
-            .title
-            .as_ref()
-            .map(|t| t.content.clone())
-            .unwrap_or_default();
-
-        let link = parsed_entry
-            .links
-            .iter()
-            .map(|l| l.href.clone())
-            .next()
-            .unwrap_or_default();
-
-        let published = if let Some(published) = &parsed_entry.published {
-            *published
-        } else if let Some(updated) = &parsed_entry.updated {
-            *updated
-        } else if feed_item_id.contains("blog.fefe.de") {
-            // Fefe-workaround :-/
-            let Some(fefeid) = feed_item_id.split('=').next_back() else {
-                continue;
-            };
-            let Ok(fefeid) = i64::from_str_radix(fefeid, 16) else {
-                continue;
-            };
-            let stamp = fefeid ^ 0xfefec0de;
-            let Some(stamp) = DateTime::<Utc>::from_timestamp(stamp, 0) else {
-                continue;
-            };
-            stamp
-        } else {
-            now
-        };
-
-        if published < oldest {
-            oldest = published;
-        }
-
-        let mut summary = parsed_entry
-            .summary
-            .as_ref()
-            .map(|s| s.content.clone())
-            .unwrap_or_default();
-        if summary.trim().is_empty() {
-            for media in &parsed_entry.media {
-                if let Some(description) = &media.description {
-                    summary = description.content.clone();
-                    break;
-                }
-            }
-        }
-
-        let mut item = Item {
-            item_id: None,
-            feed_id: None,
-            retrieved: now,
-            seen: false,
-            author,
-            title,
-            feed_item_id,
-            link,
-            published,
-            summary,
-        };
+    for ExtractedItem {
+        mut item,
+        categories,
+    } in extract_items(config, href, parsed_feed, now)
+    {
+        let published = item.published;
         item.item_id = Some(item.make_id().await);
 
         match conn
@@ -227,7 +151,31 @@ async fn get_items(
         {
             ItemStatus::Exists => (),
             s @ ItemStatus::New | s @ ItemStatus::Updated => {
-                let highlight = should_highlight(config, &item);
+                let input = RuleInput {
+                    title: &item.title,
+                    summary: &item.summary,
+                    url: &item.link,
+                    author: &item.author,
+                    feed_id: feed.feed_id,
+                    feed_title,
+                    feed_href: href,
+                    categories: &categories,
+                };
+                let rules = match_rules(config, &input);
+                if rules.drop {
+                    continue;
+                }
+                if s == ItemStatus::New {
+                    stats.count(&rules);
+                }
+                let suppressed_by = config.no_highlighting.matching_rule(&input);
+                let alerts = match_alerts(config, &input, &rules.notify);
+                rules.apply(&mut item);
+
+                if !item.important {
+                    item.suppressed_by = suppressed_by;
+                }
+                let highlight = !rules.seen && item.suppressed_by.is_none();
                 if !highlight && config.no_highlighting.set_seen {
                     item.seen = true;
                 }
@@ -235,27 +183,59 @@ async fn get_items(
                     item,
                     status: s,
                     highlight,
+                    alerts,
                 };
                 items.push(fil_item);
             }
         }
+        // Dropped items are not stored and must not hold back the garbage collection.
+        if published < oldest {
+            oldest = published;
+        }
     }
     Ok((items, oldest))
 }
 
-async fn refresh_feed(
+/// The state shared by the feed refreshes of one refresh round.
+struct RefreshRound {
     config: Arc<Config>,
     db: Arc<Db>,
-    mut feed: Feed,
     next_retrieval: DateTime<Utc>,
-    net_sema: Arc<Semaphore>,
-) -> ah::Result<()> {
+    net_sema: Semaphore,
+    stats: RuleStats,
+    notifier: Arc<Notifier>,
+    metrics: Arc<Metrics>,
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+/// Refresh one feed and return the number of new items.
+async fn refresh_feed(round: Arc<RefreshRound>, mut feed: Feed) -> ah::Result<usize> {
+    let RefreshRound {
+        config,
+        db,
+        next_retrieval,
+        net_sema,
+        stats,
+        notifier,
+        metrics,
+        shutdown_rx,
+    } = &*round;
+
     log::debug!("Refreshing {} ...", feed.title);
 
     let parsed_feed = {
         let _permit = net_sema.acquire().await?;
 
-        match get_feed(&config, &feed.href).await? {
+        // Do not start new fetches during shutdown.
+        if *shutdown_rx.borrow() {
+            return Err(err!("Shutting down. Refresh skipped."));
+        }
+
+        let begin = Instant::now();
+        let result = get_feed(config, metrics, &feed.href).await;
+        metrics.observe_fetch(feed.feed_id, begin.elapsed());
+
+        match result? {
             FeedResult::Feed(f) => f,
             FeedResult::MovedPermanently(location) => {
                 if let Some(location) = location {
@@ -269,7 +249,7 @@ async fn refresh_feed(
                     .update_feed(&feed, &[], None, true)
                     .await
                     .context("Update feed")?;
-                return Ok(());
+                return Ok(0);
             }
             FeedResult::Gone => {
                 feed.disabled = true;
@@ -279,14 +259,14 @@ async fn refresh_feed(
                     .update_feed(&feed, &[], None, true)
                     .await
                     .context("Update feed")?;
-                return Ok(());
+                return Ok(0);
             }
         }
     };
 
     let now = Utc::now();
     let mut conn = db.open().await.context("Open database")?;
-    let (items, oldest) = get_items(&config, &mut conn, &parsed_feed, now).await?;
+    let (items, oldest) = get_items(config, &mut conn, &feed, &parsed_feed, now, stats).await?;
 
     let new_items_count: i64 = items
         .iter()
@@ -297,7 +277,7 @@ async fn refresh_feed(
         feed.title = title.content.clone();
     }
     feed.last_retrieval = now;
-    feed.next_retrieval = next_retrieval;
+    feed.next_retrieval = *next_retrieval;
 
     feed.updated_items = 0;
     let mut increment_update_revision = false;
@@ -316,53 +296,809 @@ async fn refresh_feed(
 
     let gc_thres = oldest - config.db.gc_age_offset;
 
-    let items: Vec<Item> = items.into_iter().map(|i| i.item).collect();
-    conn.update_feed(&feed, &items, Some(gc_thres), increment_update_revision)
-        .await
-        .context("Update feed")?;
+    let notifications: Vec<Notification> = items
+        .iter()
+        .filter(|i| i.status == ItemStatus::New)
+        .flat_map(|i| {
+            i.alerts.iter().map(|alert| Notification {
+                alert: alert.clone(),
+                feed_id: feed.feed_id,
+                feed_title: feed.title.clone(),
+                feed_href: feed.href.clone(),
+                item: i.item.clone(),
+            })
+        })
+        .collect();
+    let webhook_items: Vec<WebhookItem> = items
+        .iter()
+        .filter(|i| i.status == ItemStatus::New && i.highlight)
+        .map(|i| WebhookItem::new(&feed, &i.item))
+        .collect();
 
-    Ok(())
-}
+    let updated_count = items
+        .iter()
+        .filter(|i| i.status == ItemStatus::Updated)
+        .count();
+    let new_count = items.len() - updated_count;
 
-pub async fn refresh_feeds(config: Arc<Config>, db: Arc<Db>) -> ah::Result<Duration> {
-    let next_retrieval =
-        Utc::now() + rand_interval(config.db.refresh_interval, config.db.refresh_slack);
+    // The webhook outbox items are written together with the items,
+    // so that no notification is lost, if feedsd is stopped.
+    // They are batched and delivered at the end of the round.
+    let outbox = webhook::outbox_items(config, &webhook_items, Utc::now());
 
-    let feeds_due = db
-        .open()
-        .await
-        .context("Open database")?
-        .get_feeds_due()
-        .await
-        .context("Get feeds due")?;
+    let items: Vec<Item> = items.into_iter().map(|i| i.item).collect();
+    conn.update_feed_with_outbox(
+        &feed,
+        &items,
+        Some(gc_thres),
+        increment_update_revision,
+        &outbox,
+    )
+    .await
+    .context("Update feed")?;
+    metrics.count_items(new_count as u64, updated_count as u64);
+
+    for notification in &notifications {
+        notifier.notify(config, notification).await;
+    }
+
+    Ok(new_count)
+}
 
-    let net_sema = Arc::new(Semaphore::new(config.net.concurrency.into()));
+/// Refresh the `feeds` concurrently.
+///
+/// Feeds that are already being refreshed are skipped with an error outcome.
+/// Returns the outcome of each feed.
+async fn refresh_feed_list(daemon: &Daemon, feeds: Vec<Feed>) -> ah::Result<Vec<RefreshOutcome>> {
+    let begin = Instant::now();
+    let config = daemon.config();
+    let round = Arc::new(RefreshRound {
+        config: Arc::clone(&config),
+        db: Arc::clone(&daemon.db),
+        next_retrieval: Utc::now()
+            + rand_interval(config.db.refresh_interval, config.db.refresh_slack),
+        net_sema: Semaphore::new(config.net.concurrency.into()),
+        stats: RuleStats::new(&config),
+        notifier: Arc::clone(&daemon.notifier),
+        metrics: Arc::clone(&daemon.metrics),
+        shutdown_rx: daemon.shutdown_rx(),
+    });
 
+    let mut outcomes = vec![];
     let mut set = JoinSet::new();
-    for feed in feeds_due {
+    for feed in feeds {
+        let feed_id = feed.feed_id.unwrap_or_default();
+        if !daemon.status.begin_feed(&feed) {
+            log::debug!("Skipping {}: Refresh in progress.", feed.title);
+            outcomes.push(RefreshOutcome::error(
+                feed_id,
+                "The feed is already being refreshed.",
+            ));
+            continue;
+        }
         set.spawn({
-            let config = Arc::clone(&config);
-            let db = Arc::clone(&db);
-            let net_sema = Arc::clone(&net_sema);
-            async move { refresh_feed(config, db, feed, next_retrieval, net_sema).await }
+            let round = Arc::clone(&round);
+            let status = Arc::clone(&daemon.status);
+            async move {
+                let result = refresh_feed(round, feed.clone()).await;
+                status.end_feed(&feed, &result);
+                (feed_id, result)
+            }
         });
     }
     while let Some(result) = set.join_next().await {
-        let _: () = result??;
+        match result? {
+            (feed_id, Ok(new_items)) => {
+                outcomes.push(RefreshOutcome {
+                    feed_id,
+                    new_items,
+                    error: None,
+                });
+            }
+            (feed_id, Err(e)) => {
+                log::error!("Feed {feed_id}: {e:?}");
+                outcomes.push(RefreshOutcome::error(feed_id, format!("{e:#}")));
+            }
+        }
+    }
+    round.stats.log(&config);
+    daemon.metrics.observe_refresh_round(begin.elapsed());
+
+    Ok(outcomes)
+}
+
+/// Deliver the due webhook outbox entries.
+///
+/// During shutdown the entries stay in the outbox for the next start.
+/// Returns the time of the next pending delivery attempt.
+async fn deliver_webhooks(daemon: &Daemon) -> ah::Result<Option<DateTime<Utc>>> {
+    if daemon.is_shutting_down() {
+        return Ok(None);
+    }
+    // Only one delivery at a time, so that no entry is POSTed twice.
+    let _guard = daemon.webhook_lock.lock().await;
+    let mut conn = daemon.db.open().await.context("Open database")?;
+    webhook::deliver(&daemon.config(), &mut conn, Utc::now(), || {
+        daemon.status.progress()
+    })
+    .await
+}
+
+/// The changes a refresh of a feed would make to the database.
+pub enum DryRun {
+    Items {
+        /// The new and updated items that would be inserted.
+        items: Vec<(ItemStatus, Item)>,
+        /// The items that would be garbage collected.
+        gc_items: Vec<Item>,
+    },
+    /// The feed would be moved to the new location or, if None, disabled.
+    MovedPermanently(Option<String>),
+    /// The feed would be disabled.
+    Gone,
+}
+
+async fn dry_run_items(
+    config: &Config,
+    conn: &mut DbConn,
+    feed: &Feed,
+    parsed_feed: &ParsedFeed,
+    now: DateTime<Utc>,
+) -> ah::Result<DryRun> {
+    let stats = RuleStats::new(config);
+    let (items, oldest) = get_items(config, conn, feed, parsed_feed, now, &stats).await?;
+    let gc_items = match feed.feed_id {
+        Some(feed_id) => conn
+            .get_gc_items(feed_id, oldest - config.db.gc_age_offset)
+            .await
+            .context("Get GC items")?,
+        None => vec![],
+    };
+    Ok(DryRun::Items {
+        items: items.into_iter().map(|i| (i.status, i.item)).collect(),
+        gc_items,
+    })
+}
+
+/// Fetch and parse the feed and compute what a refresh would change.
+///
+/// Nothing is written to the database and no notifications are sent.
+pub async fn dry_run_feed(config: &Config, db: &Db, feed: &Feed) -> ah::Result<DryRun> {
+    let metrics = Metrics::new();
+    match get_feed(config, &metrics, &feed.href).await? {
+        FeedResult::Feed(parsed_feed) => {
+            let mut conn = db.open().await.context("Open database")?;
+            dry_run_items(config, &mut conn, feed, &parsed_feed, Utc::now()).await
+        }
+        FeedResult::MovedPermanently(location) => Ok(DryRun::MovedPermanently(location)),
+        FeedResult::Gone => Ok(DryRun::Gone),
     }
+}
+
+/// The result of a refresh round.
+pub struct RefreshSummary {
+    /// The number of refreshed feeds.
+    pub feeds: usize,
+    /// The number of feeds that failed to refresh.
+    pub errors: usize,
+    /// The number of new items.
+    pub new_items: usize,
+    /// The time to sleep until the next refresh round.
+    pub sleep_dur: Duration,
+}
+
+/// Refresh the feeds that are due or, if `all` is true, all enabled feeds.
+pub async fn refresh_feeds(daemon: &Daemon, all: bool) -> ah::Result<RefreshSummary> {
+    let mut conn = daemon.db.open().await.context("Open database")?;
+    let feeds = if all {
+        conn.get_enabled_feeds()
+            .await
+            .context("Get enabled feeds")?
+    } else {
+        conn.get_feeds_due().await.context("Get feeds due")?
+    };
 
-    let next_due = db
-        .open()
-        .await
-        .context("Open database")?
-        .get_next_due_time()
-        .await
-        .context("Update feed")?;
+    let outcomes = refresh_feed_list(daemon, feeds).await?;
+
+    // Deliver the new items of this round and all pending retries.
+    let next_attempt = deliver_webhooks(daemon).await?;
+
+    let next_due = conn.get_next_due_time().await.context("Update feed")?;
+    daemon.status.set_refreshed(Utc::now(), next_due);
+
+    let errors = outcomes.iter().filter(|o| o.error.is_some()).count();
+
+    let next_due = next_attempt.map_or(next_due, |a| a.min(next_due));
     let dur = (next_due - Utc::now()).num_milliseconds().max(0);
     let sleep_dur = Duration::from_millis(dur.try_into().unwrap());
     let sleep_dur = sleep_dur + Duration::from_secs(1);
 
-    Ok(sleep_dur)
+    Ok(RefreshSummary {
+        feeds: outcomes.len(),
+        errors,
+        new_items: outcomes.iter().map(|o| o.new_items).sum(),
+        sleep_dur,
+    })
+}
+
+/// Refresh the feeds now, whether they are due or not.
+///
+/// The webhooks for the new items are not delivered here, but by the refresher,
+/// which is woken up for that. So the caller doesn't wait for the delivery.
+/// Returns the outcome of each feed in the order of `feed_ids`.
+pub async fn refresh_feeds_now(
+    daemon: &Daemon,
+    feed_ids: &[i64],
+) -> ah::Result<Vec<RefreshOutcome>> {
+    let mut conn = daemon.db.open().await.context("Open database")?;
+    let mut outcomes = vec![];
+    let mut feeds = vec![];
+    for &feed_id in feed_ids {
+        match conn.get_feed(feed_id).await.context("Get feed")? {
+            None => outcomes.push(RefreshOutcome::error(feed_id, "The feed does not exist.")),
+            Some(feed) if feed.disabled => {
+                outcomes.push(RefreshOutcome::error(feed_id, "The feed is disabled."));
+            }
+            Some(feed) => feeds.push(feed),
+        }
+    }
+
+    let refreshed = refresh_feed_list(daemon, feeds).await?;
+    if refreshed.iter().any(|o| o.new_items > 0) && !daemon.config().webhooks.is_empty() {
+        daemon.wakeup(Wakeup::Due);
+    }
+    outcomes.extend(refreshed);
+    outcomes.sort_by_key(|o| feed_ids.iter().position(|id| *id == o.feed_id));
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::{dt, feed, now};
+    use std::path::Path;
+
+    const HREF: &str = "https://example.com/feed";
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name);
+        std::fs::read(&path).unwrap()
+    }
+
+    async fn extract_href(
+        db: &Db,
+        config: &Config,
+        href: &str,
+        feed_bytes: &[u8],
+    ) -> (Vec<FilteredItem>, DateTime<Utc>) {
+        let parsed_feed = parse_feed(href, feed_bytes).unwrap();
+        let mut conn = db.open().await.unwrap();
+        let feed = feed(None, href);
+        let stats = RuleStats::new(config);
+        get_items(config, &mut conn, &feed, &parsed_feed, now(), &stats)
+            .await
+            .unwrap()
+    }
+
+    async fn extract(
+        db: &Db,
+        config: &Config,
+        feed_bytes: &[u8],
+    ) -> (Vec<FilteredItem>, DateTime<Utc>) {
+        extract_href(db, config, HREF, feed_bytes).await
+    }
+
+    /// Expected item: feed_item_id, author, title, link, published, summary.
+    type Expect<'a> = (&'a str, &'a str, &'a str, &'a str, DateTime<Utc>, &'a str);
+
+    async fn check_fixture(name: &str, expect: &[Expect<'_>]) -> DateTime<Utc> {
+        check_fixture_href(name, HREF, expect).await
+    }
+
+    async fn check_fixture_href(name: &str, href: &str, expect: &[Expect<'_>]) -> DateTime<Utc> {
+        let db = Db::new_memory(&name.replace('.', "_")).await.unwrap();
+        let (items, oldest) = extract_href(&db, &Config::default(), href, &fixture(name)).await;
+
+        assert_eq!(items.len(), expect.len(), "{name}: number of items");
+        for (i, e) in items.iter().zip(expect) {
+            let (feed_item_id, author, title, link, published, summary) = *e;
+            assert_eq!(i.status, ItemStatus::New, "{name}");
+            assert!(i.highlight, "{name}");
+            if !feed_item_id.is_empty() {
+                assert_eq!(i.item.feed_item_id, feed_item_id, "{name}");
+            }
+            assert_eq!(i.item.author, author, "{name}");
+            assert_eq!(i.item.title, title, "{name}");
+            assert_eq!(i.item.link, link, "{name}");
+            assert_eq!(i.item.published, published, "{name}");
+            assert_eq!(i.item.summary, summary, "{name}");
+            assert_eq!(i.item.retrieved, now(), "{name}");
+            assert!(!i.item.seen, "{name}");
+            assert_eq!(
+                i.item.item_id.as_deref(),
+                Some(i.item.make_id().await.as_str())
+            );
+        }
+
+        // Generated IDs must be stable over multiple retrievals.
+        let (again, _) = extract_href(&db, &Config::default(), href, &fixture(name)).await;
+        let ids: Vec<_> = items.iter().map(|i| &i.item.item_id).collect();
+        let ids_again: Vec<_> = again.iter().map(|i| &i.item.item_id).collect();
+        assert_eq!(ids, ids_again, "{name}: unstable IDs");
+
+        oldest
+    }
+
+    #[test]
+    fn test_rand_interval() {
+        let interval = Duration::from_secs(3600);
+        for _ in 0..100 {
+            let r = rand_interval(interval, 0.5);
+            assert!(r >= Duration::from_secs(2700) && r <= Duration::from_secs(4500));
+        }
+        // No slack.
+        assert_eq!(rand_interval(interval, 0.0), interval);
+        // The slack is less than one millisecond.
+        assert_eq!(
+            rand_interval(Duration::from_millis(1), 1.0),
+            Duration::from_millis(1)
+        );
+        let r = rand_interval(Duration::from_millis(3), 1.0);
+        assert!(r >= Duration::from_millis(2) && r <= Duration::from_millis(4));
+    }
+
+    #[tokio::test]
+    async fn test_rss091() {
+        let oldest = check_fixture(
+            "rss091.xml",
+            &[
+                (
+                    "",
+                    "",
+                    "First 0.91 item",
+                    "http://rss091.example.com/item1.html",
+                    now(),
+                    "Description of the first item",
+                ),
+                (
+                    "",
+                    "",
+                    "Second 0.91 item",
+                    "http://rss091.example.com/item2.html",
+                    now(),
+                    "Description of the second item",
+                ),
+            ],
+        )
+        .await;
+        assert_eq!(oldest, now());
+    }
+
+    #[tokio::test]
+    async fn test_rss092() {
+        check_fixture(
+            "rss092.xml",
+            &[(
+                "",
+                "",
+                "0.92 item with enclosure",
+                "http://rss092.example.com/episode1",
+                now(),
+                "<p>Escaped <b>HTML</b> description</p>",
+            )],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_rss10() {
+        let oldest = check_fixture(
+            "rss10.rdf",
+            &[
+                (
+                    "",
+                    "Alice",
+                    "RDF item A",
+                    "http://rss10.example.com/a",
+                    dt("2025-01-15T09:30:00Z"),
+                    "Item A description",
+                ),
+                (
+                    "",
+                    "Bob",
+                    "RDF item B",
+                    "http://rss10.example.com/b",
+                    dt("2025-01-16T08:00:00Z"),
+                    "Item B description",
+                ),
+            ],
+        )
+        .await;
+        assert_eq!(oldest, dt("2025-01-15T09:30:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_rss20() {
+        let oldest = check_fixture(
+            "rss20.xml",
+            &[
+                (
+                    "https://rss20.example.com/posts/1",
+                    "Carol, Dave",
+                    "Tom & Jerry",
+                    "https://rss20.example.com/posts/1",
+                    dt("2025-03-01T08:30:00Z"),
+                    "<p>CDATA <em>summary</em></p>",
+                ),
+                (
+                    "post-2",
+                    "",
+                    "Video without description",
+                    "https://rss20.example.com/posts/2",
+                    dt("2025-03-02T09:00:00Z"),
+                    "Media description fallback",
+                ),
+            ],
+        )
+        .await;
+        assert_eq!(oldest, dt("2025-03-01T08:30:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_atom() {
+        check_fixture(
+            "atom.xml",
+            &[
+                (
+                    "tag:atom.example.com,2025:1",
+                    "Eve",
+                    "Atom <b>entry</b> one",
+                    "https://atom.example.com/1",
+                    dt("2025-04-01T10:00:00Z"),
+                    "Plain text summary",
+                ),
+                (
+                    "tag:atom.example.com,2025:2",
+                    "",
+                    "Atom entry two",
+                    "https://atom.example.com/2",
+                    dt("2025-04-02T06:00:00Z"),
+                    "",
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_jsonfeed() {
+        check_fixture(
+            "jsonfeed.json",
+            &[
+                (
+                    "json-1",
+                    "Frank",
+                    "JSON item one",
+                    "https://json.example.com/1",
+                    dt("2025-05-01T10:00:00Z"),
+                    "JSON summary",
+                ),
+                (
+                    "json-2",
+                    "Feed Author",
+                    "",
+                    "https://json.example.com/2",
+                    dt("2025-05-02T11:00:00Z"),
+                    "",
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_fefe() {
+        // The timestamp is encoded in the ID. The item with the invalid ID is dropped.
+        let oldest = check_fixture_href(
+            "fefe.xml",
+            "https://blog.fefe.de/rss.xml",
+            &[(
+                "https://blog.fefe.de/?ts=9b1a4d5e",
+                "",
+                "Erster Blogeintrag ohne Datum",
+                "https://blog.fefe.de/?ts=9b1a4d5e",
+                DateTime::from_timestamp(0x9b1a4d5e ^ 0xfefec0de, 0).unwrap(),
+                "",
+            )],
+        )
+        .await;
+        assert_eq!(oldest, dt("2024-03-03T14:47:28Z"));
+    }
+
+    #[tokio::test]
+    async fn test_broken_dates() {
+        // Invalid or unknown dates fall back to the retrieval time.
+        check_fixture(
+            "broken_dates.xml",
+            &[
+                (
+                    "dates-1",
+                    "",
+                    "Invalid date",
+                    "https://dates.example.com/1",
+                    now(),
+                    "",
+                ),
+                (
+                    "dates-2",
+                    "",
+                    "No date",
+                    "https://dates.example.com/2",
+                    now(),
+                    "",
+                ),
+                (
+                    "dates-3",
+                    "",
+                    "Non-standard timezone",
+                    "https://dates.example.com/3",
+                    now(),
+                    "",
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_broken_bom_noguid() {
+        check_fixture(
+            "broken_bom_noguid.xml",
+            &[
+                (
+                    "",
+                    "",
+                    "",
+                    "https://noguid.example.com/1",
+                    dt("2025-01-01T00:00:00Z"),
+                    "Only a link and a description",
+                ),
+                ("", "", "Only a title", "", now(), ""),
+            ],
+        )
+        .await;
+    }
+
+    #[test]
+    fn test_unparsable() {
+        assert!(parse_feed(HREF, &fixture("broken_truncated.xml")).is_err());
+        assert!(parse_feed(HREF, &fixture("broken_html.html")).is_err());
+        assert!(parse_feed(HREF, b"").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_item_status() {
+        let db = Db::new_memory("test_item_status").await.unwrap();
+        let config = Config::default();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed(HREF).await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let feed = feeds[0].clone();
+
+        let store = async |items: Vec<FilteredItem>| {
+            let items: Vec<Item> = items.into_iter().map(|i| i.item).collect();
+            let mut conn = db.open().await.unwrap();
+            conn.update_feed(&feed, &items, None, false).await.unwrap();
+        };
+
+        let (items, _) = extract(&db, &config, &fixture("rss20.xml")).await;
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|i| i.status == ItemStatus::New));
+        store(items).await;
+
+        // Unchanged items are not returned again.
+        let (items, _) = extract(&db, &config, &fixture("rss20.xml")).await;
+        assert!(items.is_empty());
+
+        // Changed content with the same feed item ID is an update.
+        let updated = String::from_utf8(fixture("rss20.xml"))
+            .unwrap()
+            .replace("Tom &amp; Jerry", "Tom &amp; Jerry (updated)");
+        let (items, _) = extract(&db, &config, updated.as_bytes()).await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].status, ItemStatus::Updated);
+        assert_eq!(
+            items[0].item.feed_item_id,
+            "https://rss20.example.com/posts/1"
+        );
+        assert_eq!(items[0].item.title, "Tom & Jerry (updated)");
+        store(items).await;
+
+        // A new feed item ID is a new item.
+        let added = updated.replace("post-2", "post-3");
+        let (items, _) = extract(&db, &config, added.as_bytes()).await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].status, ItemStatus::New);
+        assert_eq!(items[0].item.feed_item_id, "post-3");
+    }
+
+    #[tokio::test]
+    async fn test_rule_stats() {
+        let db = Db::new_memory("test_rule_stats").await.unwrap();
+        let config = Config::parse_str(
+            r#"
+            [[rule]]
+            name = "all"
+            condition = { field = "feed", regex = '.' }
+            actions = [ "tag:all" ]
+
+            [[rule]]
+            name = "video"
+            condition = { field = "title", keyword = "video" }
+            actions = [ "drop" ]
+            "#,
+        )
+        .unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed(HREF).await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let feed = feeds[0].clone();
+        let stats = RuleStats::new(&config);
+
+        let mut round = async |feed_bytes: &[u8]| {
+            let parsed_feed = parse_feed(HREF, feed_bytes).unwrap();
+            let (items, _) = get_items(&config, &mut conn, &feed, &parsed_feed, now(), &stats)
+                .await
+                .unwrap();
+            let items: Vec<Item> = items.into_iter().map(|i| i.item).collect();
+            conn.update_feed(&feed, &items, None, false).await.unwrap();
+            items.len()
+        };
+
+        assert_eq!(round(&fixture("rss20.xml")).await, 1);
+        assert_eq!(stats.get(0), 1);
+        assert_eq!(stats.get(1), 0);
+
+        // Neither updated nor dropped items are counted.
+        let updated = String::from_utf8(fixture("rss20.xml"))
+            .unwrap()
+            .replace("Tom &amp; Jerry", "Tom &amp; Jerry (updated)");
+        assert_eq!(round(updated.as_bytes()).await, 1);
+        assert_eq!(stats.get(0), 1);
+        assert_eq!(stats.get(1), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let db = Db::new_memory("test_drop_oldest").await.unwrap();
+        let config = Config::parse_str(
+            r#"
+            [[rule]]
+            condition = { field = "title", keyword = "jerry" }
+            actions = [ "drop" ]
+            "#,
+        )
+        .unwrap();
+        // The dropped item is older than the kept one.
+        let (items, oldest) = extract(&db, &config, &fixture("rss20.xml")).await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(oldest, dt("2025-03-02T09:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_feeds_now() {
+        use crate::{daemon::ConfigFile, stub_http::stub_server_body};
+        use tokio::sync::mpsc;
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let (feed_url, _) = stub_server_body(&[200], &fixture("rss20.xml")).await;
+        let (hook_url, mut hook_requests) = stub_server_body(&[200], b"").await;
+        let config = Config::parse_str(&format!("[[webhook]]\nurl = '{hook_url}'\n")).unwrap();
+        let db = Db::new_memory("test_refresh_feeds_now").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.init().await.unwrap();
+        conn.add_feed(&feed_url).await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let feed_id = feeds[0].feed_id.unwrap();
+
+        let (exit_tx, _exit_rx) = mpsc::channel(1);
+        let daemon = Daemon::new(
+            Arc::new(db),
+            config,
+            ConfigFile {
+                path: "/nonexistent/feedreader.conf".into(),
+                strict: false,
+            },
+            exit_tx,
+        );
+
+        // The forced refresh stores the webhook items, but doesn't deliver them.
+        let outcomes = refresh_feeds_now(&daemon, &[feed_id]).await.unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].new_items, 2);
+        assert_eq!(daemon.wait_wakeup().await, Wakeup::Due);
+        assert_eq!(conn.get_outbox_items().await.unwrap().len(), 2);
+        assert!(hook_requests.try_recv().is_err());
+
+        // The refresher delivers them.
+        assert_eq!(deliver_webhooks(&daemon).await.unwrap(), None);
+        let doc: serde_json::Value =
+            serde_json::from_str(&hook_requests.recv().await.unwrap().body).unwrap();
+        assert_eq!(doc["count"], 2);
+        assert!(conn.get_outbox_items().await.unwrap().is_empty());
+        assert!(conn.get_outbox_due(Utc::now()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let db = Db::new_memory("test_dry_run").await.unwrap();
+        let config = Config::parse_str("[db]\ngc-age-offset-days = 10\n").unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed(HREF).await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let feed = feeds[0].clone();
+        let feed_id = feed.feed_id.unwrap();
+
+        // Two seen items from March 2025.
+        let (items, _) = extract(&db, &config, &fixture("rss20.xml")).await;
+        let items: Vec<Item> = items.into_iter().map(|i| i.item).collect();
+        conn.update_feed(&feed, &items, None, false).await.unwrap();
+        conn.feed_set_seen(Some(feed_id)).await.unwrap();
+
+        // The April 2025 items push the March items out of the GC age.
+        let parsed_feed = parse_feed(HREF, &fixture("atom.xml")).unwrap();
+        let DryRun::Items { items, gc_items } =
+            dry_run_items(&config, &mut conn, &feed, &parsed_feed, now())
+                .await
+                .unwrap()
+        else {
+            panic!("Expected items");
+        };
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|(s, _)| *s == ItemStatus::New));
+        let mut gc: Vec<&str> = gc_items.iter().map(|i| i.feed_item_id.as_str()).collect();
+        gc.sort();
+        assert_eq!(gc, ["https://rss20.example.com/posts/1", "post-2"]);
+
+        // Nothing has been written.
+        let (stored, _) = conn.get_feed_items(feed_id, true, 0, None).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        let parsed_feed = parse_feed(HREF, &fixture("atom.xml")).unwrap();
+        let DryRun::Items { items, .. } =
+            dry_run_items(&config, &mut conn, &feed, &parsed_feed, now())
+                .await
+                .unwrap()
+        else {
+            panic!("Expected items");
+        };
+        assert_eq!(items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_highlighting() {
+        let db = Db::new_memory("test_no_highlighting").await.unwrap();
+        let config = Config::parse_str(
+            "[no-highlighting]\n\
+             title = [ '(?i)\\bjerry\\b' ]\n\
+             set-seen = true\n",
+        )
+        .unwrap();
+
+        let (items, _) = extract(&db, &config, &fixture("rss20.xml")).await;
+        assert_eq!(items.len(), 2);
+        assert!(!items[0].highlight);
+        assert!(items[0].item.seen);
+        assert!(items[1].highlight);
+        assert!(!items[1].item.seen);
+        assert_eq!(
+            items[0].item.suppressed_by.as_deref(),
+            Some(r"global/title/(?i)\bjerry\b")
+        );
+        assert_eq!(items[1].item.suppressed_by, None);
+    }
 }
 
 // vim: ts=4 sw=4 expandtab
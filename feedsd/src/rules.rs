@@ -0,0 +1,331 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use feedscfg::{Config, RuleAction, RuleInput};
+use feedsdb::Item;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Match counters of the filter rules in one refresh round.
+///
+/// Only new items that are not dropped are counted, so that an item is
+/// not counted again when it is updated or still in the feed.
+pub struct RuleStats {
+    matches: Vec<AtomicU64>,
+}
+
+impl RuleStats {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            matches: config.rules.iter().map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// Count the matching rules of an item.
+    pub fn count(&self, result: &RuleResult) {
+        for &index in &result.matched {
+            if let Some(m) = self.matches.get(index) {
+                m.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        self.matches
+            .get(index)
+            .map(|m| m.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Log the match statistics of all rules that matched.
+    pub fn log(&self, config: &Config) {
+        for (index, rule) in config.rules.iter().enumerate() {
+            let count = self.get(index);
+            if count > 0 {
+                log::info!("Rule '{}' matched {count} new item(s).", rule.name);
+            }
+        }
+    }
+}
+
+/// The combined actions of all matching rules.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleResult {
+    /// The item shall not be stored.
+    pub drop: bool,
+    pub seen: bool,
+    pub star: bool,
+    pub important: bool,
+    pub tags: Vec<String>,
+    /// The names of the matching rules with the `notify` action.
+    pub notify: Vec<String>,
+    /// The indices of the matching rules in the configuration.
+    pub matched: Vec<usize>,
+}
+
+impl RuleResult {
+    /// Apply the item state changing actions to the `item`.
+    pub fn apply(&self, item: &mut Item) {
+        item.seen |= self.seen;
+        item.starred |= self.star;
+        item.important |= self.important;
+        for tag in &self.tags {
+            if !item.tags.contains(tag) {
+                item.tags.push(tag.clone());
+            }
+        }
+    }
+}
+
+/// Match all rules against the item `input` and collect the actions.
+pub fn match_rules(config: &Config, input: &RuleInput<'_>) -> RuleResult {
+    let mut result = RuleResult::default();
+    for (index, rule) in config.rules.iter().enumerate() {
+        if !rule.matches(input) {
+            continue;
+        }
+        log::debug!("Rule '{}' matches '{}'.", rule.name, input.title);
+        result.matched.push(index);
+
+        for action in &rule.actions {
+            match action {
+                RuleAction::Drop => result.drop = true,
+                RuleAction::Seen => result.seen = true,
+                RuleAction::Star => result.star = true,
+                RuleAction::Tag(tag) => {
+                    if !result.tags.contains(tag) {
+                        result.tags.push(tag.clone());
+                    }
+                }
+                RuleAction::Important => result.important = true,
+                RuleAction::Notify => {
+                    if !result.notify.contains(&rule.name) {
+                        result.notify.push(rule.name.clone());
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::item;
+
+    fn apply_rules(
+        config: &Config,
+        stats: &RuleStats,
+        input: &RuleInput<'_>,
+        item: &mut Item,
+    ) -> RuleResult {
+        let result = match_rules(config, input);
+        stats.count(&result);
+        result.apply(item);
+        result
+    }
+
+    fn input<'a>(title: &'a str, url: &'a str, categories: &'a [String]) -> RuleInput<'a> {
+        RuleInput {
+            title,
+            summary: "Some summary text",
+            url,
+            author: "Alice",
+            feed_id: Some(1),
+            feed_title: "Example News",
+            feed_href: "https://example.com/feed.xml",
+            categories,
+        }
+    }
+
+    const CONFIG: &str = r##"
+        [[rule]]
+        name = "shorts"
+        condition = { any = [
+            { field = "url", regex = 'youtube\.com/shorts/' },
+            { field = "title", keyword = "#short" },
+        ] }
+        actions = [ "drop" ]
+
+        [[rule]]
+        name = "rust"
+        condition = { all = [
+            { field = "title", keyword = "rust" },
+            { not = { field = "category", regex = '(?i)^games?$' } },
+        ] }
+        actions = [ "star", "tag:rust", "important", "notify" ]
+
+        [[rule]]
+        name = "other-feed"
+        feed = 'other\.org'
+        condition = { field = "author", regex = 'Alice' }
+        actions = [ "seen" ]
+
+        [[rule]]
+        name = "example-feed"
+        condition = { field = "feed", keyword = "example news" }
+        actions = [ "tag:example", "tag:rust" ]
+    "##;
+
+    #[test]
+    fn test_rules() {
+        let config = Config::parse_str(CONFIG).unwrap();
+        assert_eq!(config.rules.len(), 4);
+        let stats = RuleStats::new(&config);
+
+        let mut i = item("", "", "");
+        let r = apply_rules(
+            &config,
+            &stats,
+            &input("A video", "https://youtube.com/shorts/abc", &[]),
+            &mut i,
+        );
+        assert!(r.drop);
+        assert!(r.notify.is_empty());
+
+        let mut i = item("", "", "");
+        let r = apply_rules(
+            &config,
+            &stats,
+            &input("A video #Short", "https://youtube.com/watch", &[]),
+            &mut i,
+        );
+        assert!(r.drop);
+
+        let mut i = item("", "", "");
+        let r = apply_rules(
+            &config,
+            &stats,
+            &input("Rust 2.0 released", "https://example.com/1", &[]),
+            &mut i,
+        );
+        assert!(!r.drop);
+        assert!(!r.seen);
+        assert_eq!(r.notify, ["rust"]);
+        assert!(i.starred);
+        assert!(i.important);
+        assert!(!i.seen);
+        assert_eq!(i.tags, vec!["rust".to_string(), "example".to_string()]);
+
+        // NOT condition.
+        let mut i = item("", "", "");
+        let r = apply_rules(
+            &config,
+            &stats,
+            &input("Rust game", "https://example.com/2", &["Games".to_string()]),
+            &mut i,
+        );
+        assert!(r.notify.is_empty());
+        assert!(!i.starred);
+
+        // Keywords match whole words only.
+        let mut i = item("", "", "");
+        apply_rules(
+            &config,
+            &stats,
+            &input("Trusty", "https://example.com/3", &[]),
+            &mut i,
+        );
+        assert!(!i.starred);
+
+        assert_eq!(stats.get(0), 2);
+        assert_eq!(stats.get(1), 1);
+        assert_eq!(stats.get(2), 0);
+        assert_eq!(stats.get(3), 5);
+    }
+
+    #[test]
+    fn test_feed_scope() {
+        let config = Config::parse_str(CONFIG).unwrap();
+        let stats = RuleStats::new(&config);
+
+        let mut inp = input("Title", "https://other.org/1", &[]);
+        inp.feed_href = "https://other.org/feed";
+        inp.feed_title = "Other";
+        let mut i = item("", "", "");
+        let r = apply_rules(&config, &stats, &inp, &mut i);
+        assert!(r.seen);
+        assert!(i.seen);
+        assert!(i.tags.is_empty());
+        assert_eq!(stats.get(2), 1);
+
+        let config = Config::parse_str(
+            r#"
+            [[rule]]
+            name = "by-id"
+            feed-id = 7
+            condition = { field = "title", regex = '.' }
+            actions = [ "star" ]
+
+            [[rule]]
+            name = "by-id-or-href"
+            feed = 'other\.org'
+            feed-id = 7
+            condition = { field = "title", regex = '.' }
+            actions = [ "tag:scoped" ]
+            "#,
+        )
+        .unwrap();
+        let stats = RuleStats::new(&config);
+
+        let mut inp = input("Title", "https://example.com/1", &[]);
+        inp.feed_id = Some(7);
+        let mut i = item("", "", "");
+        apply_rules(&config, &stats, &inp, &mut i);
+        assert!(i.starred);
+        assert_eq!(i.tags, ["scoped"]);
+
+        inp.feed_id = Some(8);
+        let mut i = item("", "", "");
+        apply_rules(&config, &stats, &inp, &mut i);
+        assert!(!i.starred);
+        assert!(i.tags.is_empty());
+
+        inp.feed_href = "https://other.org/feed";
+        let mut i = item("", "", "");
+        apply_rules(&config, &stats, &inp, &mut i);
+        assert!(!i.starred);
+        assert_eq!(i.tags, ["scoped"]);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let invalid = [
+            // No condition.
+            "[[rule]]\nactions = ['drop']\n",
+            // No actions.
+            "[[rule]]\ncondition = { field = 'title', regex = 'x' }\n",
+            // Unknown action.
+            "[[rule]]\ncondition = { field = 'title', regex = 'x' }\nactions = ['explode']\n",
+            // Unknown field.
+            "[[rule]]\ncondition = { field = 'color', regex = 'x' }\nactions = ['drop']\n",
+            // Regex and keyword.
+            "[[rule]]\ncondition = { field = 'title', regex = 'x', keyword = 'x' }\nactions = ['drop']\n",
+            // Mixed operators.
+            "[[rule]]\ncondition = { all = [], any = [] }\nactions = ['drop']\n",
+            // Invalid tag.
+            "[[rule]]\ncondition = { field = 'title', regex = 'x' }\nactions = ['tag:a,b']\n",
+        ];
+        for s in invalid {
+            assert!(Config::parse_str(s).is_err(), "{s}");
+        }
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,215 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow as ah;
+use chrono::{DateTime, Utc};
+use feedsctl::{DaemonStatus, FeedError, FeedRef};
+use feedsdb::Feed;
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+struct StatusInner {
+    config_loaded: DateTime<Utc>,
+    last_refresh: Option<DateTime<Utc>>,
+    next_due: Option<DateTime<Utc>>,
+    in_flight: BTreeMap<i64, String>,
+    errors: BTreeMap<i64, FeedError>,
+    /// The time of the last progress of the running refresh round.
+    /// None, if no refresh round is running.
+    round_progress: Option<Instant>,
+}
+
+/// The refresh status of the daemon, as reported on the control socket.
+pub struct Status {
+    started: DateTime<Utc>,
+    inner: Mutex<StatusInner>,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            started: now,
+            inner: Mutex::new(StatusInner {
+                config_loaded: now,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn set_config_loaded(&self, time: DateTime<Utc>) {
+        self.inner.lock().unwrap().config_loaded = time;
+    }
+
+    pub fn set_refreshed(&self, time: DateTime<Utc>, next_due: DateTime<Utc>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_refresh = Some(time);
+        inner.next_due = Some(next_due);
+    }
+
+    /// Mark the start of a refresh round.
+    pub fn begin_round(&self) {
+        self.inner.lock().unwrap().round_progress = Some(Instant::now());
+    }
+
+    /// Mark the end of a refresh round.
+    pub fn end_round(&self) {
+        self.inner.lock().unwrap().round_progress = None;
+    }
+
+    /// Record progress of the running refresh round.
+    pub fn progress(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.round_progress.is_some() {
+            inner.round_progress = Some(Instant::now());
+        }
+    }
+
+    /// Check whether the running refresh round did not make progress for `timeout`.
+    ///
+    /// Each finished feed and each webhook delivery attempt counts as progress.
+    pub fn is_stalled(&self, timeout: Duration) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .round_progress
+            .is_some_and(|p| p.elapsed() >= timeout)
+    }
+
+    /// Mark the feed as being refreshed.
+    ///
+    /// Returns false, if the feed is already being refreshed.
+    pub fn begin_feed(&self, feed: &Feed) -> bool {
+        let Some(feed_id) = feed.feed_id else {
+            return true;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        if inner.in_flight.contains_key(&feed_id) {
+            return false;
+        }
+        inner.in_flight.insert(feed_id, feed.href.clone());
+        true
+    }
+
+    /// Mark the refresh of the feed as finished and record its result.
+    pub fn end_feed<T>(&self, feed: &Feed, result: &ah::Result<T>) {
+        let Some(feed_id) = feed.feed_id else {
+            return;
+        };
+        self.progress();
+        let mut inner = self.inner.lock().unwrap();
+        inner.in_flight.remove(&feed_id);
+        match result {
+            Ok(_) => {
+                inner.errors.remove(&feed_id);
+            }
+            Err(e) => {
+                inner.errors.insert(
+                    feed_id,
+                    FeedError {
+                        feed_id,
+                        href: feed.href.clone(),
+                        time: Utc::now(),
+                        error: format!("{e:#}"),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn get(&self) -> DaemonStatus {
+        let inner = self.inner.lock().unwrap();
+        DaemonStatus {
+            pid: std::process::id(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started: self.started,
+            config_loaded: inner.config_loaded,
+            last_refresh: inner.last_refresh,
+            next_due: inner.next_due,
+            in_flight: inner
+                .in_flight
+                .iter()
+                .map(|(feed_id, href)| FeedRef {
+                    feed_id: *feed_id,
+                    href: href.clone(),
+                })
+                .collect(),
+            errors: inner.errors.values().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::format_err as err;
+    use feedsdb::test_util;
+
+    fn feed(feed_id: i64) -> Feed {
+        test_util::feed(Some(feed_id), &format!("https://example.com/{feed_id}"))
+    }
+
+    #[test]
+    fn test_status() {
+        let status = Status::new();
+        assert!(status.begin_feed(&feed(1)));
+        assert!(status.begin_feed(&feed(2)));
+        assert!(!status.begin_feed(&feed(1)));
+        let s = status.get();
+        assert_eq!(s.in_flight.len(), 2);
+        assert_eq!(s.in_flight[0].href, "https://example.com/1");
+
+        status.end_feed(&feed(1), &Err::<(), _>(err!("HTTP 404")));
+        status.end_feed(&feed(2), &Ok(()));
+        let s = status.get();
+        assert!(s.in_flight.is_empty());
+        assert_eq!(s.errors.len(), 1);
+        assert_eq!(s.errors[0].feed_id, 1);
+        assert_eq!(s.errors[0].error, "HTTP 404");
+
+        // A successful refresh clears the error.
+        assert!(status.begin_feed(&feed(1)));
+        status.end_feed(&feed(1), &Ok(()));
+        assert!(status.get().errors.is_empty());
+    }
+
+    #[test]
+    fn test_stalled() {
+        let status = Status::new();
+        assert!(!status.is_stalled(Duration::ZERO));
+        status.begin_round();
+        assert!(status.is_stalled(Duration::ZERO));
+        assert!(!status.is_stalled(Duration::from_secs(60)));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(status.is_stalled(Duration::from_millis(10)));
+        status.progress();
+        assert!(!status.is_stalled(Duration::from_millis(10)));
+        status.end_round();
+        assert!(!status.is_stalled(Duration::ZERO));
+        // No progress outside of a refresh round.
+        status.progress();
+        assert!(!status.is_stalled(Duration::ZERO));
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
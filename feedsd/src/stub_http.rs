@@ -0,0 +1,111 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Stub HTTP server for the notification tests.
+
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+};
+
+/// A request received by the stub server.
+#[derive(Clone, Debug)]
+pub struct StubRequest {
+    /// The header lines, without the request line.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Start a stub HTTP server on localhost.
+///
+/// The n-th request is answered with the n-th status code of `statuses`.
+/// The last status code is used for all further requests.
+/// Returns the URL of the server and the received requests.
+pub async fn stub_server(statuses: &[u16]) -> (String, mpsc::UnboundedReceiver<StubRequest>) {
+    stub_server_body(statuses, b"").await
+}
+
+/// Like [stub_server], but all responses have the `body`.
+pub async fn stub_server_body(
+    statuses: &[u16],
+    body: &[u8],
+) -> (String, mpsc::UnboundedReceiver<StubRequest>) {
+    let resp_body = body.to_vec();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let statuses = statuses.to_vec();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for n in 0.. {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut headers = vec![];
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap(); // request line
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((k, v)) = line.split_once(':') {
+                    headers.push((k.trim().to_string(), v.trim().to_string()));
+                }
+            }
+            let req = StubRequest {
+                headers,
+                body: String::new(),
+            };
+            let len = req
+                .header("content-length")
+                .map(|l| l.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body).await.unwrap();
+
+            let status = statuses.get(n).or(statuses.last()).copied().unwrap_or(200);
+            let resp = format!(
+                "HTTP/1.1 {status} Stub\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                resp_body.len()
+            );
+            stream.get_mut().write_all(resp.as_bytes()).await.unwrap();
+            stream.get_mut().write_all(&resp_body).await.unwrap();
+
+            let _ = tx.send(StubRequest {
+                body: String::from_utf8(body).unwrap(),
+                ..req
+            });
+        }
+    });
+    (url, rx)
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -18,10 +18,45 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use anyhow as ah;
+use sd_notify::NotifyState;
+use std::time::Duration;
 
 /// Notify ready-status to systemd.
 pub fn systemd_notify_ready() -> ah::Result<()> {
-    sd_notify::notify(&[sd_notify::NotifyState::Ready])?;
+    sd_notify::notify(&[NotifyState::Ready])?;
+    Ok(())
+}
+
+/// Notify a human readable status line to systemd.
+///
+/// It is shown by `systemctl status`.
+pub fn systemd_notify_status(status: &str) -> ah::Result<()> {
+    sd_notify::notify(&[NotifyState::Status(status)])?;
+    Ok(())
+}
+
+/// Notify systemd that the configuration is being reloaded.
+///
+/// The reload is finished with [systemd_notify_ready].
+pub fn systemd_notify_reloading() -> ah::Result<()> {
+    sd_notify::notify(&[NotifyState::Reloading, NotifyState::monotonic_usec_now()?])?;
+    Ok(())
+}
+
+/// Notify systemd that the daemon is shutting down.
+pub fn systemd_notify_stopping() -> ah::Result<()> {
+    sd_notify::notify(&[NotifyState::Stopping])?;
+    Ok(())
+}
+
+/// Get the watchdog timeout, if systemd has enabled the watchdog for this process.
+pub fn systemd_watchdog_timeout() -> Option<Duration> {
+    sd_notify::watchdog_enabled()
+}
+
+/// Send a watchdog keep-alive to systemd.
+pub fn systemd_notify_watchdog() -> ah::Result<()> {
+    sd_notify::notify(&[NotifyState::Watchdog])?;
     Ok(())
 }
 
@@ -0,0 +1,430 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::{DateTime, TimeDelta, Utc};
+use feedscfg::{Config, ConfigWebhook};
+use feedsdb::{DbConn, Feed, Item, OutboxEntry, OutboxItem};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The maximum delay between two delivery attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A new highlighted item for the webhooks.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookItem {
+    pub feed_id: Option<i64>,
+    pub feed_title: String,
+    pub feed_url: String,
+    pub title: String,
+    pub url: String,
+    pub author: String,
+    pub published: String,
+    pub summary: String,
+}
+
+impl WebhookItem {
+    pub fn new(feed: &Feed, item: &Item) -> Self {
+        Self {
+            feed_id: feed.feed_id,
+            feed_title: feed.title.clone(),
+            feed_url: feed.href.clone(),
+            title: item.title.clone(),
+            url: item.link.clone(),
+            author: item.author.clone(),
+            published: item.published.to_rfc3339(),
+            summary: item.summary.clone(),
+        }
+    }
+}
+
+/// The context of the payload template.
+/// Without template, this is sent as-is.
+#[derive(Serialize)]
+struct Payload<'a> {
+    webhook: &'a str,
+    count: usize,
+    items: &'a [&'a WebhookItem],
+}
+
+/// Render the JSON payload of one request.
+fn render(webhook: &ConfigWebhook, items: &[&WebhookItem]) -> ah::Result<String> {
+    let payload = Payload {
+        webhook: &webhook.name,
+        count: items.len(),
+        items,
+    };
+    let Some(template) = &webhook.template else {
+        return Ok(serde_json::to_string(&payload)?);
+    };
+    let doc = minijinja::Environment::new()
+        .render_str(template, &payload)
+        .context("Render payload template")?;
+    serde_json::from_str::<serde_json::Value>(&doc)
+        .context("The rendered payload template is not valid JSON")?;
+    Ok(doc)
+}
+
+/// The delay before the next attempt after `attempts` failed attempts.
+fn backoff(webhook: &ConfigWebhook, attempts: i64) -> Duration {
+    let exp = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    webhook.backoff.saturating_mul(1 << exp).min(MAX_BACKOFF)
+}
+
+/// Build the outbox items of all webhooks for the new items of a feed.
+pub fn outbox_items(config: &Config, items: &[WebhookItem], now: DateTime<Utc>) -> Vec<OutboxItem> {
+    let mut outbox_items = vec![];
+    for webhook in &config.webhooks {
+        for item in items.iter().filter(|i| webhook.applies_to(&i.feed_url)) {
+            match serde_json::to_string(item) {
+                Ok(item) => outbox_items.push(OutboxItem {
+                    outbox_item_id: None,
+                    endpoint: webhook.name.clone(),
+                    item,
+                    created: now,
+                }),
+                Err(e) => log::error!("Webhook '{}': {e}", webhook.name),
+            }
+        }
+    }
+    outbox_items
+}
+
+/// Batch the outbox items of all feeds into requests of at most `max-items` items.
+async fn batch(config: &Config, conn: &mut DbConn, now: DateTime<Utc>) -> ah::Result<()> {
+    let outbox_items = conn.get_outbox_items().await.context("Get outbox items")?;
+    if outbox_items.is_empty() {
+        return Ok(());
+    }
+    let mut entries = vec![];
+    for webhook in &config.webhooks {
+        let items: Vec<WebhookItem> = outbox_items
+            .iter()
+            .filter(|i| i.endpoint == webhook.name)
+            .filter_map(|i| match serde_json::from_str(&i.item) {
+                Ok(item) => Some(item),
+                Err(e) => {
+                    log::error!("Webhook '{}': Invalid outbox item: {e}", webhook.name);
+                    None
+                }
+            })
+            .collect();
+        let items: Vec<&WebhookItem> = items.iter().collect();
+        for batch in items.chunks(webhook.max_items.get()) {
+            match render(webhook, batch) {
+                Ok(payload) => entries.push(OutboxEntry {
+                    outbox_id: None,
+                    endpoint: webhook.name.clone(),
+                    payload,
+                    created: now,
+                    attempts: 0,
+                    next_attempt: now,
+                    last_error: None,
+                }),
+                Err(e) => log::error!("Webhook '{}': {e:?}", webhook.name),
+            }
+        }
+    }
+    for item in &outbox_items {
+        if !config.webhooks.iter().any(|w| w.name == item.endpoint) {
+            log::warn!(
+                "Webhook '{}' is not configured anymore. Dropping item.",
+                item.endpoint
+            );
+        }
+    }
+    conn.batch_outbox_items(&outbox_items, &entries)
+        .await
+        .context("Batch outbox items")
+}
+
+async fn post(config: &Config, webhook: &ConfigWebhook, payload: &str) -> ah::Result<()> {
+    use reqwest::{Client, header::CONTENT_TYPE};
+
+    let client = Client::builder()
+        .user_agent(concat!("feedreader/", env!("CARGO_PKG_VERSION")))
+        .timeout(config.net.timeout)
+        .build()
+        .context("Webhook")?;
+    let mut req = client
+        .post(&webhook.url)
+        .header(CONTENT_TYPE, "application/json");
+    for (name, value) in &webhook.headers {
+        req = req.header(name, value);
+    }
+    // The error is logged and stored in the outbox. Strip the URL, which may contain a secret.
+    let resp = req
+        .body(payload.to_string())
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    if !resp.status().is_success() {
+        return Err(err!("HTTP status {}", resp.status()));
+    }
+    Ok(())
+}
+
+/// Batch the new outbox items and deliver all due outbox entries.
+///
+/// This is called once per refresh round, so that the new items
+/// of all feeds of the round are sent together.
+/// `progress` is called after each delivery attempt.
+/// Returns the time of the next pending delivery attempt.
+pub async fn deliver(
+    config: &Config,
+    conn: &mut DbConn,
+    now: DateTime<Utc>,
+    progress: impl Fn(),
+) -> ah::Result<Option<DateTime<Utc>>> {
+    batch(config, conn, now).await?;
+    for mut entry in conn.get_outbox_due(now).await.context("Get outbox")? {
+        let outbox_id = entry.outbox_id.unwrap_or_default();
+        let Some(webhook) = config.webhooks.iter().find(|w| w.name == entry.endpoint) else {
+            log::warn!(
+                "Webhook '{}' is not configured anymore. Dropping notification.",
+                entry.endpoint
+            );
+            conn.delete_outbox(outbox_id).await?;
+            continue;
+        };
+
+        match post(config, webhook, &entry.payload).await {
+            Ok(()) => {
+                log::debug!("Webhook '{}': Delivered.", webhook.name);
+                conn.delete_outbox(outbox_id).await?;
+            }
+            Err(e) => {
+                entry.attempts += 1;
+                if entry.attempts >= webhook.max_attempts as i64 {
+                    log::error!(
+                        "Webhook '{}': Delivery failed {} times. Giving up: {e}",
+                        webhook.name,
+                        entry.attempts
+                    );
+                    conn.delete_outbox(outbox_id).await?;
+                } else {
+                    let delay = backoff(webhook, entry.attempts);
+                    log::warn!(
+                        "Webhook '{}': Delivery failed: {e}. Retrying in {delay:?}.",
+                        webhook.name
+                    );
+                    entry.next_attempt = now + TimeDelta::from_std(delay)?;
+                    entry.last_error = Some(e.to_string());
+                    conn.update_outbox(&entry).await?;
+                }
+            }
+        }
+        progress();
+    }
+    conn.get_outbox_next_attempt()
+        .await
+        .context("Get next outbox attempt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub_http::stub_server;
+    use feedsdb::{Db, test_util::now};
+
+    fn item(n: usize) -> WebhookItem {
+        WebhookItem {
+            feed_id: Some(1),
+            feed_title: "News".to_string(),
+            feed_url: "https://news.example.com/feed".to_string(),
+            title: format!("Item {n}"),
+            url: format!("https://news.example.com/{n}"),
+            author: "".to_string(),
+            published: "2027-01-15T08:00:00+00:00".to_string(),
+            summary: "".to_string(),
+        }
+    }
+
+    fn config(url: &str, extra: &str) -> Config {
+        let mut config = Config::parse_str(&format!(
+            r#"
+            [[webhook]]
+            name = "chat"
+            url = "{url}"
+            max-items = 2
+            max-attempts = 3
+            backoff-secs = 60
+            headers = {{ Authorization = "Bearer secret" }}
+            {extra}
+            "#
+        ))
+        .unwrap();
+        config.net.timeout = Duration::from_secs(5);
+        config
+    }
+
+    /// Store the new `items` of the feed `href` like a refresh does.
+    async fn enqueue(conn: &mut DbConn, c: &Config, href: &str, items: &[WebhookItem]) {
+        conn.add_feed(href).await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let feed = feeds.into_iter().find(|f| f.href == href).unwrap();
+        conn.update_feed_with_outbox(&feed, &[], None, false, &outbox_items(c, items, now()))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_render() {
+        let items = [item(1), item(2)];
+        let items: Vec<&WebhookItem> = items.iter().collect();
+
+        let c = config("http://localhost/", "");
+        let doc: serde_json::Value =
+            serde_json::from_str(&render(&c.webhooks[0], &items).unwrap()).unwrap();
+        assert_eq!(doc["webhook"], "chat");
+        assert_eq!(doc["count"], 2);
+        assert_eq!(doc["items"][1]["title"], "Item 2");
+        assert_eq!(doc["items"][1]["feed_title"], "News");
+
+        let c = config(
+            "http://localhost/",
+            r#"template = '{"text": {{ (items | map(attribute="title") | join(", ")) | tojson }}}'"#,
+        );
+        assert_eq!(
+            render(&c.webhooks[0], &items).unwrap(),
+            r#"{"text": "Item 1, Item 2"}"#
+        );
+
+        // The template must produce JSON.
+        let c = config("http://localhost/", r#"template = 'text {{ count }}'"#);
+        assert!(render(&c.webhooks[0], &items).is_err());
+        // Syntax errors are detected while parsing the configuration.
+        assert!(Config::parse_str("[[webhook]]\nurl = 'http://x/'\ntemplate = '{{ x'\n").is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let c = config("http://localhost/", "");
+        let w = &c.webhooks[0];
+        assert_eq!(backoff(w, 1), Duration::from_secs(60));
+        assert_eq!(backoff(w, 2), Duration::from_secs(120));
+        assert_eq!(backoff(w, 3), Duration::from_secs(240));
+        assert_eq!(backoff(w, 100), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_outbox_delivery() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        // The first request fails.
+        let (url, mut requests) = stub_server(&[500, 200]).await;
+        let c = config(&url, "");
+        let db = Db::new_memory("test_outbox_delivery").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+
+        // Three items of two feeds in batches of two.
+        enqueue(&mut conn, &c, "https://a.example.com/feed", &[item(1)]).await;
+        enqueue(
+            &mut conn,
+            &c,
+            "https://b.example.com/feed",
+            &[item(2), item(3)],
+        )
+        .await;
+        assert_eq!(conn.get_outbox_items().await.unwrap().len(), 3);
+        assert!(conn.get_outbox_due(now()).await.unwrap().is_empty());
+
+        // Each attempt is reported as progress.
+        let attempts = std::cell::Cell::new(0);
+        let next = deliver(&c, &mut conn, now(), || attempts.set(attempts.get() + 1))
+            .await
+            .unwrap();
+        assert_eq!(next, Some(now() + TimeDelta::seconds(60)));
+        assert_eq!(attempts.get(), 2);
+        assert!(conn.get_outbox_items().await.unwrap().is_empty());
+
+        let req = requests.recv().await.unwrap();
+        assert_eq!(req.header("authorization"), Some("Bearer secret"));
+        assert_eq!(req.header("content-type"), Some("application/json"));
+        let doc: serde_json::Value = serde_json::from_str(&req.body).unwrap();
+        assert_eq!(doc["count"], 2);
+        let req = requests.recv().await.unwrap();
+        let doc: serde_json::Value = serde_json::from_str(&req.body).unwrap();
+        assert_eq!(doc["count"], 1);
+        assert_eq!(doc["items"][0]["title"], "Item 3");
+
+        // The failed batch is kept for a retry.
+        let pending = conn.get_outbox_due(next.unwrap()).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(
+            pending[0].last_error.as_deref(),
+            Some("HTTP status 500 Internal Server Error")
+        );
+
+        // Not due yet.
+        assert_eq!(deliver(&c, &mut conn, now(), || ()).await.unwrap(), next);
+        assert!(requests.try_recv().is_err());
+
+        // The retry succeeds.
+        assert_eq!(
+            deliver(&c, &mut conn, next.unwrap(), || ()).await.unwrap(),
+            None
+        );
+        let doc: serde_json::Value =
+            serde_json::from_str(&requests.recv().await.unwrap().body).unwrap();
+        assert_eq!(doc["count"], 2);
+        assert_eq!(doc["items"][0]["title"], "Item 1");
+        assert_eq!(doc["items"][1]["title"], "Item 2");
+    }
+
+    #[tokio::test]
+    async fn test_outbox_give_up() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let (url, _requests) = stub_server(&[503]).await;
+        let c = config(&url, "feed = 'news\\.example\\.com'");
+        let db = Db::new_memory("test_outbox_give_up").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+
+        let mut other = item(2);
+        other.feed_url = "https://other.example.com/feed".to_string();
+        enqueue(
+            &mut conn,
+            &c,
+            "https://a.example.com/feed",
+            &[item(1), other],
+        )
+        .await;
+        let items = conn.get_outbox_items().await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].endpoint, "chat");
+
+        let mut t = now();
+        for _ in 0..2 {
+            t = deliver(&c, &mut conn, t, || ()).await.unwrap().unwrap();
+        }
+        // The third failure is the last attempt.
+        assert_eq!(deliver(&c, &mut conn, t, || ()).await.unwrap(), None);
+
+        // Items of removed webhooks are dropped.
+        enqueue(&mut conn, &c, "https://b.example.com/feed", &[item(1)]).await;
+        let c = Config::default();
+        assert_eq!(deliver(&c, &mut conn, now(), || ()).await.unwrap(), None);
+        assert!(conn.get_outbox_items().await.unwrap().is_empty());
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
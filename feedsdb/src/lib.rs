@@ -21,6 +21,8 @@
 #![forbid(unsafe_code)]
 
 mod error;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
 use crate::error::Error;
 use anyhow::{self as ah, Context as _, format_err as err};
@@ -29,15 +31,41 @@ use rusqlite::{Connection, OpenFlags, Row};
 use sha2::{Digest as _, Sha256};
 use std::{
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
     time::{Duration, Instant},
 };
 use tokio::task::spawn_blocking;
 
 const TIMEOUT: Duration = Duration::from_millis(10_000);
 
+/// The number of transactions that were retried, because the database was busy.
+static BUSY_RETRIES: AtomicU64 = AtomicU64::new(0);
+
 // Keys for the global kv_int_int key-value store.
 const KV_KEY_FEED_UPDATE_REV: i64 = 1;
+const KV_KEY_LAST_DIGEST: i64 = 2;
+
+/// The condition of the items that are garbage collected.
+/// The parameters are the `feed_id` and the garbage collection threshold.
+const GC_ITEMS_CONDITION: &str = "\
+    feed_id = ? AND \
+    published < ? AND \
+    seen = TRUE AND \
+    starred = FALSE\
+";
+
+/// The columns that are checked by [DbConn::check_schema].
+/// These are the newest columns of each table.
+const SCHEMA_COLUMNS: &[(&str, &[&str])] = &[
+    ("feeds", &["feed_id", "updated_items"]),
+    ("items", &["starred", "tags", "important", "suppressed_by"]),
+    ("kv_int_int", &["key", "value"]),
+    ("outbox", &["outbox_id", "last_error"]),
+    ("outbox_items", &["outbox_item_id", "created"]),
+];
 
 pub fn get_prefix() -> PathBuf {
     option_env!("FEEDREADER_PREFIX").unwrap_or("/").into()
@@ -47,6 +75,11 @@ pub fn get_varlib() -> PathBuf {
     get_prefix().join("var/lib/feedreader")
 }
 
+/// Get the number of transactions that were retried, because the database was busy.
+pub fn busy_retries() -> u64 {
+    BUSY_RETRIES.load(Ordering::Relaxed)
+}
+
 fn sql_to_dt(timestamp: i64) -> DateTime<Utc> {
     DateTime::<Utc>::from_timestamp(timestamp, 0).unwrap_or_else(Utc::now)
 }
@@ -99,6 +132,11 @@ pub struct Item {
     pub link: String,
     pub published: DateTime<Utc>,
     pub summary: String,
+    pub starred: bool,
+    pub important: bool,
+    pub tags: Vec<String>,
+    /// The no-highlighting rule that suppressed the highlighting of the item.
+    pub suppressed_by: Option<String>,
 }
 
 impl Item {
@@ -114,13 +152,17 @@ impl Item {
             link: row.get(7)?,
             published: sql_to_dt(row.get(8)?),
             summary: row.get(9)?,
+            starred: row.get(10)?,
+            tags: tags_from_sql(row.get(11)?),
+            important: row.get(12)?,
+            suppressed_by: row.get(13)?,
         })
     }
 
     fn from_sql_row_extended(row: &Row<'_>) -> rusqlite::Result<(Self, ItemExt)> {
-        let count: i64 = row.get(10)?;
-        let max_seen: bool = row.get(11)?;
-        let sum_seen: i64 = row.get(12)?;
+        let count: i64 = row.get(14)?;
+        let max_seen: bool = row.get(15)?;
+        let sum_seen: i64 = row.get(16)?;
         Ok((
             Self::from_sql_row(row)?,
             ItemExt {
@@ -143,6 +185,63 @@ impl Item {
     }
 }
 
+fn tags_to_sql(tags: &[String]) -> String {
+    tags.join(",")
+}
+
+fn tags_from_sql(tags: Option<String>) -> Vec<String> {
+    tags.unwrap_or_default()
+        .split(',')
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Select the newest version of each item together with the state of all versions.
+///
+/// The `{cond}` placeholder must be replaced with the condition
+/// for the item versions to consider.
+/// The columns match [Item::from_sql_row_extended].
+const SELECT_NEWEST_ITEMS: &str = "\
+    SELECT \
+        items.item_id, \
+        items.feed_id, \
+        items.retrieved, \
+        items.seen, \
+        items.author, \
+        items.title, \
+        items.feed_item_id, \
+        items.link, \
+        items.published, \
+        items.summary, \
+        g.starred, \
+        items.tags, \
+        g.important, \
+        items.suppressed_by, \
+        g.count, \
+        g.any_seen, \
+        g.sum_seen \
+    FROM items \
+    JOIN (\
+        SELECT \
+            feed_id, \
+            feed_item_id, \
+            max(retrieved) AS retrieved, \
+            max(starred) AS starred, \
+            max(important) AS important, \
+            count() AS count, \
+            max(seen) AS any_seen, \
+            sum(seen) AS sum_seen \
+        FROM items \
+        WHERE {cond} \
+        GROUP BY feed_id, feed_item_id\
+    ) AS g \
+    ON \
+        items.feed_id = g.feed_id AND \
+        items.feed_item_id = g.feed_item_id AND \
+        items.retrieved = g.retrieved\
+";
+
 #[derive(Clone, Debug)]
 pub struct ItemExt {
     pub count: i64,
@@ -150,6 +249,30 @@ pub struct ItemExt {
     pub all_seen: bool,
 }
 
+#[derive(Clone, Debug)]
+pub struct ItemsExt {
+    pub more_items: bool,
+}
+
+/// Item selection for [DbConn::get_items_filtered].
+#[derive(Clone, Debug, Default)]
+pub struct ItemFilter {
+    /// Only items from these feeds. All feeds, if empty.
+    pub feed_ids: Vec<i64>,
+    /// Only starred items.
+    pub starred: bool,
+    /// Only items that contain this text in the title or summary.
+    pub search: Option<String>,
+    /// Only items with this tag.
+    pub tag: Option<String>,
+    /// Only items of which no version has been seen.
+    pub unseen: bool,
+    /// Only items that have been retrieved after this time.
+    pub retrieved_since: Option<DateTime<Utc>>,
+    /// Maximum number of items.
+    pub limit: Option<usize>,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ItemStatus {
     New,
@@ -157,6 +280,91 @@ pub enum ItemStatus {
     Exists,
 }
 
+/// A pending notification in the persistent outbox.
+#[derive(Clone, Debug)]
+pub struct OutboxEntry {
+    pub outbox_id: Option<i64>,
+    /// The name of the endpoint the payload is delivered to.
+    pub endpoint: String,
+    pub payload: String,
+    pub created: DateTime<Utc>,
+    /// The number of failed delivery attempts.
+    pub attempts: i64,
+    pub next_attempt: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+impl OutboxEntry {
+    fn from_sql_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            outbox_id: Some(row.get(0)?),
+            endpoint: row.get(1)?,
+            payload: row.get(2)?,
+            created: sql_to_dt(row.get(3)?),
+            attempts: row.get(4)?,
+            next_attempt: sql_to_dt(row.get(5)?),
+            last_error: row.get(6)?,
+        })
+    }
+}
+
+/// An item that waits in the outbox to be batched into [OutboxEntry]s.
+#[derive(Clone, Debug)]
+pub struct OutboxItem {
+    pub outbox_item_id: Option<i64>,
+    /// The name of the endpoint the item is delivered to.
+    pub endpoint: String,
+    pub item: String,
+    pub created: DateTime<Utc>,
+}
+
+impl OutboxItem {
+    fn from_sql_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            outbox_item_id: Some(row.get(0)?),
+            endpoint: row.get(1)?,
+            item: row.get(2)?,
+            created: sql_to_dt(row.get(3)?),
+        })
+    }
+}
+
+/// Insert the `items` into the outbox items.
+fn insert_outbox_items(t: &rusqlite::Transaction, items: &[OutboxItem]) -> Result<(), Error> {
+    for item in items {
+        t.prepare_cached(
+            "\
+                INSERT INTO outbox_items (endpoint, item, created) \
+                VALUES (?, ?, ?)\
+            ",
+        )?
+        .execute((&item.endpoint, &item.item, dt_to_sql(&item.created)))?;
+    }
+    Ok(())
+}
+
+/// Insert the `entries` into the outbox.
+fn insert_outbox(t: &rusqlite::Transaction, entries: &[OutboxEntry]) -> Result<(), Error> {
+    for entry in entries {
+        t.prepare_cached(
+            "\
+                INSERT INTO outbox \
+                    (endpoint, payload, created, attempts, next_attempt, last_error) \
+                VALUES (?, ?, ?, ?, ?, ?)\
+            ",
+        )?
+        .execute((
+            &entry.endpoint,
+            &entry.payload,
+            dt_to_sql(&entry.created),
+            entry.attempts,
+            dt_to_sql(&entry.next_attempt),
+            &entry.last_error,
+        ))?;
+    }
+    Ok(())
+}
+
 async fn transaction<F, R>(conn: Arc<Mutex<Connection>>, mut f: F) -> ah::Result<R>
 where
     F: FnMut(rusqlite::Transaction) -> Result<R, Error> + Send + 'static,
@@ -184,6 +392,7 @@ where
                     if Instant::now() >= timeout {
                         break Err(e.into());
                     }
+                    BUSY_RETRIES.fetch_add(1, Ordering::Relaxed);
                     std::thread::sleep(Duration::from_millis(20));
                 }
                 Err(e) => {
@@ -195,13 +404,42 @@ where
     .await?
 }
 
+/// Check whether the table has the column.
+/// Tables that do not exist have no columns.
+fn has_column(t: &rusqlite::Transaction, table: &str, column: &str) -> Result<bool, Error> {
+    Ok(t.prepare(&format!("PRAGMA table_info({table})"))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .any(|name| name.map(|name| name == column).unwrap_or(false)))
+}
+
+/// Add a column to a table, if it does not exist already.
+fn add_column(
+    t: &rusqlite::Transaction,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> Result<(), Error> {
+    if !has_column(t, table, column)? {
+        t.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
 pub struct DbConn {
     conn: Arc<Mutex<Connection>>,
 }
 
 impl DbConn {
-    async fn new(path: &Path) -> ah::Result<Self> {
+    async fn new(path: &Path, uri: bool) -> ah::Result<Self> {
         let path = path.to_path_buf();
+        let uri_flag = if uri {
+            OpenFlags::SQLITE_OPEN_URI
+        } else {
+            OpenFlags::empty()
+        };
 
         let conn = spawn_blocking(move || -> ah::Result<Connection> {
             let timeout = Instant::now() + TIMEOUT;
@@ -211,7 +449,8 @@ impl DbConn {
                     &path,
                     OpenFlags::SQLITE_OPEN_READ_WRITE
                         | OpenFlags::SQLITE_OPEN_CREATE
-                        | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                        | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                        | uri_flag,
                 ) {
                     Ok(conn) => conn,
                     Err(
@@ -278,10 +517,19 @@ impl DbConn {
                         link VARCHAR, \
                         published TIMESTAMP, \
                         summary VARCHAR, \
+                        starred BOOLEAN DEFAULT FALSE, \
+                        tags VARCHAR DEFAULT '', \
+                        important BOOLEAN DEFAULT FALSE, \
+                        suppressed_by VARCHAR DEFAULT NULL, \
                         FOREIGN KEY(feed_id) REFERENCES feeds(feed_id)\
                     )",
                 [],
             )?;
+            // Upgrade items table of old databases.
+            add_column(&t, "items", "starred", "BOOLEAN DEFAULT FALSE")?;
+            add_column(&t, "items", "tags", "VARCHAR DEFAULT ''")?;
+            add_column(&t, "items", "important", "BOOLEAN DEFAULT FALSE")?;
+            add_column(&t, "items", "suppressed_by", "VARCHAR DEFAULT NULL")?;
             // Global key-value store for integer keys and integer values.
             t.execute(
                 "\
@@ -292,6 +540,32 @@ impl DbConn {
                 [],
             )?;
 
+            // Outbox of pending notifications.
+            t.execute(
+                "\
+                    CREATE TABLE IF NOT EXISTS outbox (\
+                        outbox_id INTEGER PRIMARY KEY, \
+                        endpoint VARCHAR NOT NULL, \
+                        payload VARCHAR NOT NULL, \
+                        created TIMESTAMP, \
+                        attempts INTEGER DEFAULT 0, \
+                        next_attempt TIMESTAMP, \
+                        last_error VARCHAR DEFAULT NULL\
+                    )",
+                [],
+            )?;
+            // Items that wait in the outbox to be batched.
+            t.execute(
+                "\
+                    CREATE TABLE IF NOT EXISTS outbox_items (\
+                        outbox_item_id INTEGER PRIMARY KEY, \
+                        endpoint VARCHAR NOT NULL, \
+                        item VARCHAR NOT NULL, \
+                        created TIMESTAMP\
+                    )",
+                [],
+            )?;
+
             // Create indices.
             t.execute("CREATE INDEX IF NOT EXISTS feed_id ON feeds(feed_id)", [])?;
             t.execute("CREATE INDEX IF NOT EXISTS item_id ON items(item_id)", [])?;
@@ -320,12 +594,47 @@ impl DbConn {
                 [ KV_KEY_FEED_UPDATE_REV, 1 ]
             )?;
 
+            // Initialize the time of the last digest. 0 means never.
+            t.execute(
+                "\
+                    INSERT OR IGNORE INTO kv_int_int \
+                    VALUES(?, ?)\
+                ",
+                [ KV_KEY_LAST_DIGEST, 0 ]
+            )?;
+
             t.commit()?;
             Ok(())
         })
         .await
     }
 
+    /// Check that the database has the schema of [DbConn::init] without modifying it.
+    ///
+    /// Old databases are only upgraded by [DbConn::init].
+    pub async fn check_schema(&mut self) -> ah::Result<()> {
+        let missing = transaction(Arc::clone(&self.conn), move |t| {
+            let mut missing = vec![];
+            for (table, columns) in SCHEMA_COLUMNS {
+                for column in *columns {
+                    if !has_column(&t, table, column)? {
+                        missing.push(format!("{table}.{column}"));
+                    }
+                }
+            }
+            t.finish()?;
+            Ok(missing)
+        })
+        .await?;
+        if !missing.is_empty() {
+            return Err(err!(
+                "The database schema is outdated. Missing: {}",
+                missing.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
     pub async fn vacuum(&mut self) -> ah::Result<()> {
         spawn_blocking({
             let conn = Arc::clone(&self.conn);
@@ -365,9 +674,24 @@ impl DbConn {
         items: &[Item],
         gc_thres: Option<DateTime<Utc>>,
         increment_update_revision: bool,
+    ) -> ah::Result<()> {
+        self.update_feed_with_outbox(feed, items, gc_thres, increment_update_revision, &[])
+            .await
+    }
+
+    /// Like [DbConn::update_feed], but also add the `outbox` items
+    /// in the same transaction.
+    pub async fn update_feed_with_outbox(
+        &mut self,
+        feed: &Feed,
+        items: &[Item],
+        gc_thres: Option<DateTime<Utc>>,
+        increment_update_revision: bool,
+        outbox: &[OutboxItem],
     ) -> ah::Result<()> {
         let feed = feed.clone();
         let items = items.to_vec();
+        let outbox = outbox.to_vec();
 
         transaction(Arc::clone(&self.conn), move |t| {
             let Some(feed_id) = feed.feed_id else {
@@ -409,7 +733,7 @@ impl DbConn {
                 t.prepare_cached(
                     "\
                         INSERT INTO items \
-                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)\
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)\
                     ",
                 )?
                 .execute((
@@ -423,20 +747,16 @@ impl DbConn {
                     &item.link,
                     dt_to_sql(&item.published),
                     &item.summary,
+                    item.starred,
+                    tags_to_sql(&item.tags),
+                    item.important,
+                    &item.suppressed_by,
                 ))?;
             }
 
             if let Some(gc_thres) = gc_thres.as_ref() {
-                t.prepare_cached(
-                    "\
-                        DELETE FROM items \
-                        WHERE \
-                            feed_id = ? AND \
-                            published < ? AND \
-                            seen = TRUE\
-                    ",
-                )?
-                .execute((feed_id, dt_to_sql(gc_thres)))?;
+                t.prepare_cached(&format!("DELETE FROM items WHERE {GC_ITEMS_CONDITION}"))?
+                    .execute((feed_id, dt_to_sql(gc_thres)))?;
             }
 
             // Increment the feed update revision counter.
@@ -451,16 +771,61 @@ impl DbConn {
                 .execute([KV_KEY_FEED_UPDATE_REV])?;
             }
 
+            insert_outbox_items(&t, &outbox)?;
+
             t.commit()?;
             Ok(())
         })
         .await
     }
 
+    /// Get the items that [DbConn::update_feed] would garbage collect with `gc_thres`.
+    pub async fn get_gc_items(
+        &mut self,
+        feed_id: i64,
+        gc_thres: DateTime<Utc>,
+    ) -> ah::Result<Vec<Item>> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            let items = t
+                .prepare_cached(&format!(
+                    "SELECT * FROM items WHERE {GC_ITEMS_CONDITION} ORDER BY published DESC"
+                ))?
+                .query_map((feed_id, dt_to_sql(&gc_thres)), Item::from_sql_row)?
+                .collect::<Result<Vec<Item>, _>>()?;
+
+            t.finish()?;
+            Ok(items)
+        })
+        .await
+    }
+
     pub async fn get_feed_update_revision(&mut self) -> ah::Result<i64> {
         self.get_kv_int_int(KV_KEY_FEED_UPDATE_REV).await
     }
 
+    /// Get the time of the last digest. None, if no digest has been sent, yet.
+    pub async fn get_last_digest(&mut self) -> ah::Result<Option<DateTime<Utc>>> {
+        let timestamp = self.get_kv_int_int(KV_KEY_LAST_DIGEST).await?;
+        Ok(Some(timestamp).filter(|t| *t > 0).map(sql_to_dt))
+    }
+
+    pub async fn set_last_digest(&mut self, time: DateTime<Utc>) -> ah::Result<()> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            t.prepare_cached(
+                "\
+                    UPDATE kv_int_int \
+                    SET value = ? \
+                    WHERE key = ?\
+                ",
+            )?
+            .execute([dt_to_sql(&time), KV_KEY_LAST_DIGEST])?;
+
+            t.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
     pub async fn add_feed(&mut self, href: &str) -> ah::Result<()> {
         let href = href.to_string();
 
@@ -542,6 +907,44 @@ impl DbConn {
         .await
     }
 
+    pub async fn get_enabled_feeds(&mut self) -> ah::Result<Vec<Feed>> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            let feeds: Vec<Feed> = t
+                .prepare_cached(
+                    "\
+                        SELECT * FROM feeds \
+                        WHERE disabled == FALSE\
+                    ",
+                )?
+                .query_map([], Feed::from_sql_row)?
+                .map(|f| f.unwrap())
+                .collect();
+
+            t.finish()?;
+            Ok(feeds)
+        })
+        .await
+    }
+
+    pub async fn get_feed(&mut self, feed_id: i64) -> ah::Result<Option<Feed>> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            let feed = t
+                .prepare_cached(
+                    "\
+                        SELECT * FROM feeds \
+                        WHERE feed_id = ?\
+                    ",
+                )?
+                .query_map([feed_id], Feed::from_sql_row)?
+                .map(|f| f.unwrap())
+                .next();
+
+            t.finish()?;
+            Ok(feed)
+        })
+        .await
+    }
+
     pub async fn get_next_due_time(&mut self) -> ah::Result<DateTime<Utc>> {
         transaction(Arc::clone(&self.conn), move |t| {
             let next_retrieval = t
@@ -615,52 +1018,135 @@ impl DbConn {
         .await
     }
 
+    /// Get one page of the items of a feed, newest items first.
+    ///
+    /// `offset` items are skipped and at most `limit` items are returned.
+    /// If `peek` is false, then only the returned items are marked as seen.
     pub async fn get_feed_items(
         &mut self,
         feed_id: i64,
         peek: bool,
-    ) -> ah::Result<Vec<(Item, ItemExt)>> {
+        offset: usize,
+        limit: Option<usize>,
+    ) -> ah::Result<(Vec<(Item, ItemExt)>, ItemsExt)> {
+        let offset: i64 = offset
+            .try_into()
+            .context("get_feed_items(): Invalid offset")?;
+        // Query one more item than requested to find out whether there are more items.
+        let query_limit: i64 = match limit {
+            Some(limit) => i64::try_from(limit)
+                .context("get_feed_items(): Invalid limit")?
+                .saturating_add(1),
+            None => -1,
+        };
+
         transaction(Arc::clone(&self.conn), move |t| {
-            let items: Vec<(Item, ItemExt)> = t
-                .prepare_cached(
+            let mut items: Vec<(Item, ItemExt)> = t
+                .prepare_cached(&format!(
                     "\
-                        SELECT \
-                            item_id, \
-                            feed_id, \
-                            max(retrieved), \
-                            seen, \
-                            author, \
-                            title, \
-                            feed_item_id, \
-                            link, \
-                            published, \
-                            summary, \
-                            count() as count, \
-                            max(seen) as any_seen, \
-                            sum(seen) as sum_seen \
-                        FROM items \
-                        WHERE feed_id = ? \
-                        GROUP BY feed_item_id \
-                        ORDER BY published DESC\
+                        {} \
+                        GROUP BY items.feed_item_id \
+                        ORDER BY items.published DESC, items.feed_item_id DESC \
+                        LIMIT ? OFFSET ?\
                     ",
-                )?
-                .query_map([feed_id], Item::from_sql_row_extended)?
+                    SELECT_NEWEST_ITEMS.replace("{cond}", "feed_id = ?")
+                ))?
+                .query_map((feed_id, query_limit, offset), Item::from_sql_row_extended)?
                 .map(|i| i.unwrap())
                 .collect();
 
+            let more_items = match limit {
+                Some(limit) if items.len() > limit => {
+                    items.truncate(limit);
+                    true
+                }
+                _ => false,
+            };
+
             if peek {
                 t.finish()?;
             } else {
-                t.prepare_cached(
-                    "\
-                        UPDATE items \
-                        SET seen = TRUE \
-                        WHERE feed_id = ?\
-                    ",
-                )?
-                .execute([feed_id])?;
+                for (item, _) in &items {
+                    t.prepare_cached(
+                        "\
+                            UPDATE items \
+                            SET seen = TRUE \
+                            WHERE \
+                                feed_id = ? AND \
+                                feed_item_id = ?\
+                        ",
+                    )?
+                    .execute((feed_id, &item.feed_item_id))?;
+                }
                 t.commit()?;
             }
+            Ok((items, ItemsExt { more_items }))
+        })
+        .await
+    }
+
+    /// Get the newest version of all items that match the `filter`, newest items first.
+    pub async fn get_items_filtered(&mut self, filter: &ItemFilter) -> ah::Result<Vec<Item>> {
+        let filter = filter.clone();
+        let limit: i64 = match filter.limit {
+            Some(limit) => limit
+                .try_into()
+                .context("get_items_filtered(): Invalid limit")?,
+            None => -1,
+        };
+
+        let mut cond = vec!["TRUE".to_string()];
+        let mut params: Vec<rusqlite::types::Value> = vec![];
+        if !filter.feed_ids.is_empty() {
+            let placeholders = vec!["?"; filter.feed_ids.len()].join(", ");
+            cond.push(format!("items.feed_id IN ({placeholders})"));
+            params.extend(filter.feed_ids.iter().map(|id| (*id).into()));
+        }
+        if filter.starred {
+            cond.push("g.starred = TRUE".to_string());
+        }
+        if let Some(search) = &filter.search {
+            cond.push(
+                "(instr(lower(items.title), lower(?)) > 0 OR \
+                  instr(lower(items.summary), lower(?)) > 0)"
+                    .to_string(),
+            );
+            params.push(search.clone().into());
+            params.push(search.clone().into());
+        }
+        if let Some(tag) = &filter.tag {
+            cond.push("instr(',' || items.tags || ',', ?) > 0".to_string());
+            params.push(format!(",{tag},").into());
+        }
+        if filter.unseen {
+            cond.push("g.any_seen = FALSE".to_string());
+        }
+        if let Some(since) = &filter.retrieved_since {
+            cond.push("g.retrieved > ?".to_string());
+            params.push(dt_to_sql(since).into());
+        }
+        params.push(limit.into());
+
+        let sql = format!(
+            "\
+                {} \
+                WHERE {} \
+                GROUP BY items.feed_id, items.feed_item_id \
+                ORDER BY items.published DESC, items.feed_item_id DESC \
+                LIMIT ?\
+            ",
+            SELECT_NEWEST_ITEMS.replace("{cond}", "TRUE"),
+            cond.join(" AND ")
+        );
+
+        transaction(Arc::clone(&self.conn), move |t| {
+            let items: Vec<Item> = t
+                .prepare(&sql)?
+                .query_map(rusqlite::params_from_iter(&params), Item::from_sql_row)?
+                .map(|i| i.unwrap())
+                .collect();
+
+            t.finish()?;
             Ok(items)
         })
         .await
@@ -695,14 +1181,20 @@ impl DbConn {
             if peek {
                 t.finish()?;
             } else {
+                // Only mark the displayed item history as seen.
                 t.prepare_cached(
                     "\
                         UPDATE items \
                         SET seen = TRUE \
-                        WHERE feed_id = ?\
+                        WHERE \
+                            feed_id = ? AND \
+                            feed_item_id IN (\
+                                SELECT feed_item_id FROM items \
+                                WHERE item_id = ?\
+                            )\
                     ",
                 )?
-                .execute([feed_id])?;
+                .execute((feed_id, &item_id))?;
                 t.commit()?;
             }
             Ok(items)
@@ -754,43 +1246,207 @@ impl DbConn {
         .await
     }
 
-    pub async fn item_set_unseen(&mut self, item_id: &str) -> ah::Result<()> {
-        let item_id = item_id.to_string();
+    /// Set the seen-state of the items with the given `item_ids`.
+    ///
+    /// All versions of an item (same `feed_item_id`) are changed together.
+    /// The `updated_items` counter of the feed is adjusted by one
+    /// for every item whose state actually changed.
+    pub async fn items_set_seen(&mut self, item_ids: &[String], seen: bool) -> ah::Result<()> {
+        if item_ids.is_empty() {
+            return Ok(());
+        }
+        let item_ids = item_ids.to_vec();
 
         transaction(Arc::clone(&self.conn), move |t| {
-            let feed_id: Option<i64> = t
-                .prepare_cached(
-                    "\
-                        SELECT feed_id FROM items \
-                        WHERE item_id = ?\
-                    ",
-                )?
-                .query([&item_id])?
-                .next()?
-                .and_then(|row| row.get(0).ok());
+            for item_id in &item_ids {
+                let feed_id: Option<i64> = t
+                    .prepare_cached(
+                        "\
+                            SELECT feed_id FROM items \
+                            WHERE item_id = ?\
+                        ",
+                    )?
+                    .query([item_id])?
+                    .next()?
+                    .and_then(|row| row.get(0).ok());
 
-            if let Some(feed_id) = feed_id {
+                let Some(feed_id) = feed_id else {
+                    continue;
+                };
+
+                let changed = t
+                    .prepare_cached(
+                        "\
+                            UPDATE items \
+                            SET seen = ? \
+                            WHERE \
+                                seen != ? AND \
+                                feed_id = ? AND \
+                                feed_item_id IN (\
+                                    SELECT feed_item_id FROM items \
+                                    WHERE item_id = ?\
+                                )\
+                        ",
+                    )?
+                    .execute((seen, seen, feed_id, item_id))?;
+
+                if changed > 0 {
+                    t.prepare_cached(
+                        "\
+                            UPDATE feeds \
+                            SET updated_items = max(0, updated_items + ?) \
+                            WHERE feed_id = ?\
+                        ",
+                    )?
+                    .execute([if seen { -1 } else { 1 }, feed_id])?;
+                }
+            }
+
+            t.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the starred-state of the items with the given `item_ids`.
+    ///
+    /// All versions of an item (same `feed_item_id`) are changed together.
+    /// Starred items are never garbage collected.
+    pub async fn items_set_starred(
+        &mut self,
+        item_ids: &[String],
+        starred: bool,
+    ) -> ah::Result<()> {
+        if item_ids.is_empty() {
+            return Ok(());
+        }
+        let item_ids = item_ids.to_vec();
+
+        transaction(Arc::clone(&self.conn), move |t| {
+            for item_id in &item_ids {
                 t.prepare_cached(
                     "\
                         UPDATE items \
-                        SET seen = FALSE \
-                        WHERE feed_item_id IN (\
-                            SELECT feed_item_id FROM items \
+                        SET starred = ? \
+                        WHERE (feed_id, feed_item_id) IN (\
+                            SELECT feed_id, feed_item_id FROM items \
                             WHERE item_id = ?\
                         )\
                     ",
                 )?
-                .execute([&item_id])?;
+                .execute((starred, item_id))?;
+            }
 
-                t.prepare_cached(
+            t.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get all items that wait in the outbox to be batched, oldest first.
+    pub async fn get_outbox_items(&mut self) -> ah::Result<Vec<OutboxItem>> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            let items = t
+                .prepare_cached("SELECT * FROM outbox_items ORDER BY outbox_item_id")?
+                .query_map([], OutboxItem::from_sql_row)?
+                .collect::<Result<Vec<OutboxItem>, _>>()?;
+            t.finish()?;
+            Ok(items)
+        })
+        .await
+    }
+
+    /// Replace the outbox `items` by the outbox `entries` of their batches.
+    pub async fn batch_outbox_items(
+        &mut self,
+        items: &[OutboxItem],
+        entries: &[OutboxEntry],
+    ) -> ah::Result<()> {
+        let item_ids: Vec<i64> = items.iter().filter_map(|i| i.outbox_item_id).collect();
+        let entries = entries.to_vec();
+
+        transaction(Arc::clone(&self.conn), move |t| {
+            for item_id in &item_ids {
+                t.prepare_cached("DELETE FROM outbox_items WHERE outbox_item_id = ?")?
+                    .execute([item_id])?;
+            }
+            insert_outbox(&t, &entries)?;
+            t.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get all outbox entries that are due for delivery at `now`, oldest first.
+    pub async fn get_outbox_due(&mut self, now: DateTime<Utc>) -> ah::Result<Vec<OutboxEntry>> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            let entries: Vec<OutboxEntry> = t
+                .prepare_cached(
                     "\
-                        UPDATE feeds \
-                        SET updated_items = updated_items + 1 \
-                        WHERE feed_id = ?\
+                        SELECT * FROM outbox \
+                        WHERE next_attempt <= ? \
+                        ORDER BY outbox_id\
                     ",
                 )?
-                .execute([feed_id])?;
-            }
+                .query_map([dt_to_sql(&now)], OutboxEntry::from_sql_row)?
+                .map(|e| e.unwrap())
+                .collect();
+
+            t.finish()?;
+            Ok(entries)
+        })
+        .await
+    }
+
+    /// Get the time of the next pending outbox delivery attempt.
+    pub async fn get_outbox_next_attempt(&mut self) -> ah::Result<Option<DateTime<Utc>>> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            let next_attempt: Option<i64> = t
+                .prepare_cached("SELECT min(next_attempt) FROM outbox")?
+                .query([])?
+                .next()?
+                .unwrap()
+                .get(0)?;
+
+            t.finish()?;
+            Ok(next_attempt.map(sql_to_dt))
+        })
+        .await
+    }
+
+    /// Store the delivery state of an outbox entry.
+    pub async fn update_outbox(&mut self, entry: &OutboxEntry) -> ah::Result<()> {
+        let Some(outbox_id) = entry.outbox_id else {
+            return Err(err!("update_outbox(): Invalid entry. No outbox_id."));
+        };
+        let entry = entry.clone();
+
+        transaction(Arc::clone(&self.conn), move |t| {
+            t.prepare_cached(
+                "\
+                    UPDATE outbox \
+                    SET attempts = ?, next_attempt = ?, last_error = ? \
+                    WHERE outbox_id = ?\
+                ",
+            )?
+            .execute((
+                entry.attempts,
+                dt_to_sql(&entry.next_attempt),
+                &entry.last_error,
+                outbox_id,
+            ))?;
+
+            t.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Remove a delivered or expired entry from the outbox.
+    pub async fn delete_outbox(&mut self, outbox_id: i64) -> ah::Result<()> {
+        transaction(Arc::clone(&self.conn), move |t| {
+            t.prepare_cached("DELETE FROM outbox WHERE outbox_id = ?")?
+                .execute([outbox_id])?;
 
             t.commit()?;
             Ok(())
@@ -849,24 +1505,469 @@ impl DbConn {
     }
 }
 
+fn check_name(name: &str) -> ah::Result<()> {
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(err!("Invalid name"));
+    }
+    Ok(())
+}
+
 pub struct Db {
     path: PathBuf,
+    uri: bool,
+    /// Keeps an in-memory database alive for the lifetime of this [Db].
+    _memory: Option<DbConn>,
 }
 
 impl Db {
     pub async fn new(name: &str) -> ah::Result<Self> {
-        if !name
-            .chars()
-            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
-        {
-            return Err(err!("Invalid name"));
-        }
+        check_name(name)?;
         let path = get_varlib().join(format!("{name}.db"));
-        Ok(Self { path })
+        Ok(Self {
+            path,
+            uri: false,
+            _memory: None,
+        })
+    }
+
+    /// Create a new initialized in-memory database.
+    ///
+    /// All connections opened from the returned [Db] share the same database.
+    /// The database is deleted when the [Db] is dropped.
+    /// The `name` must be unique within the process.
+    pub async fn new_memory(name: &str) -> ah::Result<Self> {
+        check_name(name)?;
+        let path = PathBuf::from(format!("file:{name}?mode=memory&cache=shared"));
+        let mut conn = DbConn::new(&path, true).await?;
+        conn.init().await?;
+        Ok(Self {
+            path,
+            uri: true,
+            _memory: Some(conn),
+        })
     }
 
     pub async fn open(&self) -> ah::Result<DbConn> {
-        DbConn::new(&self.path).await
+        DbConn::new(&self.path, self.uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn dt(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).unwrap()
+    }
+
+    fn item(item_id: &str, feed_item_id: &str, secs: i64) -> Item {
+        Item {
+            item_id: Some(item_id.to_string()),
+            retrieved: dt(secs),
+            published: dt(secs),
+            ..test_util::item(feed_item_id, feed_item_id, "")
+        }
+    }
+
+    /// Add one feed for every href and return the feeds in the order of `hrefs`.
+    async fn add_feeds(conn: &mut DbConn, hrefs: &[&str]) -> Vec<Feed> {
+        for href in hrefs {
+            conn.add_feed(href).await.unwrap();
+        }
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        hrefs
+            .iter()
+            .map(|href| feeds.iter().find(|f| f.href == *href).unwrap().clone())
+            .collect()
+    }
+
+    async fn updated_items(conn: &mut DbConn, feed: &Feed) -> i64 {
+        let feed = conn.get_feed(feed.feed_id.unwrap()).await.unwrap();
+        feed.unwrap().updated_items
+    }
+
+    /// Get `(feed_item_id, any_seen, all_seen)` of all items of the `feed`.
+    async fn seen_state(conn: &mut DbConn, feed: &Feed) -> Vec<(String, bool, bool)> {
+        let feed_id = feed.feed_id.unwrap();
+        let (items, _) = conn.get_feed_items(feed_id, true, 0, None).await.unwrap();
+        items
+            .into_iter()
+            .map(|(i, ext)| (i.feed_item_id, ext.any_seen, ext.all_seen))
+            .collect()
+    }
+
+    fn state(s: &[(&str, bool, bool)]) -> Vec<(String, bool, bool)> {
+        s.iter()
+            .map(|(id, a, b)| (id.to_string(), *a, *b))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_items_set_seen() {
+        let db = Db::new_memory("test_items_set_seen").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        let feeds = add_feeds(&mut conn, &["https://a.example", "https://b.example"]).await;
+
+        // Two versions of item1 and one item2 in feed a.
+        // Feed b has an unrelated item with the same feed_item_id.
+        let a = Feed {
+            updated_items: 2,
+            ..feeds[0].clone()
+        };
+        let items = [
+            item("a1v1", "item1", 1),
+            item("a1v2", "item1", 2),
+            item("a2", "item2", 3),
+        ];
+        conn.update_feed(&a, &items, None, false).await.unwrap();
+        let b = Feed {
+            updated_items: 1,
+            ..feeds[1].clone()
+        };
+        conn.update_feed(&b, &[item("b1", "item1", 1)], None, false)
+            .await
+            .unwrap();
+
+        let ids = |ids: &[&str]| -> Vec<String> { ids.iter().map(|i| i.to_string()).collect() };
+
+        // All versions change together.
+        conn.items_set_seen(&ids(&["a1v1"]), true).await.unwrap();
+        assert_eq!(
+            seen_state(&mut conn, &a).await,
+            state(&[("item2", false, false), ("item1", true, true)])
+        );
+        assert_eq!(updated_items(&mut conn, &a).await, 1);
+        assert_eq!(
+            seen_state(&mut conn, &b).await,
+            state(&[("item1", false, false)])
+        );
+        assert_eq!(updated_items(&mut conn, &b).await, 1);
+
+        // Unchanged state and unknown items don't change the counter.
+        conn.items_set_seen(&ids(&["a1v2", "unknown"]), true)
+            .await
+            .unwrap();
+        assert_eq!(updated_items(&mut conn, &a).await, 1);
+
+        conn.items_set_seen(&ids(&["a1v2"]), false).await.unwrap();
+        assert_eq!(
+            seen_state(&mut conn, &a).await,
+            state(&[("item2", false, false), ("item1", false, false)])
+        );
+        assert_eq!(updated_items(&mut conn, &a).await, 2);
+
+        // The counter does not become negative after it has been reset.
+        conn.get_feeds(a.feed_id).await.unwrap();
+        assert_eq!(updated_items(&mut conn, &a).await, 0);
+        conn.items_set_seen(&ids(&["a1v1", "a2"]), true)
+            .await
+            .unwrap();
+        assert_eq!(updated_items(&mut conn, &a).await, 0);
+        assert_eq!(
+            seen_state(&mut conn, &a).await,
+            state(&[("item2", true, true), ("item1", true, true)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_items_filtered_tag() {
+        let db = Db::new_memory("test_items_filtered_tag").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.add_feed("https://example.com/feed").await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+
+        let item = |n: i64, tags: &[&str]| Item {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..item(&format!("id{n}"), &format!("item{n}"), n)
+        };
+        let items = [
+            item(1, &["rust"]),
+            item(2, &["rustic", "linux"]),
+            item(3, &[]),
+        ];
+        conn.update_feed(&feeds[0], &items, None, false)
+            .await
+            .unwrap();
+
+        let titles = async |conn: &mut DbConn, tag: &str| -> Vec<String> {
+            let filter = ItemFilter {
+                tag: Some(tag.to_string()),
+                ..Default::default()
+            };
+            let items = conn.get_items_filtered(&filter).await.unwrap();
+            items.into_iter().map(|i| i.title).collect()
+        };
+        assert_eq!(titles(&mut conn, "rust").await, ["item1"]);
+        assert_eq!(titles(&mut conn, "linux").await, ["item2"]);
+        assert!(titles(&mut conn, "rus").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_feed_items_pagination() {
+        let db = Db::new_memory("test_feed_items_pagination").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        let feeds = add_feeds(&mut conn, &["https://a.example"]).await;
+        let feed_id = feeds[0].feed_id.unwrap();
+
+        // Five items. item3 has two versions, which count as one item.
+        let items = [
+            item("i1", "item1", 1),
+            item("i2", "item2", 2),
+            item("i3v1", "item3", 3),
+            item("i3v2", "item3", 30),
+            item("i4", "item4", 4),
+            item("i5", "item5", 5),
+        ];
+        conn.update_feed(&feeds[0], &items, None, false)
+            .await
+            .unwrap();
+
+        let mut page = async |offset, limit| -> (Vec<String>, bool) {
+            let (items, ext) = conn
+                .get_feed_items(feed_id, true, offset, limit)
+                .await
+                .unwrap();
+            let ids = items.into_iter().map(|(i, _)| i.feed_item_id).collect();
+            (ids, ext.more_items)
+        };
+        let ids = |ids: &[&str]| -> Vec<String> { ids.iter().map(|i| i.to_string()).collect() };
+
+        assert_eq!(page(0, Some(2)).await, (ids(&["item3", "item5"]), true));
+        assert_eq!(page(2, Some(2)).await, (ids(&["item4", "item2"]), true));
+        assert_eq!(page(4, Some(2)).await, (ids(&["item1"]), false));
+        assert_eq!(page(5, Some(2)).await, (ids(&[]), false));
+        // The limit+1 boundary.
+        assert!(page(0, Some(4)).await.1);
+        assert!(!page(0, Some(5)).await.1);
+        assert!(!page(0, Some(6)).await.1);
+        assert_eq!(page(0, None).await.0.len(), 5);
+
+        // Without peek only the returned page is marked as seen.
+        conn.get_feed_items(feed_id, false, 2, Some(2))
+            .await
+            .unwrap();
+        let (items, _) = conn.get_feed_items(feed_id, true, 0, None).await.unwrap();
+        let seen: Vec<(String, bool)> = items
+            .into_iter()
+            .map(|(i, ext)| (i.feed_item_id, ext.all_seen))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                ("item3".to_string(), false),
+                ("item5".to_string(), false),
+                ("item4".to_string(), true),
+                ("item2".to_string(), true),
+                ("item1".to_string(), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_items_set_starred() {
+        let db = Db::new_memory("test_items_set_starred").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        let feeds = add_feeds(&mut conn, &["https://a.example", "https://b.example"]).await;
+        let (a, b) = (&feeds[0], &feeds[1]);
+
+        let items = [
+            item("a1v1", "item1", 1),
+            item("a1v2", "item1", 2),
+            item("a2", "item2", 3),
+        ];
+        conn.update_feed(a, &items, None, false).await.unwrap();
+        conn.update_feed(b, &[item("b1", "item1", 1)], None, false)
+            .await
+            .unwrap();
+
+        let starred = async |conn: &mut DbConn, feed: &Feed| -> Vec<(String, bool)> {
+            let feed_id = feed.feed_id.unwrap();
+            let (items, _) = conn.get_feed_items(feed_id, true, 0, None).await.unwrap();
+            items
+                .into_iter()
+                .map(|(i, _)| (i.feed_item_id, i.starred))
+                .collect()
+        };
+
+        // All versions change together. Other feeds are not affected.
+        conn.items_set_starred(&["a1v1".to_string()], true)
+            .await
+            .unwrap();
+        assert_eq!(
+            starred(&mut conn, a).await,
+            [("item2".to_string(), false), ("item1".to_string(), true)]
+        );
+        assert_eq!(starred(&mut conn, b).await, [("item1".to_string(), false)]);
+        let filter = ItemFilter {
+            starred: true,
+            ..Default::default()
+        };
+        let items = conn.get_items_filtered(&filter).await.unwrap();
+        let ids: Vec<&str> = items
+            .iter()
+            .map(|i| i.item_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, ["a1v2"]);
+
+        // Seen starred items are not garbage collected.
+        conn.feed_set_seen(a.feed_id).await.unwrap();
+        conn.update_feed(a, &[], Some(dt(100)), false)
+            .await
+            .unwrap();
+        assert_eq!(starred(&mut conn, a).await, [("item1".to_string(), true)]);
+
+        conn.items_set_starred(&["a1v2".to_string()], false)
+            .await
+            .unwrap();
+        assert_eq!(starred(&mut conn, a).await, [("item1".to_string(), false)]);
+        conn.update_feed(a, &[], Some(dt(100)), false)
+            .await
+            .unwrap();
+        assert!(starred(&mut conn, a).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let db = Db::new_memory("test_outbox").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+
+        assert_eq!(conn.get_outbox_next_attempt().await.unwrap(), None);
+
+        let entry = |endpoint: &str, next_attempt| OutboxEntry {
+            outbox_id: None,
+            endpoint: endpoint.to_string(),
+            payload: format!("{{\"to\":\"{endpoint}\"}}"),
+            created: dt(1000),
+            attempts: 0,
+            next_attempt,
+            last_error: None,
+        };
+        conn.batch_outbox_items(&[], &[entry("a", dt(1000)), entry("b", dt(2000))])
+            .await
+            .unwrap();
+        assert_eq!(
+            conn.get_outbox_next_attempt().await.unwrap(),
+            Some(dt(1000))
+        );
+
+        let due = conn.get_outbox_due(dt(1500)).await.unwrap();
+        assert_eq!(due.len(), 1);
+        let mut a = due[0].clone();
+        assert_eq!(a.endpoint, "a");
+        assert_eq!(a.payload, "{\"to\":\"a\"}");
+        assert_eq!(a.created, dt(1000));
+
+        // Failed attempt.
+        a.attempts += 1;
+        a.next_attempt = dt(3000);
+        a.last_error = Some("HTTP 500".to_string());
+        conn.update_outbox(&a).await.unwrap();
+        assert_eq!(
+            conn.get_outbox_next_attempt().await.unwrap(),
+            Some(dt(2000))
+        );
+
+        // The entries survive a reconnect.
+        drop(conn);
+        let mut conn = db.open().await.unwrap();
+        let due = conn.get_outbox_due(dt(3000)).await.unwrap();
+        let endpoints: Vec<&str> = due.iter().map(|e| e.endpoint.as_str()).collect();
+        assert_eq!(endpoints, ["a", "b"]);
+        assert_eq!(due[0].attempts, 1);
+        assert_eq!(due[0].last_error.as_deref(), Some("HTTP 500"));
+
+        for e in &due {
+            conn.delete_outbox(e.outbox_id.unwrap()).await.unwrap();
+        }
+        assert!(conn.get_outbox_due(dt(10000)).await.unwrap().is_empty());
+        assert_eq!(conn.get_outbox_next_attempt().await.unwrap(), None);
+
+        // The outbox items are written in the transaction of the feed update.
+        let outbox_item = |endpoint: &str, item: &str| OutboxItem {
+            outbox_item_id: None,
+            endpoint: endpoint.to_string(),
+            item: item.to_string(),
+            created: dt(1),
+        };
+        let feeds = add_feeds(&mut conn, &["https://a.example.com/"]).await;
+        let mut invalid = item("a1", "item1", 1);
+        invalid.item_id = None;
+        assert!(
+            conn.update_feed_with_outbox(
+                &feeds[0],
+                &[invalid],
+                None,
+                false,
+                &[outbox_item("c", "1")]
+            )
+            .await
+            .is_err()
+        );
+        assert!(conn.get_outbox_items().await.unwrap().is_empty());
+
+        conn.update_feed_with_outbox(
+            &feeds[0],
+            &[item("a1", "item1", 1)],
+            None,
+            false,
+            &[outbox_item("c", "1"), outbox_item("d", "1")],
+        )
+        .await
+        .unwrap();
+        conn.update_feed_with_outbox(
+            &feeds[0],
+            &[item("a2", "item2", 2)],
+            None,
+            false,
+            &[outbox_item("c", "2")],
+        )
+        .await
+        .unwrap();
+        let items = conn.get_outbox_items().await.unwrap();
+        let items: Vec<(&str, &str)> = items
+            .iter()
+            .map(|i| (i.endpoint.as_str(), i.item.as_str()))
+            .collect();
+        assert_eq!(items, [("c", "1"), ("d", "1"), ("c", "2")]);
+
+        // The items are replaced by the batches.
+        let items = conn.get_outbox_items().await.unwrap();
+        conn.batch_outbox_items(&items[..2], &[entry("c", dt(1))])
+            .await
+            .unwrap();
+        let items = conn.get_outbox_items().await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item, "2");
+        let due = conn.get_outbox_due(dt(1)).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].endpoint, "c");
+    }
+
+    #[tokio::test]
+    async fn test_check_schema() {
+        let db = Db::new_memory("test_check_schema").await.unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.check_schema().await.unwrap();
+
+        // A database of an older version.
+        for sql in [
+            "ALTER TABLE items DROP COLUMN suppressed_by",
+            "DROP TABLE outbox",
+        ] {
+            conn.conn.lock().unwrap().execute(sql, []).unwrap();
+        }
+        let e = conn.check_schema().await.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "The database schema is outdated. \
+             Missing: items.suppressed_by, outbox.outbox_id, outbox.last_error"
+        );
+        conn.init().await.unwrap();
+        conn.check_schema().await.unwrap();
     }
 }
 
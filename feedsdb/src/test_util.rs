@@ -0,0 +1,71 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Shared helpers of the unit tests of all feedreader crates.
+//!
+//! Enabled by the `test-util` feature.
+
+use crate::{Feed, Item};
+use chrono::{DateTime, Utc};
+
+/// The fixed current time of the tests.
+pub fn now() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_800_000_000, 0).unwrap()
+}
+
+/// Parse an RFC 3339 time.
+pub fn dt(s: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+}
+
+/// An unseen item without `item_id` and `feed_id` that was retrieved and published [now].
+pub fn item(feed_item_id: &str, title: &str, link: &str) -> Item {
+    Item {
+        item_id: None,
+        feed_id: None,
+        retrieved: now(),
+        seen: false,
+        author: "".to_string(),
+        title: title.to_string(),
+        feed_item_id: feed_item_id.to_string(),
+        link: link.to_string(),
+        published: now(),
+        summary: "".to_string(),
+        starred: false,
+        important: false,
+        tags: vec![],
+        suppressed_by: None,
+    }
+}
+
+/// An enabled feed that was retrieved [now].
+pub fn feed(feed_id: Option<i64>, href: &str) -> Feed {
+    Feed {
+        feed_id,
+        href: href.to_string(),
+        title: "".to_string(),
+        last_retrieval: now(),
+        next_retrieval: now(),
+        last_activity: now(),
+        disabled: false,
+        updated_items: 0,
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
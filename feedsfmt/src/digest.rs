@@ -0,0 +1,366 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::{
+    export::{ExportItem, get_export_items},
+    mail::{Mail, sendmail, write_maildir},
+    xml_escape,
+};
+use anyhow::{self as ah, Context as _};
+use chrono::{DateTime, Utc};
+use feedscfg::ConfigDigest;
+use feedsdb::{DbConn, Item, ItemFilter};
+use std::{fmt::Write as _, time::Duration};
+
+/// The items of one feed in the digest.
+struct DigestFeed<'a> {
+    title: &'a str,
+    href: &'a str,
+    items: Vec<&'a Item>,
+}
+
+/// Group the items by feed. The feeds are sorted by title.
+fn group(items: &[ExportItem]) -> Vec<DigestFeed<'_>> {
+    let mut feeds: Vec<DigestFeed<'_>> = vec![];
+    for i in items {
+        match feeds.iter_mut().find(|f| f.href == i.feed_href) {
+            Some(f) => f.items.push(&i.item),
+            None => feeds.push(DigestFeed {
+                title: &i.feed_title,
+                href: &i.feed_href,
+                items: vec![&i.item],
+            }),
+        }
+    }
+    feeds.sort_by_key(|f| f.title.to_lowercase());
+    feeds
+}
+
+/// The unseen items of one digest.
+#[derive(Clone, Debug, Default)]
+pub struct DigestItems {
+    pub items: Vec<ExportItem>,
+    /// The time of the last digest.
+    pub since: Option<DateTime<Utc>>,
+    /// More items are unseen than fit into one digest.
+    /// Only the newest items are included.
+    pub truncated: bool,
+}
+
+/// Only link to web pages.
+fn is_web_link(link: &str) -> bool {
+    link.starts_with("https://") || link.starts_with("http://")
+}
+
+fn intro(digest: &DigestItems) -> String {
+    let count = digest.items.len();
+    let mut intro = match digest.since {
+        Some(since) => format!(
+            "{count} unread item(s) since {}.",
+            since.format("%Y-%m-%d %H:%M UTC")
+        ),
+        None => format!("{count} unread item(s)."),
+    };
+    if digest.truncated {
+        intro.push_str(&format!(
+            " There are more unread items. Only the newest {count} are listed."
+        ));
+    }
+    intro
+}
+
+/// Render the plain text part of the digest.
+pub fn render_text(digest: &DigestItems) -> String {
+    let mut t = String::with_capacity(digest.items.len() * 128 + 128);
+    let _ = writeln!(t, "{}", intro(digest));
+    for feed in group(&digest.items) {
+        let _ = writeln!(t, "\n== {} ==", feed.title);
+        let _ = writeln!(t, "{}", feed.href);
+        for item in feed.items {
+            let _ = writeln!(t, "\n* {}", item.title);
+            if !item.link.is_empty() {
+                let _ = writeln!(t, "  {}", item.link);
+            }
+            let _ = write!(t, "  {}", item.published.format("%Y-%m-%d %H:%M"));
+            if !item.author.is_empty() {
+                let _ = write!(t, ", {}", item.author);
+            }
+            t.push('\n');
+        }
+    }
+    t
+}
+
+/// Render the HTML part of the digest.
+pub fn render_html(digest: &DigestItems, title: &str) -> String {
+    let mut h = String::with_capacity(digest.items.len() * 256 + 512);
+    h.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(h, "<title>{}</title>", xml_escape(title));
+    h.push_str("</head>\n<body>\n");
+    let _ = writeln!(h, "<p>{}</p>", xml_escape(&intro(digest)));
+    for feed in group(&digest.items) {
+        if is_web_link(feed.href) {
+            let _ = writeln!(
+                h,
+                "<h2><a href=\"{}\">{}</a></h2>",
+                xml_escape(feed.href),
+                xml_escape(feed.title)
+            );
+        } else {
+            let _ = writeln!(h, "<h2>{}</h2>", xml_escape(feed.title));
+        }
+        h.push_str("<ul>\n");
+        for item in feed.items {
+            h.push_str("<li>");
+            if is_web_link(&item.link) {
+                let _ = write!(
+                    h,
+                    "<a href=\"{}\">{}</a>",
+                    xml_escape(&item.link),
+                    xml_escape(&item.title)
+                );
+            } else {
+                h.push_str(&xml_escape(&item.title));
+            }
+            let _ = write!(h, " <small>{}", item.published.format("%Y-%m-%d %H:%M"));
+            if !item.author.is_empty() {
+                let _ = write!(h, ", {}", xml_escape(&item.author));
+            }
+            h.push_str("</small></li>\n");
+        }
+        h.push_str("</ul>\n");
+    }
+    h.push_str("</body>\n</html>\n");
+    h
+}
+
+/// Build the digest mail.
+pub fn digest_mail(config: &ConfigDigest, digest: &DigestItems, now: DateTime<Utc>) -> Mail {
+    let subject = format!(
+        "{} ({}{} new)",
+        config.subject,
+        digest.items.len(),
+        if digest.truncated { "+" } else { "" }
+    );
+    Mail {
+        from: config.from.clone(),
+        to: config.to.clone(),
+        html: Some(render_html(digest, &subject)),
+        text: render_text(digest),
+        subject,
+        date: now,
+    }
+}
+
+/// Get the unseen items that have been retrieved after the last digest.
+///
+/// At most `max-items` of the newest items are returned.
+pub async fn get_digest_items(config: &ConfigDigest, conn: &mut DbConn) -> ah::Result<DigestItems> {
+    let since = conn
+        .get_last_digest()
+        .await
+        .context("Get time of last digest")?;
+    let filter = ItemFilter {
+        unseen: true,
+        retrieved_since: since,
+        // One more, to detect a truncated digest.
+        limit: Some(config.max_items.get().saturating_add(1)),
+        ..Default::default()
+    };
+    let mut items = get_export_items(conn, &filter).await?;
+    let truncated = items.len() > config.max_items.get();
+    items.truncate(config.max_items.get());
+    Ok(DigestItems {
+        items,
+        since,
+        truncated,
+    })
+}
+
+/// Hand the mail to sendmail or write it to the Maildir.
+///
+/// sendmail is killed, if it does not finish within `timeout`.
+pub async fn deliver_digest(
+    config: &ConfigDigest,
+    mail: &Mail,
+    timeout: Duration,
+) -> ah::Result<()> {
+    let message = mail.render();
+    match config.maildir.clone() {
+        Some(dir) => {
+            tokio::task::spawn_blocking(move || write_maildir(&dir, &message).map(|_| ())).await?
+        }
+        None => sendmail(&config.sendmail, &message, timeout).await,
+    }
+    .context("Deliver digest")
+}
+
+/// Send the digest of all unseen items since the last digest.
+///
+/// Empty digests are not sent.
+/// Items that do not fit into the digest are not sent in the next digest either.
+/// The mail states that the digest is truncated.
+/// Returns the number of items in the digest.
+pub async fn send_digest(
+    config: &ConfigDigest,
+    conn: &mut DbConn,
+    now: DateTime<Utc>,
+    timeout: Duration,
+) -> ah::Result<usize> {
+    let digest = get_digest_items(config, conn).await?;
+    if !digest.items.is_empty() {
+        let mail = digest_mail(config, &digest, now);
+        deliver_digest(config, &mail, timeout).await?;
+    }
+    conn.set_last_digest(now)
+        .await
+        .context("Set time of last digest")?;
+    Ok(digest.items.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::{self, dt};
+
+    fn item(feed: &str, title: &str, link: &str, author: &str) -> ExportItem {
+        ExportItem {
+            item: Item {
+                item_id: Some(format!("{title}-version")),
+                feed_id: Some(1),
+                retrieved: dt("2025-03-02T10:00:00Z"),
+                author: author.to_string(),
+                published: dt("2025-03-01T08:30:00Z"),
+                ..test_util::item(title, title, link)
+            },
+            feed_title: feed.to_string(),
+            feed_href: format!("https://{}.example.com/feed", feed.to_lowercase()),
+        }
+    }
+
+    fn digest(since: Option<DateTime<Utc>>) -> DigestItems {
+        DigestItems {
+            items: vec![
+                item("Zeta", "Z1", "https://zeta.example.com/1", ""),
+                item("Alpha", "A1 <b>", "https://alpha.example.com/1", "Bob"),
+                item("Zeta", "Z2", "javascript:alert(1)", ""),
+            ],
+            since,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        let t = render_text(&digest(Some(dt("2025-03-01T00:00:00Z"))));
+        assert_eq!(
+            t,
+            "3 unread item(s) since 2025-03-01 00:00 UTC.\n\
+             \n== Alpha ==\n\
+             https://alpha.example.com/feed\n\
+             \n* A1 <b>\n  https://alpha.example.com/1\n  2025-03-01 08:30, Bob\n\
+             \n== Zeta ==\n\
+             https://zeta.example.com/feed\n\
+             \n* Z1\n  https://zeta.example.com/1\n  2025-03-01 08:30\n\
+             \n* Z2\n  javascript:alert(1)\n  2025-03-01 08:30\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let h = render_html(&digest(None), "Digest & more");
+        assert!(h.contains("<title>Digest &amp; more</title>"));
+        assert!(h.contains("<p>3 unread item(s).</p>"));
+        let alpha = h.find("<h2><a href=\"https://alpha.example.com/feed\">Alpha</a></h2>");
+        let zeta = h.find("<h2><a href=\"https://zeta.example.com/feed\">Zeta</a></h2>");
+        assert!(alpha.unwrap() < zeta.unwrap());
+        assert!(h.contains(
+            "<li><a href=\"https://alpha.example.com/1\">A1 &lt;b&gt;</a> \
+             <small>2025-03-01 08:30, Bob</small></li>"
+        ));
+        // No links to anything but web pages.
+        assert!(!h.contains("javascript:"));
+        assert!(h.contains("<li>Z2 <small>"));
+    }
+
+    #[test]
+    fn test_mail() {
+        let config = ConfigDigest {
+            to: vec!["me@example.com".to_string()],
+            ..Default::default()
+        };
+        let mail = digest_mail(&config, &digest(None), dt("2025-03-03T00:00:00Z"));
+        assert_eq!(mail.subject, "feedreader digest (3 new)");
+        assert_eq!(mail.to, ["me@example.com"]);
+        assert!(mail.html.is_some());
+        assert!(
+            mail.render()
+                .contains("Content-Type: multipart/alternative")
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let config = ConfigDigest::default();
+        let mut d = digest(None);
+        d.truncated = true;
+        let mail = digest_mail(&config, &d, dt("2025-03-03T00:00:00Z"));
+        assert_eq!(mail.subject, "feedreader digest (3+ new)");
+        assert!(mail.text.starts_with(
+            "3 unread item(s). There are more unread items. Only the newest 3 are listed.\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_digest_items() {
+        let db = feedsdb::Db::new_memory("test_get_digest_items")
+            .await
+            .unwrap();
+        let mut conn = db.open().await.unwrap();
+        conn.init().await.unwrap();
+        conn.add_feed("https://example.com/feed").await.unwrap();
+        let (feeds, _) = conn.get_feeds(None).await.unwrap();
+        let mut items = vec![];
+        for n in 0..3 {
+            let mut i = item("Example", &format!("I{n}"), "", "").item;
+            i.published = dt("2025-03-01T08:30:00Z") + chrono::Duration::hours(n);
+            i.item_id = Some(i.make_id().await);
+            items.push(i);
+        }
+        conn.update_feed(&feeds[0], &items, None, false)
+            .await
+            .unwrap();
+
+        let mut config = ConfigDigest {
+            max_items: 3.try_into().unwrap(),
+            ..Default::default()
+        };
+        let d = get_digest_items(&config, &mut conn).await.unwrap();
+        assert_eq!(d.items.len(), 3);
+        assert!(!d.truncated);
+
+        config.max_items = 2.try_into().unwrap();
+        let d = get_digest_items(&config, &mut conn).await.unwrap();
+        let titles: Vec<&str> = d.items.iter().map(|i| i.item.title.as_str()).collect();
+        assert_eq!(titles, ["I2", "I1"]);
+        assert!(d.truncated);
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
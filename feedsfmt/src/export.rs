@@ -0,0 +1,491 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::xml_escape as esc;
+use anyhow::{self as ah, Context as _, format_err as err};
+use chrono::{DateTime, SecondsFormat, Utc};
+use feedsdb::{DbConn, Item, ItemFilter};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use std::{collections::HashMap, fmt::Write as _, writeln as ln};
+
+const GENERATOR: &str = "feedreader (Rust variant)";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Atom,
+    Rss,
+    JsonFeed,
+}
+
+impl ExportFormat {
+    /// The name of the format in the `format` query parameter.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Atom => "atom",
+            Self::Rss => "rss",
+            Self::JsonFeed => "json",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Atom => "application/atom+xml",
+            Self::Rss => "application/rss+xml",
+            Self::JsonFeed => "application/feed+json",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = ah::Error;
+
+    fn from_str(s: &str) -> ah::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "atom" => Ok(Self::Atom),
+            "rss" => Ok(Self::Rss),
+            "json" | "jsonfeed" => Ok(Self::JsonFeed),
+            s => Err(err!("Unknown feed format '{s}'. Use atom, rss or json.")),
+        }
+    }
+}
+
+/// Information about the generated feed.
+#[derive(Clone, Debug)]
+pub struct ExportMeta {
+    pub title: String,
+    /// The URL of the generated feed itself.
+    pub self_url: String,
+    /// The URL of the user interface.
+    pub home_url: String,
+    /// The update time of the generated feed, if it does not contain any items.
+    pub generated: DateTime<Utc>,
+}
+
+/// An item to be exported, together with information about its source feed.
+#[derive(Clone, Debug)]
+pub struct ExportItem {
+    pub item: Item,
+    pub feed_title: String,
+    pub feed_href: String,
+}
+
+impl ExportItem {
+    /// Globally unique and stable ID of the item.
+    ///
+    /// The ID does not change, if the item content is updated.
+    fn guid(&self) -> String {
+        let mut h = Sha256::new();
+        h.update(self.item.feed_id.unwrap_or_default().to_le_bytes());
+        h.update(&self.item.feed_item_id);
+        format!("urn:feedreader:item:{}", hex::encode(h.finalize()))
+    }
+
+    fn author(&self) -> &str {
+        if self.item.author.trim().is_empty() {
+            &self.feed_title
+        } else {
+            &self.item.author
+        }
+    }
+}
+
+fn rfc3339(dt: &DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn updated(meta: &ExportMeta, items: &[ExportItem]) -> DateTime<Utc> {
+    items
+        .iter()
+        .map(|i| i.item.retrieved)
+        .max()
+        .unwrap_or(meta.generated)
+}
+
+#[rustfmt::skip]
+fn export_atom(meta: &ExportMeta, items: &[ExportItem]) -> ah::Result<String> {
+    let mut b = String::with_capacity(4096 * (items.len() + 1));
+
+    ln!(b, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    ln!(b, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+    ln!(b, r#"  <id>{}</id>"#, esc(&meta.self_url))?;
+    ln!(b, r#"  <title type="text">{}</title>"#, esc(&meta.title))?;
+    ln!(b, r#"  <updated>{}</updated>"#, rfc3339(&updated(meta, items)))?;
+    ln!(b, r#"  <link rel="self" type="application/atom+xml" href="{}"/>"#, esc(&meta.self_url))?;
+    ln!(b, r#"  <link rel="alternate" type="text/html" href="{}"/>"#, esc(&meta.home_url))?;
+    ln!(b, r#"  <author><name>feedreader</name></author>"#)?;
+    ln!(b, r#"  <generator>{GENERATOR}</generator>"#)?;
+    for i in items {
+        ln!(b, r#"  <entry>"#)?;
+        ln!(b, r#"    <id>{}</id>"#, esc(&i.guid()))?;
+        ln!(b, r#"    <title type="text">{}</title>"#, esc(&i.item.title))?;
+        if !i.item.link.is_empty() {
+            ln!(b, r#"    <link rel="alternate" href="{}"/>"#, esc(&i.item.link))?;
+        }
+        ln!(b, r#"    <published>{}</published>"#, rfc3339(&i.item.published))?;
+        ln!(b, r#"    <updated>{}</updated>"#, rfc3339(&i.item.retrieved))?;
+        ln!(b, r#"    <author><name>{}</name></author>"#, esc(i.author()))?;
+        ln!(b, r#"    <summary type="html">{}</summary>"#, esc(&i.item.summary))?;
+        ln!(b, r#"    <source>"#)?;
+        ln!(b, r#"      <id>{}</id>"#, esc(&i.feed_href))?;
+        ln!(b, r#"      <title type="text">{}</title>"#, esc(&i.feed_title))?;
+        ln!(b, r#"    </source>"#)?;
+        ln!(b, r#"  </entry>"#)?;
+    }
+    ln!(b, r#"</feed>"#)?;
+    Ok(b)
+}
+
+#[rustfmt::skip]
+fn export_rss(meta: &ExportMeta, items: &[ExportItem]) -> ah::Result<String> {
+    let mut b = String::with_capacity(4096 * (items.len() + 1));
+
+    ln!(b, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    ln!(b, r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/">"#)?;
+    ln!(b, r#"  <channel>"#)?;
+    ln!(b, r#"    <title>{}</title>"#, esc(&meta.title))?;
+    ln!(b, r#"    <link>{}</link>"#, esc(&meta.home_url))?;
+    ln!(b, r#"    <description>{}</description>"#, esc(&meta.title))?;
+    ln!(b, r#"    <atom:link rel="self" type="application/rss+xml" href="{}"/>"#, esc(&meta.self_url))?;
+    ln!(b, r#"    <lastBuildDate>{}</lastBuildDate>"#, updated(meta, items).to_rfc2822())?;
+    ln!(b, r#"    <generator>{GENERATOR}</generator>"#)?;
+    for i in items {
+        ln!(b, r#"    <item>"#)?;
+        ln!(b, r#"      <guid isPermaLink="false">{}</guid>"#, esc(&i.guid()))?;
+        ln!(b, r#"      <title>{}</title>"#, esc(&i.item.title))?;
+        if !i.item.link.is_empty() {
+            ln!(b, r#"      <link>{}</link>"#, esc(&i.item.link))?;
+        }
+        ln!(b, r#"      <pubDate>{}</pubDate>"#, i.item.published.to_rfc2822())?;
+        ln!(b, r#"      <dc:creator>{}</dc:creator>"#, esc(i.author()))?;
+        ln!(b, r#"      <description>{}</description>"#, esc(&i.item.summary))?;
+        ln!(b, r#"      <source url="{}">{}</source>"#, esc(&i.feed_href), esc(&i.feed_title))?;
+        ln!(b, r#"    </item>"#)?;
+    }
+    ln!(b, r#"  </channel>"#)?;
+    ln!(b, r#"</rss>"#)?;
+    Ok(b)
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: String,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    url: &'a str,
+    title: &'a str,
+    content_html: &'a str,
+    date_published: String,
+    date_modified: String,
+    authors: Vec<JsonFeedAuthor<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: &'a str,
+    feed_url: &'a str,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+fn export_jsonfeed(meta: &ExportMeta, items: &[ExportItem]) -> ah::Result<String> {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &meta.title,
+        home_page_url: &meta.home_url,
+        feed_url: &meta.self_url,
+        items: items
+            .iter()
+            .map(|i| JsonFeedItem {
+                id: i.guid(),
+                url: &i.item.link,
+                title: &i.item.title,
+                content_html: &i.item.summary,
+                date_published: rfc3339(&i.item.published),
+                date_modified: rfc3339(&i.item.retrieved),
+                authors: vec![JsonFeedAuthor { name: i.author() }],
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&feed).context("Serialize JSON Feed")
+}
+
+/// Get all items matching the `filter` from the database.
+pub async fn get_export_items(
+    conn: &mut DbConn,
+    filter: &ItemFilter,
+) -> ah::Result<Vec<ExportItem>> {
+    let (feeds, _) = conn.get_feeds(None).await.context("Get feeds")?;
+    let feeds: HashMap<i64, _> = feeds
+        .into_iter()
+        .filter_map(|f| f.feed_id.map(|id| (id, f)))
+        .collect();
+
+    let items = conn
+        .get_items_filtered(filter)
+        .await
+        .context("Get filtered items")?;
+
+    Ok(items
+        .into_iter()
+        .map(|item| {
+            let feed = item.feed_id.and_then(|id| feeds.get(&id));
+            ExportItem {
+                feed_title: feed.map(|f| f.title.clone()).unwrap_or_default(),
+                feed_href: feed.map(|f| f.href.clone()).unwrap_or_default(),
+                item,
+            }
+        })
+        .collect())
+}
+
+/// Generate a descriptive title for the feed selected by `filter`.
+pub fn export_title(filter: &ItemFilter, items: &[ExportItem]) -> String {
+    let mut title = "feedreader".to_string();
+    if filter.starred {
+        title.push_str(" - starred");
+    }
+    if !filter.feed_ids.is_empty() {
+        let mut feed_titles: Vec<&str> = vec![];
+        for i in items {
+            if !feed_titles.contains(&i.feed_title.as_str()) {
+                feed_titles.push(&i.feed_title);
+            }
+        }
+        if !feed_titles.is_empty() {
+            title.push_str(" - ");
+            title.push_str(&feed_titles.join(", "));
+        }
+    }
+    if let Some(tag) = &filter.tag {
+        title.push_str(&format!(" - tag {tag}"));
+    }
+    if let Some(search) = &filter.search {
+        title.push_str(&format!(" - \"{search}\""));
+    }
+    title
+}
+
+/// Percent-encode a query parameter value.
+fn encode_query_value(s: &str) -> String {
+    let mut enc = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            enc.push(b as char);
+        } else {
+            let _ = write!(enc, "%{b:02X}");
+        }
+    }
+    enc
+}
+
+/// Generate the query string of the export that selects `filter` in the `format`.
+///
+/// This is used for the self link and the ID of the exported feed.
+pub fn export_query(format: ExportFormat, filter: &ItemFilter) -> String {
+    let mut params = vec![format!("format={}", format.name())];
+    if !filter.feed_ids.is_empty() {
+        let ids: Vec<String> = filter.feed_ids.iter().map(|id| id.to_string()).collect();
+        params.push(format!("feed={}", encode_query_value(&ids.join(","))));
+    }
+    if filter.starred {
+        params.push("starred=1".to_string());
+    }
+    if let Some(tag) = &filter.tag {
+        params.push(format!("tag={}", encode_query_value(tag)));
+    }
+    if let Some(search) = &filter.search {
+        params.push(format!("q={}", encode_query_value(search)));
+    }
+    params.push(format!("limit={}", filter.limit.unwrap_or(0)));
+    params.join("&")
+}
+
+/// Render the `items` as a feed document in the given `format`.
+pub fn export(format: ExportFormat, meta: &ExportMeta, items: &[ExportItem]) -> ah::Result<String> {
+    match format {
+        ExportFormat::Atom => export_atom(meta, items),
+        ExportFormat::Rss => export_rss(meta, items),
+        ExportFormat::JsonFeed => export_jsonfeed(meta, items),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::{self, dt};
+
+    fn test_items() -> Vec<ExportItem> {
+        let item =
+            |feed_item_id: &str, title: &str, link: &str, author: &str, summary: &str| ExportItem {
+                item: Item {
+                    item_id: Some(format!("{feed_item_id}-version")),
+                    feed_id: Some(7),
+                    retrieved: dt("2025-03-02T10:00:00Z"),
+                    author: author.to_string(),
+                    published: dt("2025-03-01T08:30:00Z"),
+                    summary: summary.to_string(),
+                    starred: true,
+                    ..test_util::item(feed_item_id, title, link)
+                },
+                feed_title: "Source <Feed> & Co".to_string(),
+                feed_href: "https://example.com/feed.xml?a=1&b=2".to_string(),
+            };
+        vec![
+            item(
+                "https://example.com/1",
+                "Tom & Jerry <3 \"quotes\" 'apos'",
+                "https://example.com/1?x=1&y=2",
+                "Alice",
+                "<p>Some <b>HTML</b> &amp; text</p>",
+            ),
+            item("plain-id-2", "Control\u{1}chars", "", "", "plain"),
+        ]
+    }
+
+    fn test_meta() -> ExportMeta {
+        ExportMeta {
+            title: "Starred & shared".to_string(),
+            self_url: "https://reader.example.com/cgi-bin/feeds/export?format=atom&starred=1"
+                .to_string(),
+            home_url: "https://reader.example.com/cgi-bin/feeds".to_string(),
+            generated: dt("2025-01-01T00:00:00Z"),
+        }
+    }
+
+    fn roundtrip(format: ExportFormat) -> feed_rs::model::Feed {
+        let items = test_items();
+        let doc = export(format, &test_meta(), &items).unwrap();
+        let feed = feed_rs::parser::parse(doc.as_bytes()).unwrap();
+
+        assert_eq!(feed.title.as_ref().unwrap().content, "Starred & shared");
+        assert_eq!(feed.entries.len(), 2);
+
+        let e = &feed.entries[0];
+        assert_eq!(e.id, items[0].guid());
+        assert_eq!(
+            e.title.as_ref().unwrap().content,
+            "Tom & Jerry <3 \"quotes\" 'apos'"
+        );
+        assert_eq!(e.links[0].href, "https://example.com/1?x=1&y=2");
+        assert_eq!(e.published, Some(dt("2025-03-01T08:30:00Z")));
+        assert_eq!(e.authors[0].name, "Alice");
+
+        let e = &feed.entries[1];
+        assert_eq!(e.id, items[1].guid());
+        // XML can't represent control characters. JSON can.
+        let title = e.title.as_ref().unwrap().content.replace('\u{1}', "");
+        assert_eq!(title, "Controlchars");
+        assert!(e.links.is_empty());
+        assert_eq!(e.authors[0].name, "Source <Feed> & Co");
+
+        feed
+    }
+
+    #[test]
+    fn test_guid_stable() {
+        let mut items = test_items();
+        let guid = items[0].guid();
+        items[0].item.title = "changed".to_string();
+        items[0].item.item_id = Some("other".to_string());
+        assert_eq!(items[0].guid(), guid);
+        assert_ne!(items[1].guid(), guid);
+        assert!(guid.starts_with("urn:feedreader:item:"));
+    }
+
+    #[test]
+    fn test_atom() {
+        let feed = roundtrip(ExportFormat::Atom);
+        assert_eq!(feed.updated, Some(dt("2025-03-02T10:00:00Z")));
+        assert_eq!(
+            feed.entries[0].summary.as_ref().unwrap().content,
+            "<p>Some <b>HTML</b> &amp; text</p>"
+        );
+        assert_eq!(feed.entries[0].updated, Some(dt("2025-03-02T10:00:00Z")));
+    }
+
+    #[test]
+    fn test_rss() {
+        let feed = roundtrip(ExportFormat::Rss);
+        assert_eq!(
+            feed.entries[0].summary.as_ref().unwrap().content,
+            "<p>Some <b>HTML</b> &amp; text</p>"
+        );
+    }
+
+    #[test]
+    fn test_jsonfeed() {
+        let feed = roundtrip(ExportFormat::JsonFeed);
+        assert_eq!(feed.entries[0].updated, Some(dt("2025-03-02T10:00:00Z")));
+    }
+
+    #[test]
+    fn test_empty() {
+        for format in [
+            ExportFormat::Atom,
+            ExportFormat::Rss,
+            ExportFormat::JsonFeed,
+        ] {
+            let doc = export(format, &test_meta(), &[]).unwrap();
+            let feed = feed_rs::parser::parse(doc.as_bytes()).unwrap();
+            assert!(feed.entries.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_export_query() {
+        let filter = ItemFilter {
+            limit: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            export_query(ExportFormat::Atom, &filter),
+            "format=atom&limit=100"
+        );
+        let filter = ItemFilter {
+            feed_ids: vec![1, 2],
+            starred: true,
+            tag: Some("rust".to_string()),
+            search: Some("a&b c".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            export_query(ExportFormat::JsonFeed, &filter),
+            "format=json&feed=1%2C2&starred=1&tag=rust&q=a%26b%20c&limit=0"
+        );
+    }
+
+    #[test]
+    fn test_format_parse() {
+        assert_eq!("Atom".parse::<ExportFormat>().unwrap(), ExportFormat::Atom);
+        assert_eq!("rss".parse::<ExportFormat>().unwrap(), ExportFormat::Rss);
+        assert_eq!(
+            "json".parse::<ExportFormat>().unwrap(),
+            ExportFormat::JsonFeed
+        );
+        assert!("html".parse::<ExportFormat>().is_err());
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
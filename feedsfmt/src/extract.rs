@@ -0,0 +1,224 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+// Copyright (C) 2020 Marco Lochen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::rewrite::rewrite_item;
+use anyhow as ah;
+use chrono::{DateTime, Utc};
+use feed_rs::model::Feed as ParsedFeed;
+use feedscfg::Config;
+use feedsdb::Item;
+use itertools::Itertools as _;
+
+/// The HTTP user agent for retrieving feeds.
+pub const USER_AGENT: &str = concat!(
+    "feedreader/",
+    env!("CARGO_PKG_VERSION"),
+    " (feedreader; Rust variant)"
+);
+
+/// Parse a retrieved feed document.
+///
+/// feed-rs assigns a random UUID to entries that have neither an ID nor a link,
+/// so they would show up as new items on every refresh.
+/// Generate the ID of such entries from the feed `href` and the entry title instead.
+/// Links are not resolved against the `href` and all other IDs are
+/// generated by feed-rs as before.
+pub fn parse_feed(href: &str, feed_bytes: &[u8]) -> ah::Result<ParsedFeed> {
+    use feed_rs::parser;
+
+    let href = href.to_string();
+    let parser = parser::Builder::new()
+        .id_generator(move |links, title, _| parser::generate_id(links, title, Some(&href)))
+        .build();
+    Ok(parser.parse(feed_bytes)?)
+}
+
+/// The title of the parsed feed, if it has one.
+pub fn feed_title(parsed_feed: &ParsedFeed) -> Option<&str> {
+    parsed_feed.title.as_ref().map(|t| t.content.as_str())
+}
+
+/// An item extracted from a feed entry.
+#[derive(Clone, Debug)]
+pub struct ExtractedItem {
+    /// The item without `item_id` and `feed_id`.
+    pub item: Item,
+    /// The category labels of the entry for the rule matching.
+    pub categories: Vec<String>,
+}
+
+/// Extract the items from all entries of the parsed feed `href`.
+///
+/// The rewrite rules are applied and the items dropped by them are skipped.
+/// Entries without date are dated `now`.
+pub fn extract_items(
+    config: &Config,
+    href: &str,
+    parsed_feed: &ParsedFeed,
+    now: DateTime<Utc>,
+) -> Vec<ExtractedItem> {
+    let mut items = Vec::with_capacity(parsed_feed.entries.len());
+    for parsed_entry in &parsed_feed.entries {
+        let feed_item_id = parsed_entry.id.clone();
+
+        let author = itertools::join(parsed_entry.authors.iter().map(|a| &a.name), ", ");
+
+        let title = parsed_entry
+            .title
+            .as_ref()
+            .map(|t| t.content.clone())
+            .unwrap_or_default();
+
+        let link = parsed_entry
+            .links
+            .iter()
+            .map(|l| l.href.clone())
+            .next()
+            .unwrap_or_default();
+
+        let published = parsed_entry.published.or(parsed_entry.updated);
+        let has_date = published.is_some();
+        let published = published.unwrap_or(now);
+
+        let mut summary = parsed_entry
+            .summary
+            .as_ref()
+            .map(|s| s.content.clone())
+            .unwrap_or_default();
+        if summary.trim().is_empty() {
+            for media in &parsed_entry.media {
+                if let Some(description) = &media.description {
+                    summary = description.content.clone();
+                    break;
+                }
+            }
+        }
+
+        // The tag list is stored comma separated.
+        let tags = parsed_entry
+            .categories
+            .iter()
+            .map(|c| c.term.replace(',', " ").trim().to_string())
+            .filter(|t| !t.is_empty())
+            .unique()
+            .collect();
+
+        let mut item = Item {
+            item_id: None,
+            feed_id: None,
+            retrieved: now,
+            seen: false,
+            author,
+            title,
+            feed_item_id,
+            link,
+            published,
+            summary,
+            starred: false,
+            important: false,
+            tags,
+            suppressed_by: None,
+        };
+        if !rewrite_item(&config.rewrite, href, &mut item, has_date) {
+            continue;
+        }
+
+        let categories = parsed_entry
+            .categories
+            .iter()
+            .map(|c| c.label.clone().unwrap_or_else(|| c.term.clone()))
+            .collect();
+        items.push(ExtractedItem { item, categories });
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::now;
+
+    #[test]
+    fn test_parse_feed_ids() {
+        let doc = br#"<rss version="2.0"><channel><title>t</title>
+            <item><title>Relative</title><link>/a</link></item>
+            <item><title>No link</title></item>
+            </channel></rss>"#;
+        let a = parse_feed("https://a.example.com/feed", doc).unwrap();
+        let b = parse_feed("https://b.example.com/feed", doc).unwrap();
+
+        // Relative links are not resolved and their IDs don't depend on the feed URL.
+        assert_eq!(a.entries[0].links[0].href, "/a");
+        assert_eq!(a.entries[0].id, b.entries[0].id);
+
+        // Entries without link get an ID from the feed URL and the title.
+        assert_ne!(a.entries[1].id, b.entries[1].id);
+        let a_again = parse_feed("https://a.example.com/feed", doc).unwrap();
+        assert_eq!(a.entries[1].id, a_again.entries[1].id);
+    }
+
+    #[test]
+    fn test_extract_items() {
+        let doc = br#"<rss version="2.0"
+            xmlns:media="http://search.yahoo.com/mrss/"><channel><title>Feed</title>
+            <item><title>[Ad] Dated</title><link>http://example.com/2025/06/07/a</link>
+                <category>News, Tech</category><category domain="x">News, Tech</category></item>
+            <item><title>Media</title><link>https://example.com/b</link>
+                <media:content url="https://example.com/b.mp4">
+                <media:description>Media description</media:description>
+                </media:content></item>
+            <item><title>Dropped</title><link>https://example.com/nodate</link></item>
+            </channel></rss>"#;
+        let config = Config::parse_str(
+            r#"
+            [[rewrite]]
+            feed = 'example\.com'
+            date-from = "link"
+            date-regex = '/(\d{4}/\d{2}/\d{2})/'
+            date-format = "%Y/%m/%d"
+            date-required = true
+            title-regex = '^\[Ad\]\s*'
+            title-replace = ''
+            "#,
+        )
+        .unwrap();
+        let href = "https://example.com/feed";
+        let parsed = parse_feed(href, doc).unwrap();
+        assert_eq!(feed_title(&parsed), Some("Feed"));
+
+        let items = extract_items(&Config::default(), href, &parsed, now());
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].item.title, "[Ad] Dated");
+        assert_eq!(items[0].item.published, now());
+        assert_eq!(items[0].item.tags, ["News  Tech"]);
+        assert_eq!(items[0].categories, ["News, Tech", "News, Tech"]);
+        assert_eq!(items[1].item.summary, "Media description");
+
+        let items = extract_items(&config, href, &parsed, now());
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item.title, "Dated");
+        assert_eq!(
+            items[0].item.published,
+            DateTime::parse_from_rfc3339("2025-06-07T00:00:00Z").unwrap()
+        );
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,48 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+#![forbid(unsafe_code)]
+
+pub mod digest;
+pub mod export;
+pub mod extract;
+pub mod mail;
+pub mod rewrite;
+
+/// Escape a string for XML text content and attribute values.
+///
+/// Characters that are not allowed in XML 1.0 documents are removed.
+pub fn xml_escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + (s.len() / 8));
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            '\t' | '\n' | '\r' => ret.push(c),
+            '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => (),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -0,0 +1,327 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use anyhow::{self as ah, Context as _, format_err as err};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, Utc};
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::{io::AsyncWriteExt as _, process::Command};
+
+/// Maximum length of a base64 body line.
+const LINE_LEN: usize = 76;
+
+/// The multipart boundary. It cannot collide with the base64 encoded parts.
+const BOUNDARY: &str = "=_feedreader_alternative";
+
+/// Replace the line breaks and other control characters by spaces,
+/// so that feed content cannot inject headers.
+fn sanitize_header(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Make a string safe for use as a mail header value.
+///
+/// Line breaks are removed, so that feed content cannot inject headers.
+/// Non-ASCII text is encoded as RFC 2047 encoded-word.
+pub fn encode_header(s: &str) -> String {
+    let s = sanitize_header(s);
+    if s.is_ascii() {
+        s
+    } else {
+        format!("=?utf-8?b?{}?=", BASE64.encode(s))
+    }
+}
+
+/// Make a mail address like `Name <addr@example.com>` safe for use in a header.
+///
+/// Only the display name is encoded. The address itself must stay readable.
+pub fn encode_address(s: &str) -> String {
+    let s = sanitize_header(s);
+    if let Some((name, addr)) = s.rsplit_once('<')
+        && addr.ends_with('>')
+    {
+        let name = name.trim();
+        if name.is_empty() {
+            format!("<{addr}")
+        } else {
+            format!("{} <{addr}", encode_header(name))
+        }
+    } else {
+        s
+    }
+}
+
+/// Base64 encode `data` with line breaks.
+fn encode_body(data: &str) -> String {
+    let enc = BASE64.encode(data);
+    let mut ret = String::with_capacity(enc.len() + (enc.len() / LINE_LEN + 1) * 2);
+    for line in enc.as_bytes().chunks(LINE_LEN) {
+        // Base64 is pure ASCII.
+        ret.push_str(std::str::from_utf8(line).unwrap_or_default());
+        ret.push_str("\r\n");
+    }
+    ret
+}
+
+/// A plain text mail with an optional HTML alternative.
+#[derive(Clone, Debug)]
+pub struct Mail {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub date: DateTime<Utc>,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+impl Mail {
+    /// Render the mail as RFC 5322 message.
+    pub fn render(&self) -> String {
+        let mut m = String::with_capacity(self.text.len() * 2 + 512);
+        if let Some(from) = &self.from {
+            m.push_str(&format!("From: {}\r\n", encode_address(from)));
+        }
+        let to: Vec<String> = self.to.iter().map(|t| encode_address(t)).collect();
+        m.push_str(&format!("To: {}\r\n", to.join(", ")));
+        m.push_str(&format!("Subject: {}\r\n", encode_header(&self.subject)));
+        m.push_str(&format!("Date: {}\r\n", self.date.to_rfc2822()));
+        m.push_str("MIME-Version: 1.0\r\n");
+        match &self.html {
+            None => {
+                m.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+                m.push_str("Content-Transfer-Encoding: base64\r\n");
+                m.push_str("\r\n");
+                m.push_str(&encode_body(&self.text));
+            }
+            Some(html) => {
+                m.push_str(&format!(
+                    "Content-Type: multipart/alternative; boundary=\"{BOUNDARY}\"\r\n"
+                ));
+                m.push_str("\r\n");
+                for (mime, body) in [("text/plain", &self.text), ("text/html", html)] {
+                    m.push_str(&format!("--{BOUNDARY}\r\n"));
+                    m.push_str(&format!("Content-Type: {mime}; charset=utf-8\r\n"));
+                    m.push_str("Content-Transfer-Encoding: base64\r\n");
+                    m.push_str("\r\n");
+                    m.push_str(&encode_body(body));
+                }
+                m.push_str(&format!("--{BOUNDARY}--\r\n"));
+            }
+        }
+        m
+    }
+}
+
+/// Run the external `command` with the environment `env`.
+///
+/// `stdin` is written to the standard input of the command.
+/// The command is killed, if it does not finish within `timeout`.
+pub async fn run_command(
+    command: &[String],
+    env: &[(&str, String)],
+    stdin: Option<&[u8]>,
+    timeout: Duration,
+) -> ah::Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(err!("Empty command."));
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Run '{program}'"))?;
+
+    let run = async {
+        if let Some(data) = stdin
+            && let Some(mut pipe) = child.stdin.take()
+        {
+            pipe.write_all(data).await.context("Write to stdin")?;
+            // Close the pipe.
+            drop(pipe);
+        }
+        child.wait().await.context("Wait for command")
+    };
+    let status = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| err!("'{program}' timed out."))??;
+    if !status.success() {
+        return Err(err!("'{program}' failed: {status}"));
+    }
+    Ok(())
+}
+
+/// Hand a rendered mail `message` to the sendmail `command`.
+pub async fn sendmail(command: &[String], message: &str, timeout: Duration) -> ah::Result<()> {
+    run_command(command, &[], Some(message.as_bytes()), timeout).await
+}
+
+/// Deliver a rendered mail `message` into the Maildir `dir`.
+///
+/// The message is written to `tmp` and then moved to `new`.
+/// Returns the path of the delivered message.
+pub fn write_maildir(dir: &Path, message: &str) -> ah::Result<PathBuf> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let host = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().replace(['/', ':'], "_"))
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+    let now = Utc::now();
+    let name = format!(
+        "{}.M{}P{}Q{}.{host}",
+        now.timestamp(),
+        now.timestamp_subsec_micros(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+    );
+
+    for sub in ["tmp", "new", "cur"] {
+        std::fs::create_dir_all(dir.join(sub))
+            .with_context(|| format!("Create Maildir '{}'", dir.display()))?;
+    }
+    let tmp = dir.join("tmp").join(&name);
+    let new = dir.join("new").join(&name);
+    std::fs::write(&tmp, message.replace("\r\n", "\n"))
+        .with_context(|| format!("Write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, &new).with_context(|| format!("Move to '{}'", new.display()))?;
+    Ok(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedsdb::test_util::now;
+
+    #[test]
+    fn test_encode_header() {
+        assert_eq!(encode_header(" Hello "), "Hello");
+        assert_eq!(encode_header("a\r\nBcc: x@y"), "a  Bcc: x@y");
+        assert_eq!(encode_header("Grüße"), "=?utf-8?b?R3LDvMOfZQ==?=");
+    }
+
+    #[test]
+    fn test_encode_address() {
+        assert_eq!(encode_address(" a@example.com "), "a@example.com");
+        assert_eq!(encode_address("<a@example.com>"), "<a@example.com>");
+        assert_eq!(
+            encode_address("Feed Reader <a@example.com>"),
+            "Feed Reader <a@example.com>"
+        );
+        assert_eq!(
+            encode_address("Grüße <a@example.com>"),
+            "=?utf-8?b?R3LDvMOfZQ==?= <a@example.com>"
+        );
+        assert_eq!(
+            encode_address("a\r\nBcc: x@y <a@example.com>"),
+            "a  Bcc: x@y <a@example.com>"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mail = Mail {
+            from: Some("feedreader@example.com".to_string()),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            subject: "Alert:\nnews".to_string(),
+            date: now(),
+            text: "x".repeat(100),
+            html: None,
+        };
+        let m = mail.render();
+        let (head, body) = m.split_once("\r\n\r\n").unwrap();
+        let head: Vec<&str> = head.split("\r\n").collect();
+        assert_eq!(
+            head,
+            [
+                "From: feedreader@example.com",
+                "To: a@example.com, b@example.com",
+                "Subject: Alert: news",
+                "Date: Fri, 15 Jan 2027 08:00:00 +0000",
+                "MIME-Version: 1.0",
+                "Content-Type: text/plain; charset=utf-8",
+                "Content-Transfer-Encoding: base64",
+            ]
+        );
+        let lines: Vec<&str> = body.trim_end().split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), LINE_LEN);
+        let decoded = BASE64.decode(lines.concat()).unwrap();
+        assert_eq!(decoded, "x".repeat(100).as_bytes());
+    }
+
+    #[test]
+    fn test_render_multipart() {
+        let mail = Mail {
+            from: None,
+            to: vec!["a@example.com".to_string()],
+            subject: "Digest".to_string(),
+            date: now(),
+            text: "text".to_string(),
+            html: Some("<p>html</p>".to_string()),
+        };
+        let m = mail.render();
+        assert!(m.starts_with("To: a@example.com\r\n"));
+        assert!(m.contains(&format!(
+            "Content-Type: multipart/alternative; boundary=\"{BOUNDARY}\"\r\n"
+        )));
+        let parts: Vec<&str> = m.split(&format!("--{BOUNDARY}")).collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[3], "--\r\n");
+        for (part, mime, body) in [
+            (parts[1], "text/plain", "text"),
+            (parts[2], "text/html", "<p>html</p>"),
+        ] {
+            let (head, enc) = part.split_once("\r\n\r\n").unwrap();
+            assert!(head.contains(&format!("Content-Type: {mime}; charset=utf-8")));
+            let decoded = BASE64.decode(enc.trim().replace("\r\n", "")).unwrap();
+            assert_eq!(decoded, body.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_maildir() {
+        let dir = std::env::temp_dir().join(format!("feedsfmt-maildir-{}", std::process::id()));
+        let a = write_maildir(&dir, "Subject: a\r\n\r\nbody\r\n").unwrap();
+        let b = write_maildir(&dir, "Subject: b\r\n\r\nbody\r\n").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.parent().unwrap(), dir.join("new"));
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "Subject: a\n\nbody\n");
+        assert!(dir.join("cur").is_dir());
+        assert_eq!(std::fs::read_dir(dir.join("tmp")).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
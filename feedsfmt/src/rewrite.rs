@@ -0,0 +1,248 @@
+// -*- coding: utf-8 -*-
+//
+// Copyright (C) 2024-2025 Michael Büsch <m@bues.ch>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use feedscfg::{ConfigRewrite, ConfigRewriteDate, RewriteDateFormat, RewriteField};
+use feedsdb::Item;
+
+/// Extract a date from the string `s` with the date rule.
+fn extract_date(rule: &ConfigRewriteDate, s: &str) -> Option<DateTime<Utc>> {
+    let caps = rule.regex.captures(s)?;
+    let date = caps.get(1).or_else(|| caps.get(0))?.as_str().trim();
+
+    match &rule.format {
+        RewriteDateFormat::Unix => {
+            let stamp: i64 = date.parse().ok()?;
+            DateTime::<Utc>::from_timestamp(stamp ^ rule.xor, 0)
+        }
+        RewriteDateFormat::UnixHex => {
+            let stamp = i64::from_str_radix(date, 16).ok()?;
+            DateTime::<Utc>::from_timestamp(stamp ^ rule.xor, 0)
+        }
+        RewriteDateFormat::Chrono(fmt) => {
+            if let Ok(dt) = DateTime::parse_from_str(date, fmt) {
+                Some(dt.to_utc())
+            } else if let Ok(dt) = NaiveDateTime::parse_from_str(date, fmt) {
+                Some(dt.and_utc())
+            } else if let Ok(d) = NaiveDate::parse_from_str(date, fmt) {
+                Some(d.and_hms_opt(0, 0, 0)?.and_utc())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Apply all rewrite rules for the feed `href` to the `item`.
+///
+/// `has_date` tells whether `item.published` is a date from the feed.
+/// Returns `false`, if the item shall be dropped.
+pub fn rewrite_item(rules: &[ConfigRewrite], href: &str, item: &mut Item, has_date: bool) -> bool {
+    let mut has_date = has_date;
+
+    for rule in rules {
+        if let Some(feed) = &rule.feed
+            && !feed.is_match(href)
+        {
+            continue;
+        }
+
+        if let Some(date) = &rule.date
+            && (date.replace || !has_date)
+        {
+            let field = match date.field {
+                RewriteField::Id => &item.feed_item_id,
+                RewriteField::Link => &item.link,
+                RewriteField::Title => &item.title,
+            };
+            if let Some(published) = extract_date(date, field) {
+                item.published = published;
+                has_date = true;
+            } else if date.required {
+                log::debug!(
+                    "Rewrite rule {}: Dropping item '{}' without date.",
+                    rule.name,
+                    item.feed_item_id
+                );
+                return false;
+            }
+        }
+
+        if let Some((re, replace)) = &rule.title {
+            item.title = re.replace_all(&item.title, replace.as_str()).into_owned();
+        }
+        if let Some((re, replace)) = &rule.link {
+            item.link = re.replace_all(&item.link, replace.as_str()).into_owned();
+        }
+        if let Some(author) = &rule.author_default
+            && item.author.trim().is_empty()
+        {
+            item.author = author.clone();
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feedscfg::Config;
+    use feedsdb::test_util::{dt, item, now};
+
+    #[test]
+    fn test_builtin_fefe() {
+        let rules = Config::default().rewrite;
+        let href = "https://blog.fefe.de/rss.xml";
+
+        let mut i = item("https://blog.fefe.de/?ts=9b1a4d5e", "t", "");
+        assert!(rewrite_item(&rules, href, &mut i, false));
+        assert_eq!(
+            i.published,
+            DateTime::from_timestamp(0x9b1a4d5e ^ 0xfefec0de, 0).unwrap()
+        );
+
+        // A date from the feed is not replaced.
+        let mut i = item("https://blog.fefe.de/?ts=9b1a4d5e", "t", "");
+        assert!(rewrite_item(&rules, href, &mut i, true));
+        assert_eq!(i.published, now());
+
+        // Invalid ids are dropped.
+        let mut i = item("https://blog.fefe.de/?ts=xyz", "t", "");
+        assert!(!rewrite_item(&rules, href, &mut i, false));
+
+        // Other feeds are not affected.
+        let mut i = item("https://example.com/?ts=xyz", "t", "");
+        assert!(rewrite_item(
+            &rules,
+            "https://example.com/feed",
+            &mut i,
+            false
+        ));
+        assert_eq!(i.published, now());
+    }
+
+    #[test]
+    fn test_config_rules() {
+        let config = Config::parse_str(
+            r#"
+            [[rewrite]]
+            name = "dated-links"
+            feed = 'example\.com'
+            date-from = "link"
+            date-regex = '/(\d{4}/\d{2}/\d{2})/'
+            date-format = "%Y/%m/%d"
+            date-replace = true
+            title-regex = '^\[Sponsored\]\s*'
+            title-replace = ''
+            link-regex = '^http://'
+            link-replace = 'https://'
+            author-default = "Example Staff"
+
+            [[rewrite]]
+            date-from = "title"
+            date-regex = '\((\d+)\)$'
+            date-format = "unix"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rewrite.len(), 3);
+        assert_eq!(config.rewrite[0].name, "builtin-fefe");
+        assert_eq!(config.rewrite[1].name, "dated-links");
+        assert_eq!(config.rewrite[2].name, "rewrite-1");
+
+        let mut i = item(
+            "id1",
+            "[Sponsored] Hello",
+            "http://example.com/2025/06/07/hello",
+        );
+        assert!(rewrite_item(
+            &config.rewrite,
+            "https://example.com/feed",
+            &mut i,
+            true
+        ));
+        assert_eq!(i.published, dt("2025-06-07T00:00:00Z"));
+        assert_eq!(i.title, "Hello");
+        assert_eq!(i.link, "https://example.com/2025/06/07/hello");
+        assert_eq!(i.author, "Example Staff");
+
+        // The link date doesn't match. Not required, so the item is kept.
+        // The second rule applies to all feeds.
+        let mut i = item("id2", "Title (1700000000)", "http://example.com/x");
+        i.author = "Somebody".to_string();
+        assert!(rewrite_item(
+            &config.rewrite,
+            "https://example.com/feed",
+            &mut i,
+            false
+        ));
+        assert_eq!(i.published, dt("2023-11-14T22:13:20Z"));
+        assert_eq!(i.author, "Somebody");
+    }
+
+    #[test]
+    fn test_chrono_formats() {
+        let config = Config::parse_str(
+            r#"
+            [[rewrite]]
+            date-from = "id"
+            date-regex = 'at=(.*)$'
+            date-format = "%Y-%m-%dT%H:%M:%S%z"
+            "#,
+        )
+        .unwrap();
+        let mut i = item("x?at=2025-01-02T03:04:05+0100", "", "");
+        assert!(rewrite_item(&config.rewrite, "", &mut i, false));
+        assert_eq!(i.published, dt("2025-01-02T02:04:05Z"));
+
+        let config = Config::parse_str(
+            r#"
+            [[rewrite]]
+            date-from = "id"
+            date-regex = 'at=(.*)$'
+            date-format = "%d.%m.%Y %H:%M"
+            "#,
+        )
+        .unwrap();
+        let mut i = item("x?at=24.12.2024 18:30", "", "");
+        assert!(rewrite_item(&config.rewrite, "", &mut i, false));
+        assert_eq!(i.published, dt("2024-12-24T18:30:00Z"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let invalid = [
+            // Incomplete date rule.
+            "[[rewrite]]\ndate-from = 'id'\n",
+            // Invalid field.
+            "[[rewrite]]\ndate-from = 'summary'\ndate-regex = 'x'\ndate-format = 'unix'\n",
+            // Invalid chrono format.
+            "[[rewrite]]\ndate-from = 'id'\ndate-regex = 'x'\ndate-format = '%Q'\n",
+            // Invalid regex.
+            "[[rewrite]]\ntitle-regex = '('\ntitle-replace = ''\n",
+            // Missing replacement.
+            "[[rewrite]]\nlink-regex = 'x'\n",
+        ];
+        for s in invalid {
+            assert!(Config::parse_str(s).is_err(), "{s}");
+        }
+    }
+}
+
+// vim: ts=4 sw=4 expandtab
@@ -108,6 +108,10 @@ install_dirs()
     do_install \
         -o root -g root -m 0755 \
         -d /opt/feedreader/etc/feedreader
+
+    do_install \
+        -o root -g root -m 0755 \
+        -d /opt/feedreader/etc/feedreader/conf.d
 }
 
 install_conf()